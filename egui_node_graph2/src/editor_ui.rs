@@ -25,8 +25,11 @@ pub type ConnLocations = std::collections::HashMap<InputId, Vec<Pos2>>;
 pub type NodeRects = std::collections::HashMap<NodeId, Rect>;
 
 const DISTANCE_TO_CONNECT: f32 = 10.0;
+const DISTANCE_TO_CONNECTION: f32 = 6.0;
 const NODE_INITIAL_SIZE: Vec2 = Vec2::new(200.0, 200.0);
 const NODE_MAX_SIZE: Vec2 = Vec2::new(800.0, 800.0);
+const MINIMAP_SIZE: Vec2 = Vec2::new(200.0, 140.0);
+const MINIMAP_MARGIN: f32 = 10.0;
 
 /// Nodes communicate certain events to the parent graph when drawn. There is
 /// one special `User` variant which can be used by users as the return value
@@ -68,6 +71,20 @@ pub enum NodeResponse<UserResponse: UserResponseTrait, NodeData: NodeDataTrait>
         node: NodeId,
         resize_delta: Vec2,
     },
+    /// Emitted when an existing connection is double clicked. The position is
+    /// in the same space as [`GraphEditorState::node_positions`].
+    ConnectionDoubleClicked {
+        output: OutputId,
+        input: InputId,
+        position: Pos2,
+    },
+    /// A connection was dropped on a port with a different data type. The
+    /// ports don't get connected, but user code can connect them through a
+    /// conversion instead.
+    IncompatibleConnection {
+        output: OutputId,
+        input: InputId,
+    },
     User(UserResponse),
 }
 
@@ -140,6 +157,8 @@ where
     ) -> GraphResponse<UserResponse, NodeData> {
         ui.set_clip_rect(ui.max_rect());
         let clip_rect = ui.clip_rect();
+        // Remember the editor area, so the view can be moved from outside of the drawing code
+        self.pan_zoom.clip_rect = clip_rect;
         // Zoom may have never taken place, so ensure we use parent style
         if !self.pan_zoom.started {
             self.zoom(ui, 1.0);
@@ -164,6 +183,21 @@ where
         graph_response
     }
 
+    /// Moves the view so the node is in the center of the editor.
+    pub fn center_on_node(&mut self, node_id: NodeId) {
+        let Some(pos) = self.node_positions.get(node_id) else {
+            return;
+        };
+        // not drawn yet, don't know where the center is
+        if !self.pan_zoom.clip_rect.is_positive() {
+            return;
+        }
+        let size = self.node_sizes.get(node_id).unwrap_or(&NODE_INITIAL_SIZE);
+        // the actual height depends on the contents, only use the title part of it
+        let node_center = *pos + vec2(size.x / 2.0, 30.0) * self.pan_zoom.zoom;
+        self.pan_zoom.pan = self.pan_zoom.clip_rect.size() / 2.0 - node_center.to_vec2();
+    }
+
     /// Reset zoom to 1.0
     pub fn reset_zoom(&mut self, ui: &Ui) {
         let new_zoom = 1.0 / self.pan_zoom.zoom;
@@ -194,6 +228,15 @@ where
             *node_pos = scaled_local_pos + half_size - self.pan_zoom.pan;
             // This way we can retain pan untouched when zooming :)
         }
+        // Frames are scaled the same way
+        let scale_pos = |pos: Pos2| {
+            ((pos.to_vec2() - half_size + self.pan_zoom.pan) * zoom_delta).to_pos2() + half_size
+                - self.pan_zoom.pan
+        };
+        for frame in self.group_frames.iter_mut() {
+            frame.rect = Rect::from_min_max(scale_pos(frame.rect.min), scale_pos(frame.rect.max));
+        }
+        self.pan_zoom.origin = scale_pos(self.pan_zoom.origin);
     }
 
     fn draw_graph_editor_inside_zoom(
@@ -228,6 +271,7 @@ where
 
         // Used to detect when the background was clicked
         let mut click_on_background = false;
+        let mut double_click_on_background = false;
 
         // Used to detect drag events in the background
         let mut drag_started_on_background = false;
@@ -243,6 +287,9 @@ where
         // Allocate rect before the nodes, otherwise this will block the interaction
         // with the nodes.
         let r = ui.allocate_rect(ui.min_rect(), Sense::click().union(Sense::drag()));
+        if r.double_clicked() {
+            double_click_on_background = true;
+        }
         if r.clicked() {
             click_on_background = true;
         } else if r.drag_started() {
@@ -251,8 +298,20 @@ where
             drag_released_on_background = true;
         }
 
+        // Frames go behind the nodes
+        let frames_response = self.draw_group_frames(ui, editor_rect);
+
         /* Draw nodes */
         for node_id in self.node_order.iter().copied() {
+            if let Some(title_rect) = frames_response.hidden_nodes.get(&node_id) {
+                self.hidden_node_locations(
+                    node_id,
+                    *title_rect,
+                    &mut port_locations,
+                    &mut conn_locations,
+                );
+                continue;
+            }
             let responses = GraphNodeWidget {
                 position: self.node_positions.get_mut(node_id).unwrap(),
                 graph: &mut self.graph,
@@ -397,25 +456,45 @@ where
         }
 
         // draw existing connections
+        let mut double_clicked_connection = None;
         for (input, outputs) in self.graph.iter_connection_groups() {
             for (hook_n, &output) in outputs.iter().enumerate() {
                 let port_type = self
                     .graph
                     .any_param_type(AnyParameterId::Output(output))
                     .unwrap();
-                let connection_color = port_type.data_type_color(user_state);
+                let connection_color = self.graph[self.graph.get_input(input).node]
+                    .user_data
+                    .connection_color(input, output, &self.graph, user_state)
+                    .unwrap_or_else(|| port_type.data_type_color(user_state));
                 // outputs can't be wide yet so this is fine.
                 let src_pos = port_locations[&AnyParameterId::Output(output)][0];
                 let dst_pos = conn_locations[&input][hook_n];
-                draw_connection(
+                let bezier = draw_connection(
                     &self.pan_zoom,
                     ui.painter(),
                     src_pos,
                     dst_pos,
                     connection_color,
                 );
+                if double_click_on_background
+                    && connection_contains(&bezier, cursor_pos, DISTANCE_TO_CONNECTION)
+                {
+                    // connections drawn later are on top, so the last one wins
+                    double_clicked_connection = Some((output, input));
+                }
             }
         }
+        if let Some((output, input)) = double_clicked_connection {
+            delayed_responses.push(NodeResponse::ConnectionDoubleClicked {
+                output,
+                input,
+                position: cursor_pos - self.pan_zoom.pan - editor_rect.min.to_vec2(),
+            });
+        }
+
+        // draw the minimap on top of everything else in the graph
+        let cursor_in_minimap = self.show_minimap && self.draw_minimap(ui, editor_rect, user_state);
 
         /* Handle responses from drawing nodes */
 
@@ -494,7 +573,9 @@ where
                         NODE_MAX_SIZE,
                     );
                 }
-                NodeResponse::User(_) => {
+                NodeResponse::User(_)
+                | NodeResponse::ConnectionDoubleClicked { .. }
+                | NodeResponse::IncompatibleConnection { .. } => {
                     // These are handled by the user code.
                 }
                 NodeResponse::DeleteNodeFull { .. } => {
@@ -542,7 +623,12 @@ where
             self.connection_in_progress = None;
         }
 
-        if mouse.secondary_released() && cursor_in_editor && !cursor_in_finder {
+        if mouse.secondary_released()
+            && cursor_in_editor
+            && !cursor_in_finder
+            && !cursor_in_minimap
+            && !frames_response.cursor_on_title
+        {
             self.node_finder = Some(NodeFinder::new_at(cursor_pos));
         }
         if ui.ctx().input(|i| i.key_pressed(Key::Escape)) {
@@ -580,6 +666,112 @@ where
             cursor_in_finder,
         }
     }
+
+    /// Draws an overview of all the nodes and the visible area in the bottom
+    /// right corner of the editor. Clicking or dragging in it moves the view.
+    /// Returns true if the mouse is hovering the minimap.
+    fn draw_minimap(
+        &mut self,
+        ui: &mut Ui,
+        editor_rect: Rect,
+        user_state: &mut UserState,
+    ) -> bool {
+        if self.node_positions.is_empty() {
+            return false;
+        }
+        let minimap_rect = Rect::from_min_size(
+            editor_rect.max - MINIMAP_SIZE - Vec2::splat(MINIMAP_MARGIN),
+            MINIMAP_SIZE,
+        );
+        if !editor_rect.contains_rect(minimap_rect) {
+            return false;
+        }
+        let resp = ui.interact(
+            minimap_rect,
+            ui.id().with("minimap"),
+            Sense::click_and_drag(),
+        );
+
+        // everything here is in node position space, the screen position of
+        // a node is its position + pan + editor_rect.min
+        let viewport = Rect::from_min_size((-self.pan_zoom.pan).to_pos2(), editor_rect.size());
+        let node_rect = |node_id: NodeId, pos: Pos2| {
+            let size = self.node_sizes.get(node_id).unwrap_or(&NODE_INITIAL_SIZE);
+            Rect::from_min_size(pos, *size)
+        };
+        let bounds = self
+            .node_positions
+            .iter()
+            .fold(viewport, |bounds, (node_id, pos)| {
+                bounds.union(node_rect(node_id, *pos))
+            })
+            .expand(50.0);
+
+        // keep the aspect ratio and center the graph in the minimap
+        let scale = (minimap_rect.width() / bounds.width())
+            .min(minimap_rect.height() / bounds.height());
+        let offset = minimap_rect.center().to_vec2() - bounds.center().to_vec2() * scale;
+        let to_minimap = |rect: Rect| {
+            Rect::from_min_max(
+                (rect.min.to_vec2() * scale + offset).to_pos2(),
+                (rect.max.to_vec2() * scale + offset).to_pos2(),
+            )
+        };
+
+        if let Some(pointer) = resp.interact_pointer_pos() {
+            if resp.clicked() || resp.dragged() {
+                // center the view on the point under the mouse
+                let target = (pointer.to_vec2() - offset) / scale;
+                self.pan_zoom.pan = editor_rect.size() / 2.0 - target;
+            }
+        }
+
+        let visuals = ui.visuals();
+        let (background, default_node_color) = if visuals.dark_mode {
+            (Color32::from_black_alpha(180), Color32::from_gray(110))
+        } else {
+            (Color32::from_white_alpha(200), Color32::from_gray(170))
+        };
+        let viewport_color = visuals.selection.stroke.color;
+        let painter = ui.painter().with_clip_rect(minimap_rect);
+        painter.rect(
+            minimap_rect,
+            4.0,
+            background,
+            Stroke::new(1.0, Color32::from_gray(100)),
+            StrokeKind::Inside,
+        );
+        for frame in self.group_frames.iter() {
+            painter.rect_filled(to_minimap(frame.rect), 1.0, frame.color.gamma_multiply(0.3));
+        }
+        for node_id in self.node_order.iter().copied() {
+            let Some(pos) = self.node_positions.get(node_id) else {
+                continue;
+            };
+            let color = self.graph[node_id]
+                .user_data
+                .titlebar_color(ui, node_id, &self.graph, user_state)
+                .unwrap_or(default_node_color);
+            let rect = to_minimap(node_rect(node_id, *pos));
+            painter.rect_filled(rect, 1.0, color);
+            if self.selected_nodes.contains(&node_id) {
+                painter.rect_stroke(
+                    rect,
+                    1.0,
+                    Stroke::new(1.0, viewport_color),
+                    StrokeKind::Outside,
+                );
+            }
+        }
+        painter.rect_stroke(
+            to_minimap(viewport),
+            0.0,
+            Stroke::new(1.5, viewport_color),
+            StrokeKind::Inside,
+        );
+
+        resp.contains_pointer()
+    }
 }
 
 fn draw_connection(
@@ -588,7 +780,7 @@ fn draw_connection(
     src_pos: Pos2,
     dst_pos: Pos2,
     color: Color32,
-) {
+) -> CubicBezierShape {
     let connection_stroke = egui::Stroke {
         width: 3.0 * pan_zoom.zoom,
         color,
@@ -605,7 +797,30 @@ fn draw_connection(
         connection_stroke,
     );
 
-    painter.add(bezier);
+    painter.add(bezier.clone());
+    bezier
+}
+
+/// Is the point within `distance` of the drawn connection?
+fn connection_contains(bezier: &CubicBezierShape, pos: Pos2, distance: f32) -> bool {
+    if !bezier.visual_bounding_rect().expand(distance).contains(pos) {
+        return false;
+    }
+    let distance = distance + bezier.stroke.width / 2.0;
+    bezier
+        .flatten(Some(1.0))
+        .windows(2)
+        .any(|segment| distance_to_segment(pos, segment[0], segment[1]) <= distance)
+}
+
+fn distance_to_segment(pos: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let t = if ab.length_sq() > 0.0 {
+        ((pos - a).dot(ab) / ab.length_sq()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    pos.distance(a + ab * t)
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -640,6 +855,12 @@ where
                 )
                 .layout(*ui.layout())
         );
+        if self.graph[self.node_id]
+            .user_data
+            .dimmed(self.node_id, self.graph, user_state)
+        {
+            child_ui.multiply_opacity(0.4);
+        }
         Self::show_graph_node(self, pan_zoom, &mut child_ui, user_state)
     }
 
@@ -1015,9 +1236,22 @@ where
                     // param_type is the port that is being hovered over
                     // Allow incompatible connections if one of the ports was defined as supporting any type by user.
                     let origin_type = graph.any_param_type(origin_param).unwrap();
-                    if (port_type.allow_any_type() || origin_type.allow_any_type() || origin_type == port_type)
-                        && close_enough {
+                    let compatible = port_type.allow_any_type()
+                        || origin_type.allow_any_type()
+                        || origin_type == port_type;
+                    if close_enough {
                         match (param_id, origin_param) {
+                            (AnyParameterId::Input(input), AnyParameterId::Output(output))
+                            | (AnyParameterId::Output(output), AnyParameterId::Input(input))
+                                if !compatible =>
+                            {
+                                if ui.input(|i| i.pointer.any_released()) {
+                                    responses.push(NodeResponse::IncompatibleConnection {
+                                        output,
+                                        input,
+                                    });
+                                }
+                            }
                             (AnyParameterId::Input(input), AnyParameterId::Output(output))
                             | (AnyParameterId::Output(output), AnyParameterId::Input(input)) => {
                                 let input_hook =
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use super::*;
+use egui::*;
+
+#[cfg(feature = "persistence")]
+use serde::{Deserialize, Serialize};
+
+const TITLE_HEIGHT: f32 = 24.0;
+const RESIZE_HANDLE_SIZE: f32 = 12.0;
+const FRAME_MIN_SIZE: Vec2 = Vec2::new(120.0, 60.0);
+const FRAME_PADDING: f32 = 20.0;
+
+/// A titled, colored rectangle drawn behind the nodes. Nodes inside of it
+/// move together with it, and get hidden when it is collapsed.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct GroupFrame {
+    pub title: String,
+    pub color: Color32,
+    /// Area covered by the frame when expanded, in the same space as the
+    /// node positions.
+    pub rect: Rect,
+    pub collapsed: bool,
+}
+
+impl GroupFrame {
+    pub fn new(title: impl Into<String>, rect: Rect) -> Self {
+        Self {
+            title: title.into(),
+            color: Color32::from_rgb(70, 110, 160),
+            rect,
+            collapsed: false,
+        }
+    }
+
+    /// Nodes count as inside of the frame if their top left corner is.
+    pub fn contains_node(&self, node_position: Pos2) -> bool {
+        self.rect.contains(node_position)
+    }
+}
+
+/// What happened to the group frames this frame, used by the rest of the
+/// editor drawing code.
+#[derive(Default)]
+pub(crate) struct GroupFramesResponse {
+    /// Nodes inside of collapsed frames, these are not drawn. Their
+    /// connections go to the title bar of the frame instead.
+    pub hidden_nodes: HashMap<NodeId, Rect>,
+    /// Is the mouse over the title bar of any frame?
+    pub cursor_on_title: bool,
+}
+
+impl<NodeData, DataType, ValueType, NodeTemplate, UserState>
+    GraphEditorState<NodeData, DataType, ValueType, NodeTemplate, UserState>
+{
+    /// Adds a new frame around the given nodes and returns its index in
+    /// [`GraphEditorState::group_frames`]. Without nodes, the frame is placed
+    /// at `fallback_position`.
+    pub fn add_group_frame(
+        &mut self,
+        title: impl Into<String>,
+        node_ids: &[NodeId],
+        fallback_position: Pos2,
+    ) -> usize {
+        let zoom = self.pan_zoom.zoom;
+        let bounds = node_ids
+            .iter()
+            .filter_map(|node_id| {
+                let pos = self.node_positions.get(*node_id)?;
+                let size = self
+                    .node_sizes
+                    .get(*node_id)
+                    .copied()
+                    .unwrap_or(Vec2::splat(200.0));
+                Some(Rect::from_min_size(*pos, size * zoom))
+            })
+            .reduce(|a, b| a.union(b));
+        let rect = match bounds {
+            Some(bounds) => Rect::from_min_max(
+                bounds.min - vec2(FRAME_PADDING, FRAME_PADDING + TITLE_HEIGHT) * zoom,
+                bounds.max + Vec2::splat(FRAME_PADDING) * zoom,
+            ),
+            None => Rect::from_min_size(fallback_position, vec2(400.0, 300.0) * zoom),
+        };
+        self.group_frames.push(GroupFrame::new(title, rect));
+        self.group_frames.len() - 1
+    }
+
+    /// Draws the group frames and handles their interaction. Needs to be
+    /// called before drawing the nodes, so they end up on top.
+    pub(crate) fn draw_group_frames(
+        &mut self,
+        ui: &mut Ui,
+        editor_rect: Rect,
+    ) -> GroupFramesResponse {
+        let mut response = GroupFramesResponse::default();
+        let zoom = self.pan_zoom.zoom;
+        let offset = self.pan_zoom.pan + editor_rect.min.to_vec2();
+        let title_height = TITLE_HEIGHT * zoom;
+        let mut frame_to_delete = None;
+
+        for idx in 0..self.group_frames.len() {
+            let frame = &self.group_frames[idx];
+            let screen_rect = frame.rect.translate(offset);
+            let title_rect =
+                Rect::from_min_size(screen_rect.min, vec2(screen_rect.width(), title_height));
+            let body_rect = if frame.collapsed {
+                title_rect
+            } else {
+                screen_rect
+            };
+
+            let painter = ui.painter();
+            painter.rect(
+                body_rect,
+                4.0 * zoom,
+                frame.color.gamma_multiply(0.15),
+                Stroke::new(1.5 * zoom, frame.color),
+                StrokeKind::Inside,
+            );
+            painter.rect_filled(title_rect, 4.0 * zoom, frame.color.gamma_multiply(0.8));
+            let arrow = if frame.collapsed { "▶" } else { "▼" };
+            let font = FontId::proportional(14.0 * zoom);
+            painter.text(
+                title_rect.left_center() + vec2(6.0 * zoom, 0.0),
+                Align2::LEFT_CENTER,
+                format!("{arrow} {}", frame.title),
+                font,
+                Color32::WHITE,
+            );
+
+            let id = ui.id().with(("group_frame", idx));
+            let title_resp = ui.interact(title_rect, id, Sense::click_and_drag());
+            response.cursor_on_title |= title_resp.contains_pointer();
+
+            // nodes and nested frames that move with this one
+            let contained_nodes: Vec<NodeId> = self
+                .node_positions
+                .iter()
+                .filter(|(_, pos)| frame.contains_node(**pos))
+                .map(|(node_id, _)| node_id)
+                .collect();
+            if frame.collapsed {
+                response
+                    .hidden_nodes
+                    .extend(contained_nodes.iter().map(|node_id| (*node_id, title_rect)));
+            }
+
+            let arrow_rect = Rect::from_min_size(title_rect.min, vec2(title_height, title_height));
+            if title_resp.clicked()
+                && title_resp
+                    .interact_pointer_pos()
+                    .is_some_and(|pos| arrow_rect.contains(pos))
+            {
+                self.group_frames[idx].collapsed = !self.group_frames[idx].collapsed;
+            } else if title_resp.double_clicked() {
+                self.group_frames[idx].collapsed = !self.group_frames[idx].collapsed;
+            }
+
+            // what the frame carries is decided when the drag starts, so it doesn't pick up
+            // the nodes and frames it passes over
+            let drag_id = id.with("dragged");
+            if title_resp.drag_started() {
+                let frame_rect = self.group_frames[idx].rect;
+                let contained_frames: Vec<usize> = self
+                    .group_frames
+                    .iter()
+                    .enumerate()
+                    .filter(|(other_idx, other)| {
+                        *other_idx == idx || frame_rect.contains_rect(other.rect)
+                    })
+                    .map(|(other_idx, _)| other_idx)
+                    .collect();
+                ui.data_mut(|data| {
+                    data.insert_temp(drag_id, (contained_nodes.clone(), contained_frames))
+                });
+            }
+            let drag_delta = title_resp.drag_delta();
+            if drag_delta != Vec2::ZERO {
+                let dragged: Option<(Vec<NodeId>, Vec<usize>)> =
+                    ui.data(|data| data.get_temp(drag_id));
+                if let Some((nodes, frames)) = dragged {
+                    for node_id in nodes {
+                        if let Some(pos) = self.node_positions.get_mut(node_id) {
+                            *pos += drag_delta;
+                        }
+                    }
+                    for other_idx in frames {
+                        if let Some(other) = self.group_frames.get_mut(other_idx) {
+                            other.rect = other.rect.translate(drag_delta);
+                        }
+                    }
+                }
+            }
+            if title_resp.drag_stopped() {
+                ui.data_mut(|data| data.remove::<(Vec<NodeId>, Vec<usize>)>(drag_id));
+            }
+
+            title_resp.context_menu(|ui| {
+                let frame = &mut self.group_frames[idx];
+                ui.horizontal(|ui| {
+                    ui.label("Title");
+                    ui.text_edit_singleline(&mut frame.title);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Color");
+                    ui.color_edit_button_srgba(&mut frame.color);
+                });
+                ui.checkbox(&mut frame.collapsed, "Collapsed");
+                if ui.button("Delete frame").clicked() {
+                    frame_to_delete = Some(idx);
+                    ui.close();
+                }
+            });
+
+            if !self.group_frames[idx].collapsed {
+                let handle_rect = Rect::from_min_max(
+                    screen_rect.max - Vec2::splat(RESIZE_HANDLE_SIZE * zoom),
+                    screen_rect.max,
+                );
+                let handle_resp = ui
+                    .interact(handle_rect, id.with("resize"), Sense::drag())
+                    .on_hover_cursor(CursorIcon::ResizeNwSe);
+                let color = self.group_frames[idx].color;
+                ui.painter().add(Shape::convex_polygon(
+                    vec![
+                        handle_rect.right_top(),
+                        handle_rect.right_bottom(),
+                        handle_rect.left_bottom(),
+                    ],
+                    color,
+                    Stroke::NONE,
+                ));
+                if handle_resp.dragged() {
+                    let rect = &mut self.group_frames[idx].rect;
+                    rect.max += handle_resp.drag_delta();
+                    rect.max = rect.max.max(rect.min + FRAME_MIN_SIZE * zoom);
+                }
+            }
+        }
+
+        if let Some(idx) = frame_to_delete {
+            self.group_frames.remove(idx);
+        }
+        response
+    }
+
+    /// Collapsed frames hide their nodes, so connect their ports to the title
+    /// bar of the frame instead.
+    pub(crate) fn hidden_node_locations(
+        &self,
+        node_id: NodeId,
+        title_rect: Rect,
+        port_locations: &mut PortLocations,
+        conn_locations: &mut ConnLocations,
+    ) {
+        let node = &self.graph[node_id];
+        for (_, input) in node.inputs.iter() {
+            let connections = self.graph.connections(*input).len();
+            port_locations.insert(
+                AnyParameterId::Input(*input),
+                vec![title_rect.left_center()],
+            );
+            conn_locations.insert(*input, vec![title_rect.left_center(); connections]);
+        }
+        for (_, output) in node.outputs.iter() {
+            port_locations.insert(
+                AnyParameterId::Output(*output),
+                vec![title_rect.right_center()],
+            );
+        }
+    }
+}
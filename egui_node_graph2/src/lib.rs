@@ -32,6 +32,10 @@ pub use structs::*;
 pub mod ui_state;
 pub use ui_state::*;
 
+/// Frames that group nodes together
+pub mod group_frame;
+pub use group_frame::*;
+
 /// The node finder is a tiny widget allowing to create new node types
 pub mod node_finder;
 pub use node_finder::*;
@@ -165,6 +165,28 @@ where
         None
     }
 
+    /// Color of a connection going into one of this node's inputs.
+    /// If the return value is None, the color of the data type is used.
+    fn connection_color(
+        &self,
+        _input: InputId,
+        _output: OutputId,
+        _graph: &Graph<Self, Self::DataType, Self::ValueType>,
+        _user_state: &mut Self::UserState,
+    ) -> Option<egui::Color32> {
+        None
+    }
+
+    /// Draw the node faded out, for example to show that it has no effect.
+    fn dimmed(
+        &self,
+        _node_id: NodeId,
+        _graph: &Graph<Self, Self::DataType, Self::ValueType>,
+        _user_state: &mut Self::UserState,
+    ) -> bool {
+        false
+    }
+
     /// Separator to put between elements in the node.
     ///
     /// Invoked between inputs, outputs and bottom UI. Useful for
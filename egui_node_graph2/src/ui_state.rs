@@ -1,5 +1,5 @@
 use super::*;
-use egui::{Rect, Style, Ui, Vec2};
+use egui::{Pos2, Rect, Style, Ui, Vec2};
 use std::marker::PhantomData;
 use std::sync::Arc;
 
@@ -51,6 +51,12 @@ pub struct GraphEditorState<NodeData, DataType, ValueType, NodeTemplate, UserSta
     pub node_finder: Option<NodeFinder<NodeTemplate>>,
     /// The panning of the graph viewport.
     pub pan_zoom: PanZoom,
+    /// Frames drawn behind the nodes, in drawing order.
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub group_frames: Vec<GroupFrame>,
+    /// Show an overview of the whole graph in the corner of the editor.
+    #[cfg_attr(feature = "persistence", serde(default = "_default_show_minimap"))]
+    pub show_minimap: bool,
     pub _user_state: PhantomData<fn() -> UserState>,
     // #[serde(skip)]
     // pub undo_memory: CircularStack<Graph<NodeData, DataType, ValueType>>,
@@ -80,6 +86,8 @@ impl<NodeData, DataType, ValueType, NodeKind, UserState> Default
             node_sizes: Default::default(),
             node_finder: Default::default(),
             pan_zoom: Default::default(),
+            group_frames: Default::default(),
+            show_minimap: true,
             _user_state: Default::default(),
             //undo_memory: Default::default(),
         }
@@ -91,11 +99,20 @@ fn _default_clip_rect() -> Rect {
     Rect::NOTHING
 }
 
+#[cfg(feature = "persistence")]
+fn _default_show_minimap() -> bool {
+    true
+}
+
 #[derive(Clone)]
 #[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
 pub struct PanZoom {
     pub pan: Vec2,
     pub zoom: f32,
+    /// Where the origin of the unzoomed graph space is, in the same space as
+    /// the node positions. It moves around with the nodes when zooming.
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub origin: Pos2,
     #[cfg_attr(feature = "persistence", serde(skip, default = "_default_clip_rect"))]
     pub clip_rect: Rect,
     #[cfg_attr(feature = "persistence", serde(skip, default))]
@@ -109,6 +126,7 @@ impl Default for PanZoom {
         PanZoom {
             pan: Vec2::ZERO,
             zoom: 1.0,
+            origin: Pos2::ZERO,
             clip_rect: Rect::NOTHING,
             zoomed_style: Default::default(),
             started: false,
@@ -122,12 +140,25 @@ impl PanZoom {
         PanZoom {
             pan: Vec2::ZERO,
             zoom,
+            origin: Pos2::ZERO,
             clip_rect: Rect::NOTHING,
             zoomed_style: Arc::new(style.scaled(1.0)),
             started: false,
         }
     }
 
+    /// Converts a node position to the unzoomed graph space, where positions
+    /// stay the same when zooming.
+    pub fn to_graph_space(&self, pos: Pos2) -> Pos2 {
+        ((pos - self.origin) / self.zoom).to_pos2()
+    }
+
+    /// Converts a position in the unzoomed graph space back to a node
+    /// position. See [`Self::to_graph_space`].
+    pub fn from_graph_space(&self, pos: Pos2) -> Pos2 {
+        self.origin + pos.to_vec2() * self.zoom
+    }
+
     pub fn zoom(&mut self, clip_rect: Rect, style: &Arc<Style>, zoom_delta: f32) {
         self.clip_rect = clip_rect;
         let new_zoom = (self.zoom * zoom_delta).clamp(MIN_ZOOM, MAX_ZOOM);
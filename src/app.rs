@@ -3,13 +3,28 @@ mod impls;
 mod help;
 pub mod types;
 mod migrations;
+mod clipboard;
+mod macros;
+mod libraries;
+mod tabs;
+mod layout;
+mod search;
+mod references;
+mod validation;
+mod conversions;
+mod history;
+mod document;
+mod recovery;
+pub mod merge;
+pub mod diff;
+mod export;
+mod interface;
+pub mod fgd;
 
 use delegate::delegate;
-use std::time::UNIX_EPOCH;
 use std::{path::PathBuf, fs, thread};
 use core::panic;
-use eframe::egui::util::undoer::{Settings, Undoer};
-use eframe::egui::{Button, TextStyle, Vec2};
+use eframe::egui::{Button, Vec2};
 use serde::{Deserialize, Serialize};
 use rfd::{FileDialog, MessageDialog};
 use anyhow::anyhow;
@@ -18,9 +33,19 @@ use egui_node_graph2::*;
 use crate::bindings::*;
 use crate::compiler::compile_graph;
 use crate::pulsetypes::*;
+use clipboard::ClipboardContent;
 use crate::typing::*;
 use crate::utils::get_node_ids_connected_to_output;
 use types::*;
+use tabs::{GraphTab, PersistedTabs};
+use search::SearchPanel;
+use references::{GraphSymbol, SymbolUsage, usages_ui};
+use history::History;
+use migrations::Upgrade;
+use document::{GraphDocument, DOCUMENT_VERSION};
+use recovery::Recovery;
+use merge::MergeSession;
+use export::ExportOptions;
 
 static APP_NAME: &str = "Pulse Graph Editor";
 #[derive(Default, Clone)]
@@ -28,7 +53,13 @@ static APP_NAME: &str = "Pulse Graph Editor";
 pub enum ModalWindowType {
     #[default]
     None,
-    ConfirmSave
+    // closing a tab with unsaved changes
+    ConfirmCloseTab(usize),
+    // deleting a variable or output that's still used by some nodes
+    ConfirmDeleteSymbol(GraphSymbol, usize),
+    // graphs left over by a run that crashed, see recovery.rs
+    RecoverSessions,
+    ExportImage,
 }
 
 #[derive(Default, Clone)]
@@ -38,13 +69,26 @@ pub struct ModalWindow {
     pub is_open: bool,
 }
 
-#[derive(Default, Clone)]
+#[derive(Clone)]
 #[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
 pub struct FullGraphState {
+    // graphs saved before this was added are version 0, see migrations.rs
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub format_version: u32,
     pub state: MyEditorState,
     pub user_state: PulseGraphState
 }
 
+impl Default for FullGraphState {
+    fn default() -> Self {
+        Self {
+            format_version: migrations::FORMAT_VERSION,
+            state: MyEditorState::default(),
+            user_state: PulseGraphState::default(),
+        }
+    }
+}
+
 impl FullGraphState {
     pub fn state(&self) -> &MyEditorState {
         &self.state
@@ -59,43 +103,57 @@ impl FullGraphState {
         &mut self.user_state
     }
 
-    pub fn load_state(&mut self, filepath: &PathBuf) -> Result<(), anyhow::Error> {
+    // Adds a node from a template with its top left corner at `position`.
+    pub fn add_node_at(&mut self, template: PulseNodeTemplate, position: egui::Pos2, size: egui::Vec2) -> NodeId {
+        let FullGraphState { state, user_state, .. } = self;
+        let node_id = state.graph.add_node(
+            template.node_graph_label(user_state),
+            template.user_data(user_state),
+            |graph, node_id| template.build_node(graph, user_state, node_id),
+        );
+        state.node_positions.insert(node_id, position);
+        state.node_sizes.insert(node_id, size);
+        state.node_order.push(node_id);
+        node_id
+    }
+
+    // Parses a saved graph, and upgrades it if it was saved by an older version.
+    pub fn from_saved(contents: &str) -> anyhow::Result<(FullGraphState, Option<Upgrade>)> {
+        let version = migrations::check_format_version(contents)?;
+        let parse_error = |e: ron::error::SpannedError| anyhow::anyhow!("Failed to parse file: {e}");
+        let mut full_state = if version >= DOCUMENT_VERSION {
+            ron::from_str::<GraphDocument>(contents).map_err(parse_error)?.into_full_state()?
+        } else {
+            ron::from_str::<FullGraphState>(contents).map_err(parse_error)?
+        };
+        let upgrade = full_state.migrate()?;
+        Ok((full_state, upgrade))
+    }
+
+    // Returns what was upgraded if the file was saved by an older version.
+    pub fn load_state(&mut self, filepath: &PathBuf) -> Result<Option<Upgrade>, anyhow::Error> {
         let contents = fs::read_to_string(filepath)?;
-        let loaded_graph: FullGraphState = ron::from_str(&contents).map_err(|e| {
-            anyhow::anyhow!(
-                "Failed to parse file: {}",
-                e.to_string()
-            )
-        })?;
+        let (loaded_graph, upgrade) = FullGraphState::from_saved(&contents)?;
+        self.format_version = loaded_graph.format_version;
         self.state = loaded_graph.state;
         self.user_state.load_from(loaded_graph.user_state);
         self.user_state.save_file_path = Some(filepath.clone());
         self.verify_compat();
-        Ok(())
+        for e in self.user_state.reload_libraries() {
+            println!("[Load] Warning: {e:#}");
+        }
+        Ok(upgrade)
     }
 
     fn save_graph(&self, filepath: &PathBuf) -> Result<(), anyhow::Error> {
-        let res = ron::ser::to_string_pretty::<FullGraphState>(
-            self,
-            ron::ser::PrettyConfig::default(),
-        )?;
+        let res = GraphDocument::from_full_state(&self.root_state()).to_ron()?;
         fs::write(filepath, res)?;
         Ok(())
     }
 
-     // Applies some corrections if some data is missing or changed for files saved in older versions
+    // Fixes up things that can change between sessions without the file format changing, like the bindings.
+    // Upgrades of older files are in migrations.rs.
     pub fn verify_compat(&mut self) {
-        // v0.1.1 introduces a SecondaryMap node_sizes in GraphEditorState
-        // make sure that it is populated with every existing node.
-        if self.state.node_sizes.is_empty() {
-            for node in self.state.graph.nodes.iter() {
-                self.state.node_sizes.insert(node.0, egui::vec2(200.0, 200.0));
-            }
-        }
-        let mut sound_event_nodes = vec![];
-        let mut entfire_nodes = vec![];
-        let mut call_func_nodes = vec![];
-        let mut listen_entity_output_nodes = vec![];
         struct QueuedAddParams {
             node_id: NodeId,
             param_name: String,
@@ -108,133 +166,35 @@ impl FullGraphState {
                 Some(node) => node,
                 None => continue,
             };  
-            let template = node.user_data.template;
-            match template {
-                // verify that all existing library binding nodes have correct parameters, in case they have been updated between sessions.
-                // NOTE: this does not remove any parameters from the node, they would be just ignored.
-                PulseNodeTemplate::LibraryBindingAssigned { binding } => {
-                    if let Some(binding) = self.user_state.bindings.find_function_by_id(binding) {
-                        if binding.inparams.is_none() {
-                            continue;
-                        }
-                        let mut inputs = node.inputs.iter_mut().filter(|input| {
-                            let nam_lowercase = input.0.to_lowercase();
-                            !nam_lowercase.contains("action") && !nam_lowercase.contains("binding")
-                        }).collect::<Vec<_>>();
-
-                        for (idx, param) in binding.inparams.as_ref().unwrap().iter().enumerate() {
-                            if idx < inputs.len() {
-                                // Safety: we checked the length above
-                                inputs[idx].0 = param.name.clone();
-                            } else {
-                                // quque up missing parameters to be added after the loop to avoid borrow checker issues
-                                queued_add_params.push(QueuedAddParams { 
-                                    node_id,
-                                    param_name: param.name.clone(),
-                                    types: pulse_value_type_to_node_types(&param.pulsetype),
-                                    connection_type: get_preffered_inputparamkind_from_type(&param.pulsetype) 
-                                });
-                            }
-                        }
+            // verify that all existing library binding nodes have correct parameters, in case they have been updated between sessions.
+            // NOTE: this does not remove any parameters from the node, they would be just ignored.
+            if let PulseNodeTemplate::LibraryBindingAssigned { binding } = node.user_data.template {
+                if let Some(binding) = self.user_state.bindings.find_function_by_id(binding) {
+                    if binding.inparams.is_none() {
+                        continue;
                     }
-                }
-                // v0.3.1 we added sound event source input.
-                PulseNodeTemplate::SoundEventStart => {
-                    // if the input is not present, add it to a list, and then add the input later
-                    // can't do it here because of borrow checker
-                    if node.get_input("soundEventType").is_err() {
-                        sound_event_nodes.push(node_id);
-                    }
-                }
-                // v0.3.1 Added entity handle input to EntFire
-                PulseNodeTemplate::EntFire => {
-                    if node.get_input("entityHandle").is_err() {
-                        entfire_nodes.push(node_id);
-                    }
-                }
-                // v0.3.1 Added Async fire mode to Call Node for functions
-                PulseNodeTemplate::CallNode => {
-                    if node.get_input("Async").is_err() {
-                        let target_node_id = node
-                            .get_input("nodeId")
-                            .ok()
-                            .and_then(|input_id| {
-                                self.state().graph.get_input(input_id).value().clone().try_node_id().ok()
-                            });
+                    let mut inputs = node.inputs.iter_mut().filter(|input| {
+                        let nam_lowercase = input.0.to_lowercase();
+                        !nam_lowercase.contains("action") && !nam_lowercase.contains("binding")
+                    }).collect::<Vec<_>>();
 
-                        if let Some(target_node_id) = target_node_id {
-                            if let Some(target_node) = self.state().graph.nodes.get(target_node_id) {
-                                match target_node.user_data.template {
-                                    PulseNodeTemplate::Function => { call_func_nodes.push(node_id); },
-                                    PulseNodeTemplate::ListenForEntityOutput => { listen_entity_output_nodes.push(node_id); },
-                                    _ => {}
-                                }
-                            }
+                    for (idx, param) in binding.inparams.as_ref().unwrap().iter().enumerate() {
+                        if idx < inputs.len() {
+                            // Safety: we checked the length above
+                            inputs[idx].0 = param.name.clone();
+                        } else {
+                            // quque up missing parameters to be added after the loop to avoid borrow checker issues
+                            queued_add_params.push(QueuedAddParams { 
+                                node_id,
+                                param_name: param.name.clone(),
+                                types: pulse_value_type_to_node_types(&param.pulsetype),
+                                connection_type: get_preffered_inputparamkind_from_type(&param.pulsetype) 
+                            });
                         }
                     }
                 }
-                _ => (),
-            }
-        }
-        for node_id in sound_event_nodes {
-            self.state.graph.add_input_param(
-                node_id,
-                "soundEventType".to_string(),
-                PulseDataType::GeneralEnum,
-                PulseGraphValueType::GeneralEnumChoice {
-                    value: GeneralEnumChoice::SoundEventStartType(SoundEventStartType::default())
-                },
-                InputParamKind::ConstantOnly,
-                true,
-            );
-            // TODO: would be good to have some publically accessible simplifications for adding common inputs
-            self.state.graph.add_input_param(
-                node_id,
-                "ActionIn".to_string(),
-                PulseDataType::Action,
-                PulseGraphValueType::Action,
-                InputParamKind::ConnectionOnly,
-                true,
-            );
-            self.state.graph.add_output_param(node_id, "outAction".to_string(), PulseDataType::Action);
-            // all of this below is just to move the input action to the top, since the library doesn't really make that easy.
-            let node = self.state.graph.nodes.get_mut(node_id).unwrap();
-            let mut input_id = None;
-            node.inputs.retain(|input| {
-                input_id = Some(input.1);
-                input.0 != "ActionIn"
-            });
-            if let Some(input_id) = input_id {
-                node.inputs.insert(0,("ActionIn".to_string(), input_id));
-            }
-        }
-        for node_id in entfire_nodes {
-            self.state.graph.add_input_param(
-                node_id,
-                "entityHandle".to_string(),
-                PulseDataType::EHandle,
-                PulseGraphValueType::EHandle,
-                InputParamKind::ConnectionOnly,
-                true,
-            );
-        }
-        for node_id in call_func_nodes {
-            self.state.graph.add_input_param(
-                node_id,
-                "Async".to_string(),
-                PulseDataType::Bool,
-                PulseGraphValueType::Bool { value: false },
-                InputParamKind::ConstantOnly,
-                true,
-            );
-        }
-        for node_id in listen_entity_output_nodes {
-            let node = self.state.graph.nodes.get_mut(node_id).unwrap();
-            if let Ok(o) = node.get_output("outAction") { 
-                self.state.graph.remove_output_param(o);
             }
         }
-
         for param in queued_add_params {
             self.state.graph.add_input_param(
                 param.node_id,
@@ -258,13 +218,24 @@ impl FullGraphState {
 
 #[derive(Default, Clone)]
 pub struct PulseGraphEditor {
-    #[allow(unused)]
-    version: FileVersion,
     full_state: FullGraphState,
     #[cfg(feature = "nongame_asset_build")]
     editor_config: EditorConfig,
     current_modal_dialog: ModalWindow,
-    undoer: Undoer<FullGraphState>,
+    history: History,
+    // open graphs, see tabs.rs
+    tabs: Vec<GraphTab>,
+    active_tab: usize,
+    // find in graph panel, only there while it's open
+    search: Option<SearchPanel>,
+    show_problems: bool,
+    show_history: bool,
+    // autosaves, see recovery.rs
+    recovery: Recovery,
+    // conflicts of a merge started from the command line, see merge.rs
+    merge: Option<MergeSession>,
+    // last used settings of the export dialog, see export.rs
+    export_options: ExportOptions,
 }
 
 impl PulseGraphEditor {
@@ -276,11 +247,25 @@ impl PulseGraphEditor {
             pub fn user_state_mut(&mut self) -> &mut PulseGraphState;
         }
     }
+    fn node_templates(&self) -> AllMyNodeTemplates {
+        AllMyNodeTemplates {
+            game_function_count: self.user_state().bindings.gamefunctions.len(),
+            // a macro can't contain itself
+            macro_ids: self.user_state().macros.iter()
+                .map(|m| m.id)
+                .filter(|id| !self.user_state().macro_stack.contains(id))
+                .collect(),
+            library_templates: self.user_state().library_templates(),
+        }
+    }
     fn save_graph(&self, filepath: &PathBuf) -> Result<(), anyhow::Error> {
         self.full_state.save_graph(filepath)
     }
     // perform a save including including some cleanup
     fn perform_save(&mut self, filepath: Option<&PathBuf>) -> anyhow::Result<()> {
+        if self.user_state().diff.is_some() {
+            anyhow::bail!("This tab shows the changes between two versions of a graph, it can't be saved");
+        }
         let dest_path;
         // remove the path on manual save (we don't use serde skip because we want to save it within autosaves)
         let save_path = self.full_state.user_state.save_file_path.take();
@@ -299,6 +284,7 @@ impl PulseGraphEditor {
         self.save_graph(dest_path)?;
         // restore the path info to memory.
         self.full_state.user_state.save_file_path = save_path;
+        self.mark_saved();
         Ok(())
     }
     // promts user to choose a file to save the graph to and remembers the location for saving.
@@ -313,20 +299,13 @@ impl PulseGraphEditor {
         did_pick
     }
    
-    fn load_graph(&mut self, filepath: &PathBuf) -> Result<(), anyhow::Error> {
+    fn load_graph(&mut self, filepath: &PathBuf) -> Result<Option<Upgrade>, anyhow::Error> {
         let res = self.full_state.load_state(filepath);
         if res.is_ok() {
-            self.undoer = Self::get_new_undoer();
+            self.history = History::saved();
         }
         res
     }
-    fn new_graph(&mut self, ctx: &egui::Context) {
-        self.undoer = Self::get_new_undoer();
-        self.full_state.state = MyEditorState::default();
-        self.user_state_mut().load_from(PulseGraphState::default());
-        self.user_state_mut().save_file_path = None;
-        self.update_titlebar(ctx);
-    }
     pub fn update_output_node_param(&mut self, node_id: NodeId, name: &String, input_name: &str) {
         let param = self
             .state_mut()
@@ -455,7 +434,11 @@ impl PulseGraphEditor {
                     .iter()
                     .find(|var| var.name == *name);
                 if let Some(var) = var {
-                    let val_typ = data_type_to_value_type(&var.data_type);
+                    // schema enums need to know which enum they are, data type alone isn't enough.
+                    let val_typ = match &var.typ_and_default_value {
+                        PulseValueType::PVAL_SCHEMA_ENUM(_) => pulse_value_type_to_node_types(&var.typ_and_default_value).1,
+                        _ => data_type_to_value_type(&var.data_type),
+                    };
                     self.add_node_input_simple(
                         node_id,
                         var.data_type.clone(),
@@ -591,6 +574,19 @@ impl PulseGraphEditor {
                     InputParamKind::ConnectionOrConstant,
                 );
             }
+            PulseNodeTemplate::CombineFlags | PulseNodeTemplate::TestFlags => {
+                if new_type.is_none() {
+                    panic!("update_node_inputs_outputs() ended up on node that requires new value type from response, but it was not provided");
+                }
+                let types = pulse_value_type_to_node_types(&new_type.unwrap());
+                // data type stays the same, only the enum inside the value changes so connections are kept.
+                let params: Vec<_> = ["value", "flags"].iter()
+                    .filter_map(|name| node.get_input(name).ok())
+                    .collect();
+                for param in params {
+                    self.state_mut().graph.get_input_mut(param).value = types.1.clone();
+                }
+            }
             PulseNodeTemplate::NewArray => {
                 let types = pulse_value_type_to_node_types(&new_type.unwrap_or_default());
                 let inputs = node.user_data.added_inputs.clone();
@@ -974,9 +970,10 @@ impl PulseGraphEditor {
     fn clone_node(&mut self, source_node_id: NodeId, pos_offset: egui::Vec2) -> NodeId {
         let source_node_data = self.state().graph.nodes.get(source_node_id).unwrap();
         let source_label = source_node_data.label.clone();
-        let source_user_data = source_node_data.user_data.clone();
+        let mut source_user_data = source_node_data.user_data.clone();
         let inputs = source_node_data.inputs.clone();
         let outputs = source_node_data.outputs.clone();
+        source_user_data.uid = self.user_state_mut().new_node_uid();
         let new_node = self.state_mut().graph.add_node(
             source_label,
             source_user_data,
@@ -1017,8 +1014,50 @@ impl PulseGraphEditor {
         new_node
     }
 
+    // splits a connection in two with a reroute node placed at `position` (graph space)
+    fn insert_reroute(&mut self, output: OutputId, input: InputId, position: egui::Pos2) -> anyhow::Result<()> {
+        let size = egui::vec2(100.0, 60.0);
+        let position = position - size / 2.0 * self.state().pan_zoom.zoom;
+        let node_id = self.full_state.add_node_at(PulseNodeTemplate::Reroute, position, size);
+
+        let graph = &mut self.state_mut().graph;
+        // keep the place of the connection in wide inputs
+        let hook = graph.connections(input).iter().position(|id| *id == output).unwrap_or(0);
+        graph.remove_connection(input, output);
+        let reroute_in = graph.nodes[node_id].get_input("in")?;
+        let reroute_out = graph.nodes[node_id].get_output("out")?;
+        graph.add_connection(output, reroute_in, 0);
+        graph.add_connection(reroute_out, input, hook);
+        self.commit_history(Some("Insert reroute"));
+        Ok(())
+    }
+
+    fn copy_selected_nodes(&self, ctx: &egui::Context) -> anyhow::Result<()> {
+        let selected_nodes = self.state().selected_nodes.to_vec();
+        if selected_nodes.is_empty() {
+            return Ok(());
+        }
+        let content = ClipboardContent::from_nodes(&self.full_state, &selected_nodes);
+        ctx.copy_text(content.to_clipboard_string()?);
+        Ok(())
+    }
+
+    // paste nodes from clipboard text, `position` is in graph space (same as node_positions)
+    fn paste_nodes(&mut self, text: &str, position: egui::Pos2) -> anyhow::Result<()> {
+        // not our data, ignore it
+        let Some(content) = ClipboardContent::from_clipboard_string(text) else {
+            return Ok(());
+        };
+        let new_nodes = content?.paste_into(&mut self.full_state, position);
+        self.state_mut().selected_nodes = new_nodes;
+        self.commit_history(Some("Paste"));
+        Ok(())
+    }
+
     fn update_titlebar(&self, ctx: &egui::Context) {
-        let file_name = if let Some(file_path) = &self.user_state().save_file_path {
+        let file_name = if let Some(diff) = &self.user_state().diff {
+            diff.title.clone()
+        } else if let Some(file_path) = &self.user_state().save_file_path {
             file_path
                 .file_name()
                 .and_then(|name| name.to_str())
@@ -1033,31 +1072,18 @@ impl PulseGraphEditor {
         ));
     }
 
-    fn feed_undo_state(&mut self) {
-        self.undoer.feed_state(
-            std::time::SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("time went backwards")
-                .as_secs_f64(), 
-            &self.full_state
-        );
+    // Records the changes made so far as an undo step, named `label` (or after what changed if None).
+    fn commit_history(&mut self, label: Option<&str>) {
+        self.history.commit(&self.full_state, label);
     }
 
     fn do_undo(&mut self) {
-        let current_file = self.user_state().save_file_path.clone();
-        if let Some(state) = self.undoer.undo(&self.full_state) {
-            self.full_state = state.clone();
-        }
-        self.user_state_mut().save_file_path = current_file;
+        self.history.undo(&mut self.full_state);
         self.state_mut().connection_in_progress = None;
     }
 
     fn do_redo(&mut self) {
-        let current_file = self.user_state().save_file_path.clone();
-        if let Some(state) = self.undoer.redo(&self.full_state) {
-            self.full_state = state.clone();
-        }
-        self.user_state_mut().save_file_path = current_file;
+        self.history.redo(&mut self.full_state);
         self.state_mut().connection_in_progress = None;
     }
 }
@@ -1066,13 +1092,52 @@ impl PulseGraphEditor{
     /// If the persistence feature is enabled, Called once before the first frame.
     /// Load previous app state (if any).
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        // older versions only remembered a single graph
+        let mut persisted: PersistedTabs = cc.storage
+            .and_then(|storage| eframe::get_value(storage, TABS_PERSISTENCE_KEY))
+            .or_else(|| cc.storage
+                .and_then(|storage| eframe::get_value::<FullGraphState>(storage, PERSISTENCE_KEY))
+                .map(|full_state| PersistedTabs { tabs: vec![full_state], active_tab: 0, dirty: vec![] }))
+            .unwrap_or_default();
+        if persisted.tabs.is_empty() {
+            persisted.tabs.push(FullGraphState::default());
+        }
+        let active_tab = persisted.active_tab.min(persisted.tabs.len() - 1);
+        let dirty = persisted.dirty;
+        let mut tabs: Vec<GraphTab> = persisted.tabs.into_iter()
+            .enumerate()
+            .map(|(idx, mut full_state)| {
+                if let Err(e) = full_state.migrate() {
+                    println!("[Load] Warning: {e:#}");
+                }
+                full_state.verify_compat();
+                for e in full_state.user_state.reload_libraries() {
+                    println!("[Load] Warning: {e:#}");
+                }
+                // without the flag, anything that isn't empty might have unsaved changes
+                let dirty = dirty.get(idx).copied().unwrap_or(!full_state.state.graph.nodes.is_empty());
+                GraphTab {
+                    full_state,
+                    // a new history counts as unsaved
+                    history: if dirty { History::default() } else { History::saved() },
+                }
+            })
+            .collect();
+        let active = std::mem::take(&mut tabs[active_tab]);
         let mut grph = Self {
-            full_state: cc.storage
-                .and_then(|storage| eframe::get_value(storage, PERSISTENCE_KEY))
-                .unwrap_or_default(),
-            undoer: Self::get_new_undoer(),
+            full_state: active.full_state,
+            #[cfg(feature = "nongame_asset_build")]
+            editor_config: EditorConfig::default(),
+            history: active.history,
+            tabs,
+            active_tab,
+            search: None,
+            show_problems: false,
+            show_history: false,
+            recovery: Recovery::default(),
+            merge: None,
+            export_options: ExportOptions::default(),
             current_modal_dialog: ModalWindow::default(),
-            version: FileVersion::default(),
         };
 
         grph.update_titlebar(&cc.egui_ctx);
@@ -1099,6 +1164,9 @@ impl PulseGraphEditor{
         let bindings = load_bindings(std::path::Path::new("bindings.json"));
         match bindings {
             Ok(bindings) => {
+                for tab in grph.tabs.iter_mut() {
+                    tab.full_state.user_state.bindings = bindings.clone();
+                }
                 grph.user_state_mut().bindings = bindings;
             }
             Err(e) => {
@@ -1110,30 +1178,63 @@ impl PulseGraphEditor{
                     .show();
             }
         };
-        grph.full_state.verify_compat();
+        grph.recovery.find_sessions();
+        grph.open_recovery_prompt();
         grph
     }
 
+    // opens the file in a new tab, unless the current one is still empty
+    fn open_in_tab(&mut self, filepath: &PathBuf, ctx: &egui::Context) -> anyhow::Result<()> {
+        // already open, just switch to it
+        let open_tab = (0..self.tabs.len()).find(|idx| {
+            let full_state = if *idx == self.active_tab { &self.full_state } else { &self.tabs[*idx].full_state };
+            full_state.user_state.save_file_path.as_ref() == Some(filepath)
+        });
+        if let Some(idx) = open_tab {
+            self.switch_tab(idx, ctx);
+            return Ok(());
+        }
+        let created_tab = !self.is_active_tab_blank();
+        if created_tab {
+            self.new_tab(ctx);
+        }
+        let res = self.handle_open_file(filepath);
+        if res.is_err() && created_tab {
+            self.close_tab(self.active_tab, ctx);
+        }
+        res
+    }
+
     fn handle_open_file(&mut self, filepath: &PathBuf) -> anyhow::Result<()> {
-        if let Err(e) = self.load_graph(filepath) {
+        let upgrade = match self.load_graph(filepath) {
+            Ok(upgrade) => upgrade,
+            Err(e) => {
+                MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Load failed")
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .set_description(e.to_string())
+                    .show();
+                return Err(e);
+            }
+        };
+        // files that only needed their version bumped don't need to bother anyone
+        if let Some(upgrade) = upgrade.filter(|upgrade| !upgrade.changes.is_empty()) {
+            let changes: Vec<String> = upgrade.changes.iter().map(|change| format!("- {change}")).collect();
             MessageDialog::new()
-                .set_level(rfd::MessageLevel::Error)
-                .set_title("Load failed")
+                .set_level(rfd::MessageLevel::Info)
+                .set_title("File upgraded")
                 .set_buttons(rfd::MessageButtons::Ok)
-                .set_description(e.to_string())
+                .set_description(format!(
+                    "'{}' was saved by an older version of the editor (file format version {}) and has been upgraded:\n{}\n\nOnce saved, older versions of the editor may not be able to open it.",
+                    self.tab_title(self.active_tab),
+                    upgrade.from,
+                    changes.join("\n"),
+                ))
                 .show();
-            return Err(e);
         }
         Ok(())
     }
-
-    fn get_new_undoer() -> Undoer<FullGraphState> {
-        Undoer::with_settings(Settings {
-            max_undos: 100,
-            stable_time: 0.2,
-            auto_save_interval: 30.0,
-        })
-    }
 }
 
 // assigns proper default values based on the text buffer, and updates the graph node types (DataTypes)
@@ -1208,6 +1309,8 @@ pub fn update_variable_data(var: &mut PulseVariable) {
 
 #[cfg(feature = "persistence")]
 const PERSISTENCE_KEY: &str = "egui_node_graph";
+#[cfg(feature = "persistence")]
+const TABS_PERSISTENCE_KEY: &str = "open_tabs";
 
 pub fn has_polymorhpic_dependent_return(
     template: &PulseNodeTemplate,
@@ -1234,28 +1337,44 @@ impl eframe::App for PulseGraphEditor {
     /// If the persistence function is enabled,
     /// Called by the frame work to save state before shutdown.
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
-        eframe::set_value(storage, PERSISTENCE_KEY, &self.full_state);
+        eframe::set_value(storage, TABS_PERSISTENCE_KEY, &self.persisted_tabs());
     }
     /// Called each time the UI needs repainting, which may be many times per second.
     /// Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
-    fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
+        // the panic hook has already run by the time we get here, all that's left is saving what we can
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.update_editor(ctx, frame)));
+        if let Err(payload) = result {
+            self.emergency_save();
+            std::panic::resume_unwind(payload);
+        }
+    }
+    /// Called once the window is closed, after `save`.
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // nothing to recover after a clean exit
+        self.recovery.remove_session_dir();
+    }
+}
+
+impl PulseGraphEditor {
+    fn update_editor(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
         ctx.set_visuals(egui::Visuals::dark());
         ctx.style_mut(|s| s.interaction.selectable_labels = false);
-        self.undoer.feed_state(
-            std::time::SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("time went backwards")
-                .as_secs_f64(), 
-            &self.full_state
-        );
+        let (time, pointer_down) = ctx.input(|i| (i.time, i.pointer.any_down()));
+        self.history.update(&self.full_state, time, pointer_down);
+        if self.history.is_pending() {
+            // so the edit gets recorded even if nothing else happens
+            ctx.request_repaint_after(std::time::Duration::from_millis(250));
+        }
+        self.autosave(ctx, time);
         if self.current_modal_dialog.is_open {
             let modal = Modal::new(Id::new("MainModal")).show(ctx, |ui| {
                 match self.current_modal_dialog.window_type {
-                    ModalWindowType::ConfirmSave => {
+                    ModalWindowType::ConfirmCloseTab(idx) => {
                         ui.set_width(400.0);
                         
-                        ui.label(RichText::new("Create new graph").size(24.0));
-                        ui.label(RichText::new("Are you sure you want to create a new graph? Unsaved changes will be lost.").size(16.0));
+                        ui.label(RichText::new("Close graph").size(24.0));
+                        ui.label(RichText::new(format!("'{}' has unsaved changes. Are you sure you want to close it? Unsaved changes will be lost.", self.tab_title(idx))).size(16.0));
 
                         egui::Sides::new().show(
                             ui,
@@ -1267,11 +1386,47 @@ impl eframe::App for PulseGraphEditor {
                                 ui.close();
                             }
                             if btn_yes.clicked() {
-                                self.new_graph(ctx);
+                                self.close_tab(idx, ctx);
                                 ui.close();
                             }
                         });
                     }
+                    ModalWindowType::ConfirmDeleteSymbol(symbol, idx) => {
+                        ui.set_width(400.0);
+                        let name = match symbol {
+                            GraphSymbol::Variable => self.user_state().variables.get(idx).map(|var| var.name.clone()),
+                            GraphSymbol::PublicOutput => self.user_state().public_outputs.get(idx).map(|output| output.name.clone()),
+                        }.unwrap_or_default();
+                        let usages = self.full_state.find_usages(symbol, &name).len();
+
+                        ui.label(RichText::new(format!("Delete {}", symbol.ui_name().to_lowercase())).size(24.0));
+                        ui.label(RichText::new(format!("'{name}' is still used by {usages} node(s). Are you sure you want to delete it? These nodes will not compile until they are changed.")).size(16.0));
+
+                        egui::Sides::new().show(
+                            ui,
+                |_ui| {},
+                |ui| {
+                            let btn_no = ui.add_sized([120., 30.], Button::new(RichText::new("No").size(18.0)));
+                            let btn_yes = ui.add_sized([120., 30.], Button::new(RichText::new("Yes").size(18.0)));
+                            if btn_no.clicked() {
+                                ui.close();
+                            }
+                            if btn_yes.clicked() {
+                                self.delete_symbol(symbol, idx);
+                                ui.close();
+                            }
+                        });
+                    }
+                    ModalWindowType::RecoverSessions => {
+                        if self.recovery_ui(ui) {
+                            ui.close();
+                        }
+                    }
+                    ModalWindowType::ExportImage => {
+                        if self.export_ui(ui) {
+                            ui.close();
+                        }
+                    }
                     ModalWindowType::None => {}
                 }
             });
@@ -1283,8 +1438,20 @@ impl eframe::App for PulseGraphEditor {
             egui::MenuBar::new().ui(ui, |ui: &mut egui::Ui| {
                 if ui.button("Compile").clicked()
                     || ctx.input(|i| i.modifiers.command && i.key_pressed(egui::Key::R)) {
-                    if let Err(e) =
-                        compile_graph(&self.state().graph, self.user_state(), 
+                    // pick up any changes made to the libraries since they were loaded
+                    let library_errors = self.user_state_mut().reload_libraries();
+                    // the history doesn't see libraries changing on disk
+                    self.full_state.validate();
+                    let root = self.full_state.root_state();
+                    if let Some(e) = library_errors.first() {
+                        MessageDialog::new()
+                            .set_level(rfd::MessageLevel::Error)
+                            .set_title("Compile failed")
+                            .set_buttons(rfd::MessageButtons::Ok)
+                            .set_description(format!("{e:#}"))
+                            .show();
+                    } else if let Err(e) =
+                        compile_graph(&root.state.graph, &root.user_state, 
                             #[cfg(feature = "nongame_asset_build")]&self.editor_config)
                     {
                         MessageDialog::new()
@@ -1340,34 +1507,50 @@ impl eframe::App for PulseGraphEditor {
                         .add_filter("Pulse Graph Editor State", &["ron"])
                         .pick_file();
                     if let Some(filepath) = &chosen_file {
-                        if self.handle_open_file(filepath).is_ok() {
+                        if self.open_in_tab(filepath, ctx).is_ok() {
                             self.update_titlebar(ctx);
                         }
                     }
                 }
                 let mut should_update_title = false;
-                ctx.input(|i| {
-                    if let Some(dropped_file) = i.raw.dropped_files.first() {
-                        if let Some(path) = &dropped_file.path {
-                            if self.handle_open_file(path).is_ok() {
-                                // defer title update after handling the DND event, otherwise we freeze due to Windows OLE bug.
-                                should_update_title = true;
-                            }
-                        }
-                    }
+                let dropped_file = ctx.input(|i| {
+                    i.raw.dropped_files.first().and_then(|dropped_file| dropped_file.path.clone())
                 });
+                if let Some(path) = &dropped_file {
+                    if self.open_in_tab(path, ctx).is_ok() {
+                        // defer title update after handling the DND event, otherwise we freeze due to Windows OLE bug.
+                        should_update_title = true;
+                    }
+                }
                 if should_update_title {
                     self.update_titlebar(ctx);
                 }
-                if ui.button("New").clicked()
-                    && !self.state().graph.nodes.is_empty() {
+                if ui.button("New").clicked() {
+                    self.new_tab(ctx);
+                }
+                ui.menu_button("Compare", |ui| self.compare_menu_ui(ui));
+                ui.menu_button("Export", |ui| {
+                    if ui.button("Image...").clicked() {
                         self.current_modal_dialog.is_open = true;
-                        self.current_modal_dialog.window_type = ModalWindowType::ConfirmSave;
+                        self.current_modal_dialog.window_type = ModalWindowType::ExportImage;
+                    }
+                    if ui.button("Interface document...").on_hover_text("Inputs, outputs and events of the graph, as Markdown").clicked() {
+                        self.export_interface();
+                    }
+                    ui.separator();
+                    if ui.button("FGD snippet...").on_hover_text("Lets Hammer know the inputs and outputs of the graph").clicked() {
+                        self.export_fgd(false);
                     }
+                    if ui.button("Add to FGD file...").on_hover_text("Adds the inputs and outputs of the graph to an existing FGD, or updates them").clicked() {
+                        self.export_fgd(true);
+                    }
+                });
 
+                let undo_hover = self.history.undo_label().map_or_else(|| "Undo".to_string(), |label| format!("Undo {label}"));
+                let redo_hover = self.history.redo_label().map_or_else(|| "Redo".to_string(), |label| format!("Redo {label}"));
                 if ui.add_enabled(
-                    self.undoer.has_undo(&self.full_state), egui::Button::new("⟲")
-                    ).clicked() ||
+                    self.history.has_undo(), egui::Button::new("⟲")
+                    ).on_hover_text(undo_hover).clicked() ||
                     ctx.input(|i| {
                         i.modifiers.command && i.key_pressed(egui::Key::Z)
                     })
@@ -1375,8 +1558,8 @@ impl eframe::App for PulseGraphEditor {
                     self.do_undo();
                 }
                 else if ui.add_enabled(
-                    self.undoer.has_redo(&self.full_state), egui::Button::new("⟳")
-                    ).clicked() ||
+                    self.history.has_redo(), egui::Button::new("⟳")
+                    ).on_hover_text(redo_hover).clicked() ||
                     ctx.input(|i| {
                         i.modifiers.command && i.key_pressed(egui::Key::Y)
                     })
@@ -1394,6 +1577,54 @@ impl eframe::App for PulseGraphEditor {
                     }
                     self.state_mut().selected_nodes = new_nodes;
                 }
+                if ui.add_enabled(
+                    !self.state().selected_nodes.is_empty(), egui::Button::new("Collapse to macro")
+                    ).on_hover_text("Ctrl+G").clicked() ||
+                    (!ctx.wants_keyboard_input()
+                        && ctx.input(|i| i.modifiers.command && i.key_pressed(egui::Key::G)))
+                {
+                    let selected_nodes = self.state().selected_nodes.to_vec();
+                    match self.full_state.collapse_to_macro(&selected_nodes) {
+                        Ok(_) => self.commit_history(Some("Collapse to macro")),
+                        Err(e) => {
+                            MessageDialog::new()
+                                .set_level(rfd::MessageLevel::Error)
+                                .set_title("Failed to create macro")
+                                .set_buttons(rfd::MessageButtons::Ok)
+                                .set_description(e.to_string())
+                                .show();
+                        }
+                    }
+                }
+                if ui.button("Auto layout")
+                    .on_hover_text("Arrange the selected nodes, or the whole graph if nothing is selected (Ctrl+L)")
+                    .clicked() ||
+                    (!ctx.wants_keyboard_input()
+                        && ctx.input(|i| i.modifiers.command && i.key_pressed(egui::Key::L)))
+                {
+                    let selected_nodes = self.state().selected_nodes.to_vec();
+                    self.full_state.auto_layout(&selected_nodes);
+                    self.commit_history(Some("Auto layout"));
+                }
+                if ui.button("Add frame").on_hover_text("Group the selected nodes in a frame").clicked() {
+                    let selected_nodes = self.state().selected_nodes.to_vec();
+                    // without a selection, put it in the top left corner of the view
+                    let fallback_position = (egui::vec2(50.0, 50.0) - self.state().pan_zoom.pan).to_pos2();
+                    self.state_mut().add_group_frame("Group", &selected_nodes, fallback_position);
+                    self.commit_history(Some("Add frame"));
+                }
+                ui.checkbox(&mut self.state_mut().show_minimap, "Minimap");
+                let problem_count = self.problem_count();
+                ui.toggle_value(&mut self.show_problems, format!("Problems ({problem_count})"));
+                ui.toggle_value(&mut self.show_history, "History");
+                if let Some(macro_def) = self.full_state.current_macro_mut() {
+                    ui.separator();
+                    ui.label("Editing macro:");
+                    ui.add(egui::TextEdit::singleline(&mut macro_def.name).desired_width(120.0));
+                    if ui.button("⬅ Back").clicked() {
+                        self.full_state.exit_macro();
+                    }
+                }
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
                     if ui.button("Check for updates").clicked() {
                         thread::spawn(move || {
@@ -1411,9 +1642,47 @@ impl eframe::App for PulseGraphEditor {
                 });
             });
         });
+        egui::TopBottomPanel::top("tabs").show(ctx, |ui| {
+            self.tabs_ui(ui);
+        });
         let mut output_scheduled_for_deletion: usize = usize::MAX; // we can get away with just one reference (it's not like the user can click more than one at once)
         let mut variable_scheduled_for_deletion: usize = usize::MAX;
         let mut output_node_updates = vec![];
+        // (kind, old name, new name) of the variables and outputs renamed this frame
+        let mut symbol_renames: Vec<(GraphSymbol, String, String)> = vec![];
+        let mut jump_to_usage = None;
+        if ctx.input(|i| i.modifiers.command && i.key_pressed(egui::Key::F)) {
+            self.open_search();
+        }
+        if self.search.is_some() {
+            egui::SidePanel::right("search_panel").show(ctx, |ui| {
+                self.search_ui(ui);
+            });
+        }
+        if self.user_state().diff.is_some() {
+            egui::SidePanel::right("diff_panel").show(ctx, |ui| {
+                self.diff_ui(ui);
+            });
+        }
+        if self.merge.is_some() {
+            egui::SidePanel::right("merge_panel").show(ctx, |ui| {
+                self.merge_ui(ui);
+            });
+        }
+        if self.show_history {
+            egui::SidePanel::right("history_panel").show(ctx, |ui| {
+                self.history_ui(ui);
+            });
+        }
+        // after switching tabs, so the results always belong to the graph being drawn
+        if self.history.take_changed() {
+            self.full_state.validate();
+        }
+        if self.show_problems {
+            egui::TopBottomPanel::bottom("problems_panel").resizable(true).show(ctx, |ui| {
+                self.problems_ui(ui);
+            });
+        }
         egui::SidePanel::left("left_panel").show(ctx, |ui| {
             egui::CollapsingHeader::new("Advanced")
                 .default_open(false)
@@ -1427,6 +1696,58 @@ impl eframe::App for PulseGraphEditor {
                         ui.text_edit_singleline(&mut self.user_state_mut().graph_subtype);
                     });
                 });
+            egui::CollapsingHeader::new("Libraries")
+                .default_open(false)
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("Import library").clicked() {
+                            if let Some(path) = FileDialog::new()
+                                .add_filter("Pulse Graph Editor State", &["ron"])
+                                .pick_file()
+                            {
+                                if let Err(e) = self.user_state_mut().add_library(&path) {
+                                    MessageDialog::new()
+                                        .set_level(rfd::MessageLevel::Error)
+                                        .set_title("Failed to import library")
+                                        .set_buttons(rfd::MessageButtons::Ok)
+                                        .set_description(format!("{e:#}"))
+                                        .show();
+                                }
+                            }
+                        }
+                        if ui.button("Reload").clicked() {
+                            let errors = self.user_state_mut().reload_libraries();
+                            self.full_state.validate();
+                            if !errors.is_empty() {
+                                MessageDialog::new()
+                                    .set_level(rfd::MessageLevel::Error)
+                                    .set_title("Failed to reload libraries")
+                                    .set_buttons(rfd::MessageButtons::Ok)
+                                    .set_description(errors.iter().map(|e| format!("{e:#}")).collect::<Vec<_>>().join("\n"))
+                                    .show();
+                            }
+                        }
+                    });
+                    let mut library_to_remove = None;
+                    for import in self.user_state_mut().libraries.iter_mut() {
+                        ui.horizontal(|ui| {
+                            if ui.button("X").clicked() {
+                                library_to_remove = Some(import.id);
+                            }
+                            ui.add(egui::TextEdit::singleline(&mut import.name).desired_width(100.0))
+                                .on_hover_text(import.path.display().to_string());
+                        });
+                    }
+                    if let Some(id) = library_to_remove {
+                        self.user_state_mut().remove_library(id);
+                    }
+                });
+            let output_usages: Vec<Vec<SymbolUsage>> = self.user_state().public_outputs.iter()
+                .map(|output| self.full_state.find_usages(GraphSymbol::PublicOutput, &output.name))
+                .collect();
+            let variable_usages: Vec<Vec<SymbolUsage>> = self.user_state().variables.iter()
+                .map(|var| self.full_state.find_usages(GraphSymbol::Variable, &var.name))
+                .collect();
             egui::ScrollArea::vertical().show(ui, |ui| {
                 ui.label("Outputs:");
                 if ui.button("Add output").clicked() {
@@ -1439,6 +1760,7 @@ impl eframe::App for PulseGraphEditor {
                         typ_old: PulseValueType::PVAL_INT(None),
                     });
                 }
+                let output_names: Vec<String> = self.user_state().public_outputs.iter().map(|output| output.name.clone()).collect();
                 for (idx, outputdef) in self.full_state.user_state.public_outputs.iter_mut().enumerate() {
                     ui.add_space(4.0);
                     egui::Frame::default()
@@ -1450,11 +1772,13 @@ impl eframe::App for PulseGraphEditor {
                             if ui.button("X").clicked() {
                                 output_scheduled_for_deletion = idx;
                             }
-                            ui.add(egui::TextEdit::singleline(&mut outputdef.name)
-                                .font(TextStyle::Heading)
-                                .hint_text("Output name")
-                            );
+                            if let Some((old, new)) = Self::symbol_name_edit(ui, &mut outputdef.name, "Output name", |new| {
+                                output_names.iter().enumerate().any(|(other, name)| other != idx && name == new)
+                            }) {
+                                symbol_renames.push((GraphSymbol::PublicOutput, old, new));
+                            }
                         });
+                        usages_ui(ui, ("output_usages", idx), &output_usages[idx], &mut jump_to_usage);
                         ui.horizontal(|ui| {
                             ui.label("Param type");
                             ComboBox::from_id_salt(format!("output{idx}"))
@@ -1506,6 +1830,7 @@ impl eframe::App for PulseGraphEditor {
                         default_value_buffer: String::default(),
                     });
                 }
+                let variable_names: Vec<String> = self.user_state().variables.iter().map(|var| var.name.clone()).collect();
                 for (idx, var) in self.user_state_mut().variables.iter_mut().enumerate() {
                     ui.add_space(4.0);
                     egui::Frame::default()
@@ -1517,11 +1842,13 @@ impl eframe::App for PulseGraphEditor {
                             if ui.button("X").clicked() {
                                 variable_scheduled_for_deletion = idx;
                             }
-                            ui.add(egui::TextEdit::singleline(&mut var.name)
-                                .font(TextStyle::Heading)
-                                .hint_text("Variable name")
-                            );
+                            if let Some((old, new)) = Self::symbol_name_edit(ui, &mut var.name, "Variable name", |new| {
+                                variable_names.iter().enumerate().any(|(other, name)| other != idx && name == new)
+                            }) {
+                                symbol_renames.push((GraphSymbol::Variable, old, new));
+                            }
                         });
+                        usages_ui(ui, ("variable_usages", idx), &variable_usages[idx], &mut jump_to_usage);
                         ui.horizontal(|ui| {
                             ui.label("Param type");
                             ComboBox::from_id_salt(format!("var{idx}"))
@@ -1608,6 +1935,13 @@ impl eframe::App for PulseGraphEditor {
                                             }
                                         });
                                 }
+                                PulseValueType::PVAL_SCHEMA_ENUM(enum_type) => {
+                                    let mut value = SchemaEnumValue::from_str_typed(enum_type, &var.default_value_buffer)
+                                        .unwrap_or_else(|| SchemaEnumValue::default_from_type(enum_type));
+                                    if impls::schema_enum_value_widget(ui, format!("var{idx}_enum"), enum_type, &mut value) {
+                                        var.default_value_buffer = value.to_str().into_owned();
+                                    }
+                                }
                                 PulseValueType::DOMAIN_ENTITY_NAME 
                                 | PulseValueType::PVAL_SNDEVT_GUID(_)
                                 | PulseValueType::PVAL_TRANSFORM(_)
@@ -1625,14 +1959,16 @@ impl eframe::App for PulseGraphEditor {
             });
         });
         if output_scheduled_for_deletion != usize::MAX {
-            self.user_state_mut()
-                .public_outputs
-                .remove(output_scheduled_for_deletion);
+            self.request_delete_symbol(GraphSymbol::PublicOutput, output_scheduled_for_deletion);
         }
         if variable_scheduled_for_deletion != usize::MAX {
-            self.user_state_mut()
-                .variables
-                .remove(variable_scheduled_for_deletion);
+            self.request_delete_symbol(GraphSymbol::Variable, variable_scheduled_for_deletion);
+        }
+        for (symbol, old, new) in symbol_renames {
+            self.full_state.rename_symbol(symbol, &old, &new);
+        }
+        if let Some(node_id) = jump_to_usage {
+            self.jump_to_node(node_id);
         }
 
         let mut prepended_responses: Vec<NodeResponse<PulseGraphResponse, PulseNodeData>> = vec![];
@@ -1642,20 +1978,60 @@ impl eframe::App for PulseGraphEditor {
                 prepended_responses.push(NodeResponse::DeleteNodeUi(*node_id));
             }
         }
+        // egui turns Ctrl+C/X/V into these events, the text widgets handle them on their own
+        let mut paste_text = None;
+        if !ctx.wants_keyboard_input() {
+            let events = ctx.input(|i| i.events.clone());
+            for event in events {
+                match event {
+                    egui::Event::Copy | egui::Event::Cut => {
+                        if let Err(e) = self.copy_selected_nodes(ctx) {
+                            MessageDialog::new()
+                                .set_level(rfd::MessageLevel::Error)
+                                .set_title("Copy failed")
+                                .set_buttons(rfd::MessageButtons::Ok)
+                                .set_description(e.to_string())
+                                .show();
+                        } else if event == egui::Event::Cut {
+                            for node_id in self.state().selected_nodes.iter() {
+                                prepended_responses.push(NodeResponse::DeleteNodeUi(*node_id));
+                            }
+                        }
+                    }
+                    egui::Event::Paste(text) => paste_text = Some(text),
+                    _ => {}
+                }
+            }
+        }
 
         let graph_response = egui::CentralPanel::default()
             .show(ctx, |ui| {
+                if let Some(text) = paste_text {
+                    // place the nodes under the cursor, or in the middle of the view if it's outside
+                    let cursor_pos = ctx.pointer_latest_pos()
+                        .filter(|pos| ui.max_rect().contains(*pos))
+                        .unwrap_or(ui.max_rect().center());
+                    let position = cursor_pos - self.state().pan_zoom.pan - ui.max_rect().min.to_vec2();
+                    if let Err(e) = self.paste_nodes(&text, position) {
+                        MessageDialog::new()
+                            .set_level(rfd::MessageLevel::Error)
+                            .set_title("Paste failed")
+                            .set_buttons(rfd::MessageButtons::Ok)
+                            .set_description(e.to_string())
+                            .show();
+                    }
+                }
+                let node_templates = self.node_templates();
                 self.full_state.state.draw_graph_editor(
                     ui,
-                    AllMyNodeTemplates {
-                        game_function_count: self.user_state().bindings.gamefunctions.len()
-                    },
+                    node_templates,
                     &mut self.full_state.user_state,
                     prepended_responses,
                 )
             })
             .inner;
 
+        let mut macro_to_open = None;
         for node_response in graph_response.node_responses {
             // handle all responses generated by the graph ui...
             match node_response {
@@ -1738,6 +2114,10 @@ impl eframe::App for PulseGraphEditor {
                                 println!("[UI] Warning: Failed to update polymorphic output types: {e}");
                             }
                         }
+                        // switch graphs only after all of the responses are handled, they refer to the current one
+                        PulseGraphResponse::OpenMacro(id) => {
+                            macro_to_open = Some(id);
+                        }
                     }
                 }
                 NodeResponse::DeleteNodeFull { node_id, .. } => {
@@ -1753,7 +2133,17 @@ impl eframe::App for PulseGraphEditor {
                             self.update_library_binding_params(&node_id, &binding);
                         }
                     }
-                    self.feed_undo_state();
+                    self.commit_history(None);
+                }
+                NodeResponse::ConnectionDoubleClicked { output, input, position } => {
+                    if let Err(e) = self.insert_reroute(output, input, position) {
+                        println!("[UI] Warning: Failed to insert reroute node: {e}");
+                    }
+                }
+                NodeResponse::IncompatibleConnection { output, input } => {
+                    if let Err(e) = self.connect_with_conversion(output, input) {
+                        println!("[UI] Warning: Failed to insert conversion: {e}");
+                    }
                 }
                 NodeResponse::ConnectEventEnded { output, input: _ , input_hook: _} => {
                     let graph = &self.state().graph;
@@ -1768,6 +2158,16 @@ impl eframe::App for PulseGraphEditor {
         for (nodeid, name) in output_node_updates {
             self.update_output_node_param(nodeid, &name, "param");
         }
+        if let Some(id) = macro_to_open {
+            if let Err(e) = self.full_state.enter_macro(id) {
+                MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Failed to open macro")
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .set_description(e.to_string())
+                    .show();
+            }
+        }
     }
 }
 
@@ -0,0 +1,272 @@
+// Copy/paste of nodes between graphs through the system clipboard.
+// The content is plain RON text with a header line, so it can be recognized when pasting.
+use std::collections::HashMap;
+use eframe::egui;
+use egui_node_graph2::*;
+use serde::{Deserialize, Serialize};
+use super::types::*;
+use super::FullGraphState;
+use crate::pulsetypes::{OutputDefinition, PulseVariable};
+
+const CLIPBOARD_HEADER: &str = "PulseGraphEditor nodes v1\n";
+
+#[derive(Clone)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct ClipboardInput {
+    pub name: String,
+    pub typ: PulseDataType,
+    pub value: PulseGraphValueType,
+    pub kind: InputParamKind,
+    pub shown_inline: bool,
+    // was added by the user (eg. IntSwitch cases), needs to be put back in user_data.added_inputs
+    pub user_added: bool,
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct ClipboardNode {
+    // used to point node references (eg. CallNode) to the pasted copy
+    pub original_id: NodeId,
+    pub label: String,
+    pub user_data: PulseNodeData,
+    pub inputs: Vec<ClipboardInput>,
+    pub outputs: Vec<(String, PulseDataType)>,
+    // position relative to the top-left most node, without zoom applied
+    pub offset: [f32; 2],
+    pub size: [f32; 2],
+    pub exposed_name: Option<String>,
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct ClipboardConnection {
+    pub from_node: usize,
+    pub output: String,
+    pub to_node: usize,
+    pub input: String,
+}
+
+// The graph the nodes were copied from.
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct ClipboardSource {
+    pub graph: GraphInstanceId,
+    pub macro_stack: Vec<MacroId>,
+}
+
+impl ClipboardSource {
+    fn of(full_state: &FullGraphState) -> Self {
+        Self {
+            graph: full_state.user_state().instance_id,
+            macro_stack: full_state.user_state().macro_stack.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct ClipboardContent {
+    // node references to nodes that weren't copied are only kept when pasting into the same graph
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub source: Option<ClipboardSource>,
+    pub nodes: Vec<ClipboardNode>,
+    // only connections between the copied nodes
+    pub connections: Vec<ClipboardConnection>,
+    // definitions referenced by the copied nodes, created on paste if missing in the target graph
+    pub variables: Vec<PulseVariable>,
+    pub public_outputs: Vec<OutputDefinition>,
+}
+
+impl ClipboardContent {
+    pub fn from_nodes(full_state: &FullGraphState, node_ids: &[NodeId]) -> Self {
+        let state = full_state.state();
+        let graph = &state.graph;
+        let zoom = state.pan_zoom.zoom;
+        let node_ids: Vec<NodeId> = node_ids.iter().copied()
+            .filter(|id| graph.nodes.contains_key(*id))
+            .collect();
+        let origin = node_ids.iter()
+            .filter_map(|id| state.node_positions.get(*id))
+            .fold(egui::pos2(f32::MAX, f32::MAX), |acc, pos| acc.min(*pos));
+
+        let mut content = ClipboardContent {
+            source: Some(ClipboardSource::of(full_state)),
+            ..Default::default()
+        };
+        let mut var_names = vec![];
+        let mut output_names = vec![];
+        for node_id in node_ids.iter() {
+            let node = &graph.nodes[*node_id];
+            let inputs = node.inputs.iter().map(|(name, input_id)| {
+                let param = graph.get_input(*input_id);
+                match &param.value {
+                    PulseGraphValueType::InternalVariableName { value, .. } => var_names.push(value.clone()),
+                    PulseGraphValueType::InternalOutputName { value, .. } => output_names.push(value.clone()),
+                    _ => {}
+                }
+                ClipboardInput {
+                    name: name.clone(),
+                    typ: param.typ.clone(),
+                    value: param.value.clone(),
+                    kind: param.kind,
+                    shown_inline: param.shown_inline,
+                    user_added: node.user_data.added_inputs.contains(input_id),
+                }
+            }).collect();
+            let outputs = node.outputs.iter()
+                .map(|(name, output_id)| (name.clone(), graph.get_output(*output_id).typ.clone()))
+                .collect();
+            let pos = state.node_positions.get(*node_id).copied().unwrap_or(origin);
+            let size = state.node_sizes.get(*node_id).copied().unwrap_or(egui::Vec2::ZERO);
+            let offset = (pos - origin) / zoom;
+            content.nodes.push(ClipboardNode {
+                original_id: *node_id,
+                label: node.label.clone(),
+                user_data: node.user_data.clone(),
+                inputs,
+                outputs,
+                offset: [offset.x, offset.y],
+                size: [size.x, size.y],
+                exposed_name: full_state.user_state().exposed_nodes.get(*node_id).cloned(),
+            });
+        }
+        for (input_id, output_ids) in graph.iter_connection_groups() {
+            let input = graph.get_input(input_id);
+            let Some(to_node) = node_ids.iter().position(|id| *id == input.node) else {
+                continue;
+            };
+            let Some(input_name) = find_input_name(&graph.nodes[input.node], input_id) else {
+                continue;
+            };
+            for output_id in output_ids {
+                let output = graph.get_output(output_id);
+                let Some(from_node) = node_ids.iter().position(|id| *id == output.node) else {
+                    continue;
+                };
+                let Some(output_name) = find_output_name(&graph.nodes[output.node], output_id) else {
+                    continue;
+                };
+                content.connections.push(ClipboardConnection {
+                    from_node,
+                    output: output_name,
+                    to_node,
+                    input: input_name.clone(),
+                });
+            }
+        }
+        let user_state = full_state.user_state();
+        content.variables = user_state.variables.iter()
+            .filter(|var| var_names.contains(&var.name))
+            .cloned()
+            .collect();
+        content.public_outputs = user_state.public_outputs.iter()
+            .filter(|out| output_names.contains(&out.name))
+            .cloned()
+            .collect();
+        content
+    }
+
+    pub fn to_clipboard_string(&self) -> anyhow::Result<String> {
+        Ok(format!("{CLIPBOARD_HEADER}{}", ron::ser::to_string(self)?))
+    }
+
+    // returns None if the text doesn't come from the editor
+    pub fn from_clipboard_string(text: &str) -> Option<anyhow::Result<Self>> {
+        let body = text.strip_prefix(CLIPBOARD_HEADER)?;
+        Some(ron::from_str(body).map_err(anyhow::Error::from))
+    }
+
+    // Recreates the nodes in the graph, with the top-left most node placed at `position`.
+    // Returns the ids of the new nodes.
+    pub fn paste_into(&self, full_state: &mut FullGraphState, position: egui::Pos2) -> Vec<NodeId> {
+        let zoom = full_state.state().pan_zoom.zoom;
+        let mut new_nodes = Vec::with_capacity(self.nodes.len());
+        let mut remapped_nodes: HashMap<NodeId, NodeId> = HashMap::new();
+        for clip_node in self.nodes.iter() {
+            let mut user_data = clip_node.user_data.clone();
+            user_data.added_inputs.clear();
+            user_data.uid = full_state.user_state_mut().new_node_uid();
+            let mut added_inputs = vec![];
+            let new_node = full_state.state_mut().graph.add_node(
+                clip_node.label.clone(),
+                user_data,
+                |graph, node_id| {
+                    for input in clip_node.inputs.iter() {
+                        let input_id = graph.add_input_param(
+                            node_id,
+                            input.name.clone(),
+                            input.typ.clone(),
+                            input.value.clone(),
+                            input.kind,
+                            input.shown_inline,
+                        );
+                        if input.user_added {
+                            added_inputs.push(input_id);
+                        }
+                    }
+                    for (name, typ) in clip_node.outputs.iter() {
+                        graph.add_output_param(node_id, name.clone(), typ.clone());
+                    }
+                },
+            );
+            full_state.state_mut().graph.nodes[new_node].user_data.added_inputs = added_inputs;
+            let offset = egui::vec2(clip_node.offset[0], clip_node.offset[1]) * zoom;
+            full_state.state_mut().node_positions.insert(new_node, position + offset);
+            full_state.state_mut().node_sizes.insert(new_node, egui::vec2(clip_node.size[0], clip_node.size[1]));
+            full_state.state_mut().node_order.push(new_node);
+            if let Some(name) = &clip_node.exposed_name {
+                full_state.user_state_mut().exposed_nodes.insert(new_node, name.clone());
+            }
+            remapped_nodes.insert(clip_node.original_id, new_node);
+            new_nodes.push(new_node);
+        }
+
+        for conn in self.connections.iter() {
+            let (Some(from), Some(to)) = (new_nodes.get(conn.from_node), new_nodes.get(conn.to_node)) else {
+                continue;
+            };
+            let graph = &mut full_state.state_mut().graph;
+            let (Ok(output_id), Ok(input_id)) = (graph.nodes[*from].get_output(&conn.output), graph.nodes[*to].get_input(&conn.input)) else {
+                continue;
+            };
+            let pos = graph.connections(input_id).len();
+            graph.add_connection(output_id, input_id, pos);
+        }
+
+        // point node references to the pasted copies. References to other nodes are only valid in the graph
+        // they were copied from, anywhere else the same NodeId could be an unrelated node.
+        let same_graph = self.source.as_ref() == Some(&ClipboardSource::of(full_state));
+        let FullGraphState { state, user_state, .. } = full_state;
+        for node_id in new_nodes.iter() {
+            for input_id in state.graph.nodes[*node_id].input_ids().collect::<Vec<_>>() {
+                if let PulseGraphValueType::NodeChoice { node } = &mut state.graph.get_input_mut(input_id).value {
+                    *node = node.and_then(|target| {
+                        remapped_nodes.get(&target).copied()
+                            .or((same_graph && user_state.exposed_nodes.contains_key(target)).then_some(target))
+                    });
+                }
+            }
+        }
+
+        let user_state = full_state.user_state_mut();
+        for var in self.variables.iter() {
+            if !user_state.variables.iter().any(|v| v.name == var.name) {
+                user_state.variables.push(var.clone());
+            }
+        }
+        for output in self.public_outputs.iter() {
+            if !user_state.public_outputs.iter().any(|o| o.name == output.name) {
+                user_state.public_outputs.push(output.clone());
+            }
+        }
+        new_nodes
+    }
+}
+
+fn find_input_name<N>(node: &Node<N>, input_id: InputId) -> Option<String> {
+    node.inputs.iter().find(|(_, id)| *id == input_id).map(|(name, _)| name.clone())
+}
+
+fn find_output_name<N>(node: &Node<N>, output_id: OutputId) -> Option<String> {
+    node.outputs.iter().find(|(_, id)| *id == output_id).map(|(name, _)| name.clone())
+}
@@ -0,0 +1,99 @@
+// Conversions between port types. When a connection is dropped on a port of a different type that the
+// value can be converted to, the conversion nodes get put in between instead of refusing the connection.
+use eframe::egui;
+use egui_node_graph2::*;
+use crate::typing::PulseValueType;
+use super::types::*;
+use super::PulseGraphEditor;
+
+const CONVERSION_NODE_SIZE: egui::Vec2 = egui::vec2(200.0, 120.0);
+
+struct ConversionStep {
+    template: PulseNodeTemplate,
+    input: &'static str,
+    output: &'static str,
+    // for Cast nodes, the type to cast to
+    cast_to: Option<PulseValueType>,
+}
+
+const STRING_TO_ENTITY_NAME: ConversionStep = ConversionStep {
+    template: PulseNodeTemplate::StringToEntityName,
+    input: "entityName",
+    output: "out",
+    cast_to: None,
+};
+
+const FIND_ENTITY_BY_NAME: ConversionStep = ConversionStep {
+    template: PulseNodeTemplate::FindEntByName,
+    input: "entName",
+    output: "out",
+    cast_to: None,
+};
+
+fn cast(to: PulseValueType) -> ConversionStep {
+    ConversionStep {
+        template: PulseNodeTemplate::Convert,
+        input: "input",
+        output: "out",
+        cast_to: Some(to),
+    }
+}
+
+// The nodes that turn a value of one type into the other, in order. None if there is no legal conversion.
+fn conversion_steps(from: &PulseDataType, to: &PulseDataType) -> Option<Vec<ConversionStep>> {
+    use PulseDataType::*;
+    Some(match (from, to) {
+        (String, EntityName) => vec![STRING_TO_ENTITY_NAME],
+        (EntityName, EHandle) => vec![FIND_ENTITY_BY_NAME],
+        (String, EHandle) => vec![STRING_TO_ENTITY_NAME, FIND_ENTITY_BY_NAME],
+        (Scalar | Bool | Vec2 | Vec3 | Vec3Local | Vec4 | QAngle | Color | EHandle | EntityName | GameTime, String) => {
+            vec![cast(PulseValueType::PVAL_STRING(None))]
+        }
+        (GameTime, Scalar) => vec![cast(PulseValueType::PVAL_FLOAT(None))],
+        (Scalar, GameTime) => vec![cast(PulseValueType::PVAL_GAMETIME(None))],
+        _ => return None,
+    })
+}
+
+impl PulseGraphEditor {
+    // Connects the two ports through conversion nodes, if their types can be converted.
+    // Returns false if they can't, in which case nothing changes.
+    pub(super) fn connect_with_conversion(&mut self, output: OutputId, input: InputId) -> anyhow::Result<bool> {
+        let graph = &self.state().graph;
+        let Some(steps) = conversion_steps(&graph.get_output(output).typ, &graph.get_input(input).typ) else {
+            return Ok(false);
+        };
+        // spread the new nodes out between the two nodes that get connected
+        let state = self.state();
+        let zoom = state.pan_zoom.zoom;
+        let from_node = graph.get_output(output).node;
+        let to_node = graph.get_input(input).node;
+        let from_size = state.node_sizes.get(from_node).copied().unwrap_or(egui::vec2(200.0, 200.0));
+        let start = state.node_positions[from_node] + egui::vec2(from_size.x * zoom, 0.0);
+        let end = state.node_positions[to_node] - egui::vec2(CONVERSION_NODE_SIZE.x * zoom, 0.0);
+
+        let mut previous = output;
+        let step_count = steps.len();
+        for (idx, step) in steps.into_iter().enumerate() {
+            let position = start.lerp(end, (idx + 1) as f32 / (step_count + 1) as f32);
+            let node_id = self.full_state.add_node_at(step.template, position, CONVERSION_NODE_SIZE);
+            if let Some(cast_to) = step.cast_to {
+                let typeto = self.state().graph.nodes[node_id].get_input("typeto")?;
+                self.state_mut().graph.get_input_mut(typeto).value = PulseGraphValueType::Typ { value: cast_to.clone() };
+                self.update_node_inputs_outputs_types(node_id, &"typeto".to_string(), Some(cast_to));
+            }
+            let node = &self.state().graph.nodes[node_id];
+            let (step_input, step_output) = (node.get_input(step.input)?, node.get_output(step.output)?);
+            self.state_mut().graph.add_connection(previous, step_input, 0);
+            previous = step_output;
+        }
+        // full inputs get their connection replaced, like when connecting by hand
+        let graph = &self.state().graph;
+        let max_connections = graph.get_input(input).max_connections.map_or(usize::MAX, |max| max.get() as usize);
+        let connections = graph.connections(input).len();
+        let hook = if connections >= max_connections { 0 } else { connections };
+        self.state_mut().graph.add_connection(previous, input, hook);
+        self.commit_history(Some("Insert conversion"));
+        Ok(true)
+    }
+}
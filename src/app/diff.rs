@@ -0,0 +1,564 @@
+// Comparing two versions of a graph, for reviewing changes. Nodes are matched by their stable ids (see
+// document.rs). The editor shows the newer version with what was removed put back in, and the changes
+// colored on the canvas. `pulseedit diff` prints the same changes as text.
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use eframe::egui::{self, Color32, RichText};
+use rfd::{FileDialog, MessageDialog};
+use crate::pulsetypes::{OutputDefinition, PulseVariable};
+use super::document::*;
+use super::history::History;
+use super::types::*;
+use super::{FullGraphState, PulseGraphEditor};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl ChangeKind {
+    fn sign(&self) -> char {
+        match self {
+            ChangeKind::Added => '+',
+            ChangeKind::Removed => '-',
+            ChangeKind::Modified => '~',
+        }
+    }
+
+    pub fn color(&self) -> Color32 {
+        match self {
+            ChangeKind::Added => Color32::from_rgb(46, 160, 67),
+            ChangeKind::Removed => Color32::from_rgb(190, 50, 50),
+            ChangeKind::Modified => Color32::from_rgb(200, 150, 30),
+        }
+    }
+}
+
+// a node, variable, output or macro that changed
+#[derive(Clone, PartialEq)]
+pub struct Change<K> {
+    pub key: K,
+    pub name: String,
+    pub kind: ChangeKind,
+    // what was modified
+    pub details: Vec<String>,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct ConnectionChange {
+    pub connection: DocumentConnection,
+    pub kind: ChangeKind,
+    pub text: String,
+}
+
+#[derive(Clone, PartialEq, Default)]
+pub struct GraphChanges {
+    pub nodes: Vec<Change<NodeUid>>,
+    pub connections: Vec<ConnectionChange>,
+}
+
+impl GraphChanges {
+    fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.connections.is_empty()
+    }
+}
+
+#[derive(Clone, PartialEq, Default)]
+pub struct GraphDiff {
+    // domain, subtype and libraries
+    pub settings: Vec<String>,
+    pub variables: Vec<Change<String>>,
+    pub public_outputs: Vec<Change<String>>,
+    pub graph: GraphChanges,
+    pub macros: Vec<(Change<MacroId>, GraphChanges)>,
+}
+
+fn node_name(uid: NodeUid, node: &DocumentNode) -> String {
+    format!("{} #{uid}", node.label)
+}
+
+fn value_text(value: &PulseGraphValueType) -> String {
+    match value {
+        PulseGraphValueType::Scalar { value } => value.to_string(),
+        PulseGraphValueType::Bool { value } => value.to_string(),
+        PulseGraphValueType::String { value }
+        | PulseGraphValueType::SoundEventName { value }
+        | PulseGraphValueType::EntityName { value }
+        | PulseGraphValueType::CommentBox { value }
+        | PulseGraphValueType::Resource { value, .. }
+        | PulseGraphValueType::InternalOutputName { value, .. }
+        | PulseGraphValueType::InternalVariableName { value, .. } => format!("\"{value}\""),
+        PulseGraphValueType::Typ { value } => value.to_string(),
+        value => format!("{value:?}"),
+    }
+}
+
+fn node_details(old: &DocumentNode, new: &DocumentNode) -> Vec<String> {
+    let mut details = vec![];
+    if old.template != new.template {
+        details.push(format!("type {:?} -> {:?}", old.template, new.template));
+    }
+    if old.label != new.label {
+        details.push(format!("label \"{}\" -> \"{}\"", old.label, new.label));
+    }
+    for new_input in new.inputs.iter() {
+        let Some(old_input) = old.inputs.iter().find(|input| input.name == new_input.name) else {
+            details.push(format!("input '{}' added", new_input.name));
+            continue;
+        };
+        if old_input.value != new_input.value {
+            details.push(format!("'{}' {} -> {}", new_input.name, value_text(&old_input.value), value_text(&new_input.value)));
+        }
+        if old_input.target != new_input.target {
+            match new_input.target {
+                Some(uid) => details.push(format!("'{}' refers to #{uid}", new_input.name)),
+                None => details.push(format!("'{}' no longer refers to a node", new_input.name)),
+            }
+        }
+        if old_input.typ != new_input.typ || old_input.kind != new_input.kind {
+            details.push(format!("input '{}' changed type", new_input.name));
+        }
+    }
+    for old_input in old.inputs.iter().filter(|old_input| !new.inputs.iter().any(|input| input.name == old_input.name)) {
+        details.push(format!("input '{}' removed", old_input.name));
+    }
+    if old.outputs != new.outputs {
+        details.push("outputs changed".to_string());
+    }
+    if old.input_hint_text != new.input_hint_text || old.custom_output_type != new.custom_output_type {
+        details.push("settings changed".to_string());
+    }
+    if old.position != new.position {
+        details.push("moved".to_string());
+    }
+    if old.size != new.size {
+        details.push("resized".to_string());
+    }
+    details
+}
+
+fn diff_graph(old: &DocumentGraph, new: &DocumentGraph) -> GraphChanges {
+    let mut changes = GraphChanges::default();
+    for (uid, node) in new.nodes.iter() {
+        let (kind, details) = match old.nodes.get(uid) {
+            None => (ChangeKind::Added, vec![]),
+            Some(old_node) => {
+                let mut details = node_details(old_node, node);
+                if old.exposed_nodes.get(uid) != new.exposed_nodes.get(uid) {
+                    details.push("name changed".to_string());
+                }
+                if details.is_empty() {
+                    continue;
+                }
+                (ChangeKind::Modified, details)
+            }
+        };
+        changes.nodes.push(Change { key: *uid, name: node_name(*uid, node), kind, details });
+    }
+    for (uid, node) in old.nodes.iter().filter(|(uid, _)| !new.nodes.contains_key(uid)) {
+        changes.nodes.push(Change { key: *uid, name: node_name(*uid, node), kind: ChangeKind::Removed, details: vec![] });
+    }
+    changes.nodes.sort_by_key(|change| change.key);
+
+    let end_text = |graph: &DocumentGraph, (uid, port): &(NodeUid, String)| {
+        let name = graph.nodes.get(uid).map_or_else(|| format!("#{uid}"), |node| node_name(*uid, node));
+        format!("{name}.{port}")
+    };
+    for (kind, graph, other) in [(ChangeKind::Added, new, old), (ChangeKind::Removed, old, new)] {
+        for connection in graph.connections.iter().filter(|connection| !other.connections.contains(connection)) {
+            changes.connections.push(ConnectionChange {
+                connection: connection.clone(),
+                kind,
+                text: format!("{} -> {}", end_text(graph, &connection.from), end_text(graph, &connection.to)),
+            });
+        }
+    }
+    changes
+}
+
+fn diff_keyed<T: PartialEq, K: PartialEq + Clone>(
+    old: &[T],
+    new: &[T],
+    key: impl Fn(&T) -> K,
+    name: impl Fn(&T) -> String,
+    details: impl Fn(&T, &T) -> Vec<String>,
+) -> Vec<Change<K>> {
+    let mut changes = vec![];
+    for new_item in new.iter() {
+        let kind_details = match old.iter().find(|item| key(item) == key(new_item)) {
+            None => Some((ChangeKind::Added, vec![])),
+            Some(old_item) if old_item != new_item => Some((ChangeKind::Modified, details(old_item, new_item))),
+            Some(_) => None,
+        };
+        if let Some((kind, details)) = kind_details {
+            changes.push(Change { key: key(new_item), name: name(new_item), kind, details });
+        }
+    }
+    for old_item in old.iter().filter(|old_item| !new.iter().any(|item| key(item) == key(old_item))) {
+        changes.push(Change { key: key(old_item), name: name(old_item), kind: ChangeKind::Removed, details: vec![] });
+    }
+    changes
+}
+
+fn variable_details(old: &PulseVariable, new: &PulseVariable) -> Vec<String> {
+    if old.typ_and_default_value == new.typ_and_default_value {
+        return vec![];
+    }
+    // the type carries the default value
+    vec![format!("{:?} -> {:?}", old.typ_and_default_value, new.typ_and_default_value)]
+}
+
+fn output_details(old: &OutputDefinition, new: &OutputDefinition) -> Vec<String> {
+    if old.typ == new.typ {
+        return vec![];
+    }
+    vec![format!("type {} -> {}", old.typ, new.typ)]
+}
+
+pub fn diff_documents(old: &GraphDocument, new: &GraphDocument) -> GraphDiff {
+    let mut settings = vec![];
+    if old.graph_domain != new.graph_domain || old.graph_subtype != new.graph_subtype {
+        settings.push(format!("graph type {}/{} -> {}/{}", old.graph_domain, old.graph_subtype, new.graph_domain, new.graph_subtype));
+    }
+    for change in diff_keyed(&old.libraries, &new.libraries, |lib| lib.id, |lib| lib.name.clone(), |_, _| vec![]) {
+        settings.push(format!("library '{}' {:?}", change.name, change.kind).to_lowercase());
+    }
+    let mut macros = vec![];
+    for change in diff_keyed(&old.macros, &new.macros, |def| def.id, |def| def.name.clone(), |old, new| {
+        let mut details = vec![];
+        if old.name != new.name {
+            details.push(format!("renamed from \"{}\"", old.name));
+        }
+        if old.inputs != new.inputs || old.outputs != new.outputs {
+            details.push("ports changed".to_string());
+        }
+        details
+    }) {
+        let empty = DocumentGraph::default();
+        let old_graph = old.find_macro(change.key).map_or(&empty, |def| &def.graph);
+        let new_graph = new.find_macro(change.key).map_or(&empty, |def| &def.graph);
+        let graph_changes = diff_graph(old_graph, new_graph);
+        // a macro whose only changes are inside of it
+        if change.kind == ChangeKind::Modified && change.details.is_empty() && graph_changes.is_empty() {
+            continue;
+        }
+        macros.push((change, graph_changes));
+    }
+    GraphDiff {
+        settings,
+        variables: diff_keyed(&old.variables, &new.variables, |var| var.name.clone(), |var| var.name.clone(), variable_details),
+        public_outputs: diff_keyed(&old.public_outputs, &new.public_outputs, |output| output.name.clone(), |output| output.name.clone(), output_details),
+        graph: diff_graph(&old.graph, &new.graph),
+        macros,
+    }
+}
+
+fn write_changes<K>(text: &mut String, changes: &[Change<K>]) {
+    for change in changes {
+        let _ = write!(text, "  {} {}", change.kind.sign(), change.name);
+        if !change.details.is_empty() {
+            let _ = write!(text, ": {}", change.details.join(", "));
+        }
+        text.push('\n');
+    }
+}
+
+fn write_graph_changes(text: &mut String, changes: &GraphChanges) {
+    write_changes(text, &changes.nodes);
+    for change in changes.connections.iter() {
+        let _ = writeln!(text, "  {} connection {}", change.kind.sign(), change.text);
+    }
+}
+
+impl GraphDiff {
+    pub fn is_empty(&self) -> bool {
+        self.settings.is_empty() && self.variables.is_empty() && self.public_outputs.is_empty()
+            && self.graph.is_empty() && self.macros.is_empty()
+    }
+
+    pub fn summary(&self) -> String {
+        let mut text = String::new();
+        for setting in self.settings.iter() {
+            let _ = writeln!(text, "~ {setting}");
+        }
+        if !self.variables.is_empty() {
+            text.push_str("Variables:\n");
+            write_changes(&mut text, &self.variables);
+        }
+        if !self.public_outputs.is_empty() {
+            text.push_str("Outputs:\n");
+            write_changes(&mut text, &self.public_outputs);
+        }
+        if !self.graph.is_empty() {
+            text.push_str("Graph:\n");
+            write_graph_changes(&mut text, &self.graph);
+        }
+        for (change, graph_changes) in self.macros.iter() {
+            let _ = write!(text, "{} Macro '{}'", change.kind.sign(), change.name);
+            if !change.details.is_empty() {
+                let _ = write!(text, ": {}", change.details.join(", "));
+            }
+            text.push_str(":\n");
+            write_graph_changes(&mut text, graph_changes);
+        }
+        text
+    }
+
+    fn graph_changes(&self, graph: Option<MacroId>) -> Option<&GraphChanges> {
+        match graph {
+            None => Some(&self.graph),
+            Some(id) => self.macros.iter().find(|(change, _)| change.key == id).map(|(_, changes)| changes),
+        }
+    }
+}
+
+// Puts what was removed from `old` back into `new`, so it can be shown.
+fn show_removed(old: &DocumentGraph, new: &mut DocumentGraph) {
+    for (uid, node) in old.nodes.iter() {
+        new.nodes.entry(*uid).or_insert_with(|| node.clone());
+    }
+    for connection in old.connections.iter() {
+        if new.connections.contains(connection) {
+            continue;
+        }
+        let Some(input) = new.nodes.get(&connection.to.0)
+            .and_then(|node| node.inputs.iter().find(|input| input.name == connection.to.1)) else {
+            continue;
+        };
+        let from_exists = new.nodes.get(&connection.from.0)
+            .is_some_and(|node| node.outputs.iter().any(|(name, _)| *name == connection.from.1));
+        // inputs only have room for so many connections, those that don't fit only show up in the list
+        let count = new.connections.iter().filter(|other| other.to == connection.to).count() as u32;
+        if from_exists && input.max_connections.is_none_or(|max| count < max.get()) {
+            new.connections.push(connection.clone());
+        }
+    }
+    new.sort_connections();
+}
+
+// Loads a graph file as it is in a git revision.
+pub fn git_version(path: &Path, rev: &str) -> anyhow::Result<String> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let file_name = path.file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid path {}", path.display()))?;
+    let output = Command::new("git")
+        .arg("-C").arg(dir)
+        .arg("show").arg(format!("{rev}:./{file_name}"))
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run git: {e}"))?;
+    if !output.status.success() {
+        anyhow::bail!("git show failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+pub fn parse_document(contents: &str) -> anyhow::Result<GraphDocument> {
+    let (full_state, _) = FullGraphState::from_saved(contents)?;
+    Ok(GraphDocument::from_full_state(&full_state))
+}
+
+// Two versions of a graph to open in the editor, see PulseGraphEditor::with_diff.
+pub struct DiffSession {
+    old: GraphDocument,
+    new: GraphDocument,
+    title: String,
+    // libraries are found relative to the graph file, but the diff isn't saved anywhere
+    graph_path: Option<PathBuf>,
+}
+
+impl DiffSession {
+    pub fn new(old: &str, new: &str, title: String, graph_path: Option<PathBuf>) -> anyhow::Result<Self> {
+        Ok(Self { old: parse_document(old)?, new: parse_document(new)?, title, graph_path })
+    }
+
+    pub fn diff(&self) -> GraphDiff {
+        diff_documents(&self.old, &self.new)
+    }
+}
+
+// Kept in the user state of a tab that shows a diff.
+#[derive(Clone, PartialEq)]
+pub struct DiffView {
+    pub title: String,
+    pub changes: GraphDiff,
+}
+
+impl DiffView {
+    pub fn node_change(&self, graph: Option<MacroId>, uid: NodeUid) -> Option<ChangeKind> {
+        self.changes.graph_changes(graph)?.nodes.iter()
+            .find(|change| change.key == uid)
+            .map(|change| change.kind)
+    }
+
+    pub fn connection_change(&self, graph: Option<MacroId>, connection: &DocumentConnection) -> Option<ChangeKind> {
+        self.changes.graph_changes(graph)?.connections.iter()
+            .find(|change| change.connection == *connection)
+            .map(|change| change.kind)
+    }
+}
+
+impl PulseGraphEditor {
+    pub fn with_diff(mut self, session: DiffSession, ctx: &egui::Context) -> Self {
+        if let Err(e) = self.open_diff(session, ctx) {
+            MessageDialog::new()
+                .set_level(rfd::MessageLevel::Error)
+                .set_title("Compare failed")
+                .set_buttons(rfd::MessageButtons::Ok)
+                .set_description(format!("{e:#}"))
+                .show();
+        }
+        self
+    }
+
+    // Opens a diff in a new tab. The tab can't be saved, it's only for looking at the changes.
+    fn open_diff(&mut self, session: DiffSession, ctx: &egui::Context) -> anyhow::Result<()> {
+        let DiffSession { old, mut new, title, graph_path } = session;
+        let changes = diff_documents(&old, &new);
+        show_removed(&old.graph, &mut new.graph);
+        for def in new.macros.iter_mut() {
+            if let Some(old_def) = old.find_macro(def.id) {
+                show_removed(&old_def.graph, &mut def.graph);
+            }
+        }
+        let mut full_state = new.into_full_state()?;
+        full_state.user_state.bindings = self.user_state().bindings.clone();
+        full_state.user_state.save_file_path = graph_path;
+        for e in full_state.user_state.reload_libraries() {
+            println!("[Load] Warning: {e:#}");
+        }
+        full_state.user_state.save_file_path = None;
+        full_state.verify_compat();
+        full_state.user_state.diff = Some(Box::new(DiffView { title, changes }));
+        if !self.is_active_tab_blank() {
+            self.new_tab(ctx);
+        }
+        self.full_state = full_state;
+        self.history = History::saved();
+        self.update_titlebar(ctx);
+        Ok(())
+    }
+
+    fn compare_with(&mut self, old: anyhow::Result<String>, title: String, ctx: &egui::Context) -> anyhow::Result<()> {
+        let old = parse_document(&old?)?;
+        let new = GraphDocument::from_full_state(&self.full_state.root_state());
+        let graph_path = self.user_state().save_file_path.clone();
+        self.open_diff(DiffSession { old, new, title, graph_path }, ctx)
+    }
+
+    pub(super) fn compare_menu_ui(&mut self, ui: &mut egui::Ui) {
+        let file_name = self.user_state().save_file_path.as_ref()
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().to_string());
+        let mut result = Ok(());
+        if ui.button("With file...").on_hover_text("Show what changed from another graph file to this one").clicked() {
+            if let Some(path) = FileDialog::new().add_filter("Pulse Graph Editor State", &["ron"]).pick_file() {
+                let title = format!("{} ↔ {}", path.file_name().unwrap_or_default().to_string_lossy(), file_name.as_deref().unwrap_or("<UNSAVED>"));
+                result = self.compare_with(std::fs::read_to_string(&path).map_err(Into::into), title, ui.ctx());
+            }
+            ui.close();
+        }
+        let saved_path = self.user_state().save_file_path.clone();
+        if ui.add_enabled(saved_path.is_some(), egui::Button::new("With last commit"))
+            .on_hover_text("Show the changes since the last git commit")
+            .on_disabled_hover_text("The graph needs to be saved in a git repository")
+            .clicked() {
+            if let (Some(path), Some(file_name)) = (saved_path, file_name) {
+                result = self.compare_with(git_version(&path, "HEAD"), format!("{file_name} (HEAD ↔ current)"), ui.ctx());
+            }
+            ui.close();
+        }
+        if let Err(e) = result {
+            MessageDialog::new()
+                .set_level(rfd::MessageLevel::Error)
+                .set_title("Compare failed")
+                .set_buttons(rfd::MessageButtons::Ok)
+                .set_description(format!("{e:#}"))
+                .show();
+        }
+    }
+
+    fn show_diff_node(&mut self, graph: Option<MacroId>, uid: NodeUid) {
+        if self.user_state().macro_stack.last().copied() != graph {
+            while self.full_state.exit_macro() {}
+            if let Some(id) = graph {
+                if let Err(e) = self.full_state.enter_macro(id) {
+                    println!("[Diff] Warning: {e:#}");
+                    return;
+                }
+            }
+        }
+        if let Some(node_id) = self.state().graph.nodes.iter().find(|(_, node)| node.user_data.uid == uid).map(|(node_id, _)| node_id) {
+            self.jump_to_node(node_id);
+        }
+    }
+
+    pub(super) fn diff_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(view) = self.user_state().diff.clone() else {
+            return;
+        };
+        let changes = &view.changes;
+        let mut show_node = None;
+        ui.heading("Changes");
+        ui.label(&view.title);
+        if changes.is_empty() {
+            ui.label("No changes");
+        }
+        let change_label = |ui: &mut egui::Ui, kind: ChangeKind, name: &str, details: &[String], link: bool| {
+            let text = RichText::new(format!("{} {name}", kind.sign())).color(kind.color());
+            ui.horizontal_wrapped(|ui| {
+                let clicked = if link { ui.link(text).clicked() } else { ui.label(text); false };
+                if !details.is_empty() {
+                    ui.weak(details.join(", "));
+                }
+                clicked
+            }).inner
+        };
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for setting in changes.settings.iter() {
+                change_label(ui, ChangeKind::Modified, setting, &[], false);
+            }
+            for (heading, symbol_changes) in [("Variables", &changes.variables), ("Outputs", &changes.public_outputs)] {
+                if symbol_changes.is_empty() {
+                    continue;
+                }
+                ui.separator();
+                ui.strong(heading);
+                for change in symbol_changes {
+                    change_label(ui, change.kind, &change.name, &change.details, false);
+                }
+            }
+            let graphs = std::iter::once((None, "Graph".to_string(), &changes.graph))
+                .chain(changes.macros.iter().map(|(change, graph_changes)| {
+                    let mut heading = format!("{} Macro '{}'", change.kind.sign(), change.name);
+                    if !change.details.is_empty() {
+                        heading = format!("{heading}: {}", change.details.join(", "));
+                    }
+                    (Some(change.key), heading, graph_changes)
+                }));
+            for (graph, heading, graph_changes) in graphs {
+                if graph_changes.is_empty() {
+                    continue;
+                }
+                ui.separator();
+                ui.strong(heading);
+                for change in graph_changes.nodes.iter() {
+                    if change_label(ui, change.kind, &change.name, &change.details, true) {
+                        show_node = Some((graph, change.key));
+                    }
+                }
+                for change in graph_changes.connections.iter() {
+                    if change_label(ui, change.kind, &change.text, &[], true) {
+                        show_node = Some((graph, change.connection.to.0));
+                    }
+                }
+            }
+        });
+        if let Some((graph, uid)) = show_node {
+            self.show_diff_node(graph, uid);
+        }
+    }
+}
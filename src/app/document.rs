@@ -0,0 +1,406 @@
+// The layout graphs are saved in. The editor state is full of slotmap keys, has its maps in slot order and
+// keeps the view (pan, zoom, selection, drawing order) next to the graph, so saving it as it is turns small
+// edits into huge diffs. A document only has what makes up the graph: nodes keyed by stable ids, ports
+// referred to by name, everything in a fixed order and positions in the unzoomed graph space.
+//
+// The stable id of a node is the uid kept in its PulseNodeData. Uids come from a counter of the whole document
+// that is saved with it and only ever goes up, so a new node never gets the id of a deleted one.
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::num::NonZeroU32;
+use eframe::egui;
+use egui_node_graph2::*;
+use serde::{Deserialize, Serialize};
+use slotmap::SecondaryMap;
+use crate::pulsetypes::{OutputDefinition, PulseVariable};
+use crate::typing::PulseValueType;
+use super::history::frames_of;
+use super::types::*;
+use super::FullGraphState;
+
+// first format version saved as a document, older files are the editor state as it was
+pub const DOCUMENT_VERSION: u32 = 5;
+
+// 0 is never given out, nodes from graphs saved before uids existed have it until they're migrated
+pub type NodeUid = u32;
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct GraphDocument {
+    pub format_version: u32,
+    pub graph_domain: String,
+    pub graph_subtype: String,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub variables: Vec<PulseVariable>,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub public_outputs: Vec<OutputDefinition>,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub libraries: Vec<LibraryImport>,
+    pub graph: DocumentGraph,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub macros: Vec<DocumentMacro>,
+    // uid of the next new node in any of the graphs, documents saved without it go on from the highest one
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub next_node_uid: NodeUid,
+}
+
+#[derive(Clone, PartialEq, Default)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct DocumentGraph {
+    pub nodes: BTreeMap<NodeUid, DocumentNode>,
+    // sorted by the node and port they go into
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub connections: Vec<DocumentConnection>,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub exposed_nodes: BTreeMap<NodeUid, String>,
+    // unzoomed, in drawing order
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub group_frames: Vec<GroupFrame>,
+}
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct DocumentNode {
+    pub template: PulseNodeTemplate,
+    pub label: String,
+    // unzoomed, see PanZoom::to_graph_space
+    pub position: (f32, f32),
+    pub size: (f32, f32),
+    #[cfg_attr(feature = "persistence", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub input_hint_text: Option<Cow<'static, str>>,
+    #[cfg_attr(feature = "persistence", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub custom_output_type: Option<PulseValueType>,
+    pub inputs: Vec<DocumentInput>,
+    pub outputs: Vec<(String, PulseDataType)>,
+}
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct DocumentInput {
+    pub name: String,
+    pub typ: PulseDataType,
+    // node choices are saved without the node, it's in `target` instead
+    pub value: PulseGraphValueType,
+    pub kind: InputParamKind,
+    pub shown_inline: bool,
+    #[cfg_attr(feature = "persistence", serde(default = "single_connection", skip_serializing_if = "is_single_connection"))]
+    pub max_connections: Option<NonZeroU32>,
+    #[cfg_attr(feature = "persistence", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub target: Option<NodeUid>,
+    // was added by the user (eg. IntSwitch cases), goes in user_data.added_inputs
+    #[cfg_attr(feature = "persistence", serde(default, skip_serializing_if = "is_false"))]
+    pub user_added: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct DocumentConnection {
+    pub from: (NodeUid, String),
+    pub to: (NodeUid, String),
+}
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct DocumentMacro {
+    pub id: MacroId,
+    pub name: String,
+    pub inputs: Vec<MacroPort>,
+    pub outputs: Vec<MacroPort>,
+    pub graph: DocumentGraph,
+}
+
+fn single_connection() -> Option<NonZeroU32> {
+    NonZeroU32::new(1)
+}
+
+fn is_single_connection(max_connections: &Option<NonZeroU32>) -> bool {
+    *max_connections == single_connection()
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+// float noise from zooming in and out shouldn't show up as changes
+fn round(value: f32) -> f32 {
+    (value * 10.0).round() / 10.0
+}
+
+impl DocumentGraph {
+    pub fn from_state(state: &MyEditorState, exposed_nodes: &SecondaryMap<NodeId, String>) -> Self {
+        let graph = &state.graph;
+        let mut document = DocumentGraph::default();
+        let node_uid = |node_id: NodeId| graph.nodes.get(node_id).map(|node| node.user_data.uid);
+        let mut node_ids: Vec<NodeId> = graph.nodes.keys().collect();
+        node_ids.sort_by_key(|node_id| graph.nodes[*node_id].user_data.uid);
+        for node_id in node_ids {
+            let node = &graph.nodes[node_id];
+            let uid = node.user_data.uid;
+            let inputs = node.inputs.iter().map(|(name, input_id)| {
+                let input = graph.get_input(*input_id);
+                let (value, target) = match &input.value {
+                    PulseGraphValueType::NodeChoice { node } => {
+                        (PulseGraphValueType::NodeChoice { node: None }, node.and_then(node_uid))
+                    }
+                    value => (value.clone(), None),
+                };
+                DocumentInput {
+                    name: name.clone(),
+                    typ: input.typ.clone(),
+                    value,
+                    kind: input.kind,
+                    shown_inline: input.shown_inline,
+                    max_connections: input.max_connections,
+                    target,
+                    user_added: node.user_data.added_inputs.contains(input_id),
+                }
+            }).collect();
+            let outputs = node.outputs.iter()
+                .map(|(name, output_id)| (name.clone(), graph.get_output(*output_id).typ.clone()))
+                .collect();
+            let position = state.node_positions.get(node_id)
+                .map_or(egui::Pos2::ZERO, |pos| state.pan_zoom.to_graph_space(*pos));
+            let size = state.node_sizes.get(node_id).copied().unwrap_or(egui::vec2(200.0, 200.0));
+            document.nodes.insert(uid, DocumentNode {
+                template: node.user_data.template,
+                label: node.label.clone(),
+                position: (round(position.x), round(position.y)),
+                size: (round(size.x), round(size.y)),
+                input_hint_text: node.user_data.input_hint_text.clone(),
+                custom_output_type: node.user_data.custom_output_type.clone(),
+                inputs,
+                outputs,
+            });
+            // connections in the order of the inputs, and the order they were made in for each input
+            for (input_name, input_id) in node.inputs.iter() {
+                for output_id in graph.connections(*input_id) {
+                    let output = graph.get_output(output_id);
+                    let Some((output_name, _)) = graph.nodes[output.node].outputs.iter().find(|(_, id)| *id == output_id) else {
+                        continue;
+                    };
+                    document.connections.push(DocumentConnection {
+                        from: (graph.nodes[output.node].user_data.uid, output_name.clone()),
+                        to: (uid, input_name.clone()),
+                    });
+                }
+            }
+        }
+        document.exposed_nodes = exposed_nodes.iter()
+            .filter_map(|(node_id, name)| Some((node_uid(node_id)?, name.clone())))
+            .collect();
+        document.group_frames = frames_of(state).into_iter()
+            .map(|frame| GroupFrame {
+                rect: egui::Rect::from_min_max(
+                    egui::pos2(round(frame.rect.min.x), round(frame.rect.min.y)),
+                    egui::pos2(round(frame.rect.max.x), round(frame.rect.max.y)),
+                ),
+                ..frame
+            })
+            .collect();
+        document
+    }
+
+    // Puts the connections in the order from_state writes them in. Connections into the same input keep their order.
+    pub fn sort_connections(&mut self) {
+        let nodes = &self.nodes;
+        self.connections.sort_by_key(|connection| {
+            let input_idx = nodes.get(&connection.to.0)
+                .and_then(|node| node.inputs.iter().position(|input| input.name == connection.to.1));
+            (connection.to.0, input_idx)
+        });
+    }
+
+    pub fn into_state(self) -> anyhow::Result<(MyEditorState, SecondaryMap<NodeId, String>)> {
+        let mut state = MyEditorState::default();
+        let mut ids: HashMap<NodeUid, NodeId> = HashMap::new();
+        for (uid, node) in self.nodes.iter() {
+            let user_data = PulseNodeData {
+                template: node.template,
+                uid: *uid,
+                input_hint_text: node.input_hint_text.clone(),
+                custom_output_type: node.custom_output_type.clone(),
+                ..Default::default()
+            };
+            let mut added_inputs = vec![];
+            let node_id = state.graph.add_node(node.label.clone(), user_data, |graph, node_id| {
+                for input in node.inputs.iter() {
+                    let input_id = graph.add_wide_input_param(
+                        node_id,
+                        input.name.clone(),
+                        input.typ.clone(),
+                        input.value.clone(),
+                        input.kind,
+                        input.max_connections,
+                        input.shown_inline,
+                    );
+                    if input.user_added {
+                        added_inputs.push(input_id);
+                    }
+                }
+                for (name, typ) in node.outputs.iter() {
+                    graph.add_output_param(node_id, name.clone(), typ.clone());
+                }
+            });
+            state.graph.nodes[node_id].user_data.added_inputs = added_inputs;
+            state.node_positions.insert(node_id, egui::pos2(node.position.0, node.position.1));
+            state.node_sizes.insert(node_id, egui::vec2(node.size.0, node.size.1));
+            state.node_order.push(node_id);
+            ids.insert(*uid, node_id);
+        }
+
+        // node choices can point to nodes that come later, so they're set once all nodes exist
+        for (uid, node) in self.nodes.iter() {
+            let node_id = ids[uid];
+            for (input, (_, input_id)) in node.inputs.iter().zip(state.graph.nodes[node_id].inputs.clone()) {
+                if let Some(target) = input.target {
+                    state.graph.get_input_mut(input_id).value = PulseGraphValueType::NodeChoice { node: ids.get(&target).copied() };
+                }
+            }
+        }
+        for connection in self.connections.iter() {
+            let port = |uid: NodeUid| ids.get(&uid).map(|node_id| &state.graph.nodes[*node_id]);
+            let output = port(connection.from.0).and_then(|node| node.get_output(&connection.from.1).ok());
+            let input = port(connection.to.0).and_then(|node| node.get_input(&connection.to.1).ok());
+            let (Some(output), Some(input)) = (output, input) else {
+                anyhow::bail!(
+                    "Connection from {}.{} to {}.{} goes to a node or port that doesn't exist",
+                    connection.from.0, connection.from.1, connection.to.0, connection.to.1
+                );
+            };
+            let hook = state.graph.connections(input).len();
+            state.graph.add_connection(output, input, hook);
+        }
+        let mut exposed_nodes = SecondaryMap::new();
+        for (uid, name) in self.exposed_nodes {
+            if let Some(node_id) = ids.get(&uid) {
+                exposed_nodes.insert(*node_id, name);
+            }
+        }
+        state.group_frames = self.group_frames;
+        Ok((state, exposed_nodes))
+    }
+}
+
+impl GraphDocument {
+    // `full_state` should be the root state, macros that are being edited are saved as they were before.
+    pub fn from_full_state(full_state: &FullGraphState) -> Self {
+        let user_state = &full_state.user_state;
+        Self {
+            format_version: full_state.format_version,
+            graph_domain: user_state.graph_domain.clone(),
+            graph_subtype: user_state.graph_subtype.clone(),
+            variables: user_state.variables.clone(),
+            public_outputs: user_state.public_outputs.clone(),
+            libraries: user_state.libraries.clone(),
+            graph: DocumentGraph::from_state(&full_state.state, &user_state.exposed_nodes),
+            macros: user_state.macros.iter()
+                .map(|def| DocumentMacro {
+                    id: def.id,
+                    name: def.name.clone(),
+                    inputs: def.inputs.clone(),
+                    outputs: def.outputs.clone(),
+                    graph: DocumentGraph::from_state(&def.state, &def.exposed_nodes),
+                })
+                .collect(),
+            next_node_uid: user_state.next_node_uid,
+        }
+    }
+
+    pub fn into_full_state(self) -> anyhow::Result<FullGraphState> {
+        let mut full_state = FullGraphState { format_version: self.format_version, ..Default::default() };
+        full_state.user_state.next_node_uid = self.next_node_uid.max(self.max_node_uid() + 1);
+        let (state, exposed_nodes) = self.graph.into_state()?;
+        full_state.state = state;
+        let user_state = &mut full_state.user_state;
+        user_state.exposed_nodes = exposed_nodes;
+        user_state.graph_domain = self.graph_domain;
+        user_state.graph_subtype = self.graph_subtype;
+        user_state.variables = self.variables;
+        user_state.public_outputs = self.public_outputs;
+        user_state.libraries = self.libraries;
+        for def in self.macros {
+            let (state, exposed_nodes) = def.graph.into_state()
+                .map_err(|e| anyhow::anyhow!("Macro '{}': {e}", def.name))?;
+            user_state.macros.push(MacroDefinition {
+                id: def.id,
+                name: def.name,
+                inputs: def.inputs,
+                outputs: def.outputs,
+                state,
+                exposed_nodes,
+            });
+        }
+        Ok(full_state)
+    }
+
+    // highest uid used by a node in any of the graphs
+    pub fn max_node_uid(&self) -> NodeUid {
+        std::iter::once(&self.graph)
+            .chain(self.macros.iter().map(|def| &def.graph))
+            .filter_map(|graph| graph.nodes.keys().max())
+            .max()
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn find_macro(&self, id: MacroId) -> Option<&DocumentMacro> {
+        self.macros.iter().find(|def| def.id == id)
+    }
+
+    // the graph of a macro, or the top level one for None
+    pub fn graph(&self, id: Option<MacroId>) -> Option<&DocumentGraph> {
+        match id {
+            Some(id) => self.find_macro(id).map(|def| &def.graph),
+            None => Some(&self.graph),
+        }
+    }
+
+    pub fn graph_mut(&mut self, id: Option<MacroId>) -> Option<&mut DocumentGraph> {
+        match id {
+            Some(id) => self.macros.iter_mut().find(|def| def.id == id).map(|def| &mut def.graph),
+            None => Some(&mut self.graph),
+        }
+    }
+
+    pub fn to_ron(&self) -> anyhow::Result<String> {
+        // values, positions and connections each go on one line, so changing one of them or moving a node
+        // is a single line in a diff
+        let config = ron::ser::PrettyConfig::default().depth_limit(6);
+        Ok(ron::ser::to_string_pretty(self, config)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use slotmap::Key;
+    use super::*;
+
+    fn add_log(full_state: &mut FullGraphState) -> NodeId {
+        full_state.add_node_at(PulseNodeTemplate::DebugLog, egui::Pos2::ZERO, egui::vec2(200.0, 200.0))
+    }
+
+    #[test]
+    fn test_node_uids_are_not_reused() {
+        let mut full_state = FullGraphState::default();
+        let first = add_log(&mut full_state);
+        let second = add_log(&mut full_state);
+        let second_uid = full_state.state.graph.nodes[second].user_data.uid;
+        full_state.state.graph.remove_node(second);
+        // the slot of the deleted node gets reused by the graph, its uid doesn't
+        let third = add_log(&mut full_state);
+        assert_eq!(third.data().as_ffi() as u32, second.data().as_ffi() as u32);
+        let third_uid = full_state.state.graph.nodes[third].user_data.uid;
+        assert!(third_uid > second_uid);
+
+        let document = GraphDocument::from_full_state(&full_state);
+        let first_uid = full_state.state.graph.nodes[first].user_data.uid;
+        assert_eq!(document.graph.nodes.keys().copied().collect::<Vec<_>>(), vec![first_uid, third_uid]);
+        let ron = document.to_ron().unwrap();
+        let (mut loaded, _) = FullGraphState::from_saved(&ron).unwrap();
+        assert!(GraphDocument::from_full_state(&loaded) == document);
+        // the counter is saved, so the uid of the deleted node isn't given out after loading either
+        assert_eq!(loaded.user_state.next_node_uid, third_uid + 1);
+        let fourth = add_log(&mut loaded);
+        assert!(loaded.state.graph.nodes[fourth].user_data.uid > third_uid);
+    }
+}
@@ -0,0 +1,568 @@
+// Exporting the graph as a picture, for documentation. Instead of taking a screenshot, the graph is drawn by
+// egui_node_graph2 into an egui context of its own that isn't shown anywhere, big enough to fit all of it.
+// The shapes it painted are then written out as SVG, or tessellated the same way egui does for the screen
+// and rasterized for PNG.
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::Arc;
+use eframe::egui::{self, Color32, ColorImage, Pos2, Rect, RichText, Style, TextureId, Vec2};
+use eframe::epaint::{self, ClippedPrimitive, ClippedShape, Mesh, Primitive, Shape, StrokeKind};
+use egui_node_graph2::*;
+use rfd::{FileDialog, MessageDialog};
+use super::history::frames_of;
+use super::types::*;
+use super::PulseGraphEditor;
+
+// empty space around the graph, in points
+const MARGIN: f32 = 20.0;
+// biggest PNG, counted the way it's held in memory while drawing, 4 bytes per pixel
+const MAX_IMAGE_BYTES: usize = 256 * 1024 * 1024;
+
+#[derive(Clone)]
+pub struct ExportOptions {
+    pub selection_only: bool,
+    // pixels per point of a PNG, SVGs get it as their default size
+    pub scale: f32,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self { selection_only: false, scale: 2.0 }
+    }
+}
+
+struct Picture {
+    // the context the shapes were painted in, it has the fonts to tessellate them
+    ctx: egui::Context,
+    shapes: Vec<ClippedShape>,
+    textures: HashMap<TextureId, ColorImage>,
+    size: Vec2,
+    scale: f32,
+    background: Color32,
+}
+
+fn apply_textures(textures: &mut HashMap<TextureId, ColorImage>, delta: epaint::textures::TexturesDelta) {
+    for (id, image_delta) in delta.set {
+        let epaint::ImageData::Color(image) = image_delta.image;
+        match image_delta.pos {
+            None => {
+                textures.insert(id, (*image).clone());
+            }
+            Some([x, y]) => {
+                let Some(texture) = textures.get_mut(&id) else {
+                    continue;
+                };
+                for row in 0..image.size[1] {
+                    let start = (y + row) * texture.size[0] + x;
+                    texture.pixels[start..start + image.size[0]]
+                        .copy_from_slice(&image.pixels[row * image.size[0]..(row + 1) * image.size[0]]);
+                }
+            }
+        }
+    }
+    for id in delta.free {
+        textures.remove(&id);
+    }
+}
+
+impl PulseGraphEditor {
+    // Draws the graph shown in the editor, unzoomed, with its top left corner at the margin.
+    fn draw_picture(&self, options: &ExportOptions, style: Arc<Style>) -> anyhow::Result<Picture> {
+        let mut state = self.state().clone();
+        let pan_zoom = state.pan_zoom.clone();
+        state.group_frames = frames_of(&state);
+        for pos in state.node_positions.values_mut() {
+            *pos = pan_zoom.to_graph_space(*pos);
+        }
+        state.pan_zoom = PanZoom::default();
+        state.selected_nodes.clear();
+        state.node_finder = None;
+        state.connection_in_progress = None;
+        state.ongoing_box_selection = None;
+        state.show_minimap = false;
+        if options.selection_only {
+            let selected = &self.state().selected_nodes;
+            let others: Vec<NodeId> = state.graph.nodes.keys().filter(|node_id| !selected.contains(node_id)).collect();
+            for node_id in others {
+                state.graph.remove_node(node_id);
+                state.node_positions.remove(node_id);
+            }
+            state.node_order.retain(|node_id| selected.contains(node_id));
+            state.group_frames.clear();
+        }
+        if state.graph.nodes.is_empty() {
+            anyhow::bail!("There's nothing to export");
+        }
+        let mut user_state = self.user_state().clone();
+
+        // where the graph ends only shows once it's drawn, so it's drawn once on a canvas that's surely big
+        // enough, and then again on one that fits it
+        let corners = state.node_positions.values().copied()
+            .chain(state.group_frames.iter().flat_map(|frame| [frame.rect.min, frame.rect.max]));
+        let (min, max) = corners.fold((Pos2::new(f32::INFINITY, f32::INFINITY), Pos2::new(f32::NEG_INFINITY, f32::NEG_INFINITY)), |(min, max), pos| {
+            (min.min(pos), max.max(pos))
+        });
+        let ctx = egui::Context::default();
+        ctx.set_style(style.clone());
+        let mut textures = HashMap::new();
+        state.pan_zoom.pan = Vec2::splat(MARGIN) - min.to_vec2();
+        // nodes only have their width saved, leave plenty of room under them
+        let canvas = (max - min) + Vec2::new(1000.0, 4000.0);
+        let mut draw = |state: &mut MyEditorState, size: Vec2, scale: f32| {
+            let mut raw_input = egui::RawInput {
+                screen_rect: Some(Rect::from_min_size(Pos2::ZERO, size)),
+                ..Default::default()
+            };
+            raw_input.viewports.entry(egui::ViewportId::ROOT).or_default().native_pixels_per_point = Some(scale);
+            let mut shapes = vec![];
+            // the first passes lay out the nodes, sizes of their contents are only known after drawing them
+            for _ in 0..3 {
+                let output = ctx.run(raw_input.clone(), |ctx| {
+                    egui::CentralPanel::default().frame(egui::Frame::NONE).show(ctx, |ui| {
+                        let _ = state.draw_graph_editor(ui, self.node_templates(), &mut user_state, vec![]);
+                    });
+                });
+                apply_textures(&mut textures, output.textures_delta);
+                shapes = output.shapes;
+            }
+            shapes
+        };
+        let shapes = draw(&mut state, canvas, 1.0);
+        let bounds = shapes.iter()
+            .map(|clipped| clipped.shape.visual_bounding_rect().intersect(clipped.clip_rect))
+            .filter(|rect| rect.is_positive() && rect.is_finite())
+            .fold(Rect::NOTHING, |bounds, rect| bounds.union(rect));
+        if !bounds.is_positive() {
+            anyhow::bail!("There's nothing to export");
+        }
+        state.pan_zoom.pan += Vec2::splat(MARGIN) - bounds.min.to_vec2();
+        let size = bounds.size() + Vec2::splat(MARGIN * 2.0);
+        let shapes = draw(&mut state, size, options.scale);
+        Ok(Picture { ctx, shapes, textures, size, scale: options.scale, background: style.visuals.panel_fill })
+    }
+
+    fn export_image(&self, path: &Path, ctx: &egui::Context) -> anyhow::Result<()> {
+        let picture = self.draw_picture(&self.export_options, ctx.style())?;
+        let is_svg = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+        if is_svg {
+            std::fs::write(path, picture.to_svg())?;
+        } else {
+            picture.to_png()?.save_with_format(path, image::ImageFormat::Png)?;
+        }
+        Ok(())
+    }
+
+    // Contents of the export dialog. Returns true once it should close.
+    pub(super) fn export_ui(&mut self, ui: &mut egui::Ui) -> bool {
+        ui.set_width(350.0);
+        ui.label(RichText::new("Export image").size(24.0));
+        let has_selection = !self.state().selected_nodes.is_empty();
+        let options = &mut self.export_options;
+        options.selection_only &= has_selection;
+        ui.radio_value(&mut options.selection_only, false, "Whole graph");
+        if ui.add_enabled(has_selection, egui::RadioButton::new(options.selection_only, "Selected nodes")).clicked() {
+            options.selection_only = true;
+        }
+        ui.horizontal(|ui| {
+            ui.label("Scale");
+            ui.add(egui::DragValue::new(&mut options.scale).range(0.5..=8.0).speed(0.05).suffix("x"));
+        });
+        ui.weak("PNGs are rendered at this scale. SVGs are vector images, it only sets their default size.");
+        ui.separator();
+        let (cancel, export) = egui::Sides::new().show(
+            ui,
+            |_ui| {},
+            |ui| {
+                let cancel = ui.add_sized([120., 30.], egui::Button::new(RichText::new("Cancel").size(18.0))).clicked();
+                let export = ui.add_sized([120., 30.], egui::Button::new(RichText::new("Export...").size(18.0))).clicked();
+                (cancel, export)
+            },
+        ).1;
+        if !export {
+            return cancel;
+        }
+        let file_name = self.user_state().save_file_path.as_ref()
+            .and_then(|path| path.file_stem())
+            .map_or("graph".to_string(), |name| name.to_string_lossy().to_string());
+        let Some(path) = FileDialog::new()
+            .add_filter("PNG image", &["png"])
+            .add_filter("SVG image", &["svg"])
+            .set_file_name(format!("{file_name}.png"))
+            .save_file() else {
+            return false;
+        };
+        if let Err(e) = self.export_image(&path, ui.ctx()) {
+            MessageDialog::new()
+                .set_level(rfd::MessageLevel::Error)
+                .set_title("Export failed")
+                .set_buttons(rfd::MessageButtons::Ok)
+                .set_description(format!("{e:#}"))
+                .show();
+            return false;
+        }
+        true
+    }
+}
+
+// SVG
+
+fn svg_color(color: Color32) -> (String, f32) {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    (format!("#{r:02x}{g:02x}{b:02x}"), a as f32 / 255.0)
+}
+
+fn paint_attr(name: &str, color: Color32) -> String {
+    if color.a() == 0 {
+        return format!(" {name}=\"none\"");
+    }
+    let (rgb, alpha) = svg_color(color);
+    if alpha < 1.0 {
+        format!(" {name}=\"{rgb}\" {name}-opacity=\"{alpha:.3}\"")
+    } else {
+        format!(" {name}=\"{rgb}\"")
+    }
+}
+
+fn stroke_attr(stroke: &epaint::Stroke) -> String {
+    if stroke.is_empty() {
+        return " stroke=\"none\"".to_string();
+    }
+    format!("{} stroke-width=\"{}\"", paint_attr("stroke", stroke.color), stroke.width)
+}
+
+fn path_stroke_attr(stroke: &epaint::PathStroke) -> String {
+    match stroke.color {
+        epaint::ColorMode::Solid(color) if stroke.width > 0.0 => {
+            stroke_attr(&epaint::Stroke::new(stroke.width, color))
+        }
+        _ => " stroke=\"none\"".to_string(),
+    }
+}
+
+fn points_path(points: &[Pos2], closed: bool) -> String {
+    let mut d = String::new();
+    for (idx, point) in points.iter().enumerate() {
+        let _ = write!(d, "{}{} {} ", if idx == 0 { "M" } else { "L" }, point.x, point.y);
+    }
+    if closed {
+        d.push('Z');
+    }
+    d
+}
+
+fn rounded_rect_path(rect: Rect, corner_radius: epaint::CornerRadiusF32) -> String {
+    let max_radius = rect.width().min(rect.height()) / 2.0;
+    let [nw, ne, sw, se] = [corner_radius.nw, corner_radius.ne, corner_radius.sw, corner_radius.se]
+        .map(|radius| radius.clamp(0.0, max_radius));
+    let (l, t, r, b) = (rect.left(), rect.top(), rect.right(), rect.bottom());
+    format!(
+        "M{} {t} L{} {t} A{ne} {ne} 0 0 1 {r} {} L{r} {} A{se} {se} 0 0 1 {} {b} L{} {b} A{sw} {sw} 0 0 1 {l} {} L{l} {} A{nw} {nw} 0 0 1 {} {t} Z",
+        l + nw, r - ne, t + ne, b - se, r - se, l + sw, b - sw, t + nw, l + nw,
+    )
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn font_family(family: &egui::FontFamily) -> String {
+    // the fonts egui comes with
+    match family {
+        egui::FontFamily::Proportional => "'Ubuntu Light', Ubuntu, sans-serif".to_string(),
+        egui::FontFamily::Monospace => "Hack, monospace".to_string(),
+        egui::FontFamily::Name(name) => format!("'{name}', sans-serif"),
+    }
+}
+
+// Index of the section of the layout job each glyph comes from. Glyphs don't keep it, so they're matched
+// back to the characters of the text.
+fn glyph_sections(galley: &epaint::Galley) -> Vec<Vec<usize>> {
+    let job = &galley.job;
+    let mut byte = 0;
+    galley.rows.iter()
+        .map(|placed| placed.row.glyphs.iter()
+            .map(|glyph| {
+                // skips newlines and spaces dropped at wrapping, the overflow character of elided text isn't there
+                if let Some(offset) = job.text[byte..].find(glyph.chr) {
+                    byte += offset + glyph.chr.len_utf8();
+                }
+                let char_byte = byte.saturating_sub(glyph.chr.len_utf8());
+                job.sections.iter().position(|section| section.byte_range.contains(&char_byte)).unwrap_or(0)
+            })
+            .collect())
+        .collect()
+}
+
+fn write_text(out: &mut String, text: &epaint::TextShape) {
+    let galley = &text.galley;
+    if text.angle != 0.0 {
+        let _ = write!(out, "<g transform=\"rotate({} {} {})\">", text.angle.to_degrees(), text.pos.x, text.pos.y);
+    }
+    for (placed, sections) in galley.rows.iter().zip(glyph_sections(galley)) {
+        let glyphs = &placed.row.glyphs;
+        let mut start = 0;
+        // one text element per run of glyphs with the same format
+        while start < glyphs.len() {
+            let section = sections[start];
+            let end = (start..glyphs.len()).find(|idx| sections[*idx] != section).unwrap_or(glyphs.len());
+            let run = &glyphs[start..end];
+            start = end;
+            let chars: String = run.iter().map(|glyph| glyph.chr).collect();
+            if chars.trim().is_empty() {
+                continue;
+            }
+            let Some(format) = galley.job.sections.get(section).map(|section| &section.format) else {
+                continue;
+            };
+            let mut color = text.override_text_color.unwrap_or(format.color);
+            if color == Color32::PLACEHOLDER {
+                color = text.fallback_color;
+            }
+            color = color.gamma_multiply(text.opacity_factor);
+            let origin = text.pos + placed.pos.to_vec2();
+            let xs: Vec<String> = run.iter().map(|glyph| (origin.x + glyph.pos.x).to_string()).collect();
+            let _ = write!(
+                out,
+                "<text x=\"{}\" y=\"{}\" font-size=\"{}\" font-family=\"{}\"{} xml:space=\"preserve\">{}</text>",
+                xs.join(" "),
+                origin.y + run[0].pos.y,
+                format.font_id.size,
+                font_family(&format.font_id.family),
+                paint_attr("fill", color),
+                escape_xml(&chars),
+            );
+        }
+    }
+    if text.angle != 0.0 {
+        out.push_str("</g>");
+    }
+}
+
+fn write_shape(out: &mut String, defs: &mut String, shape: &Shape, clip: &str) {
+    match shape {
+        Shape::Noop | Shape::Callback(_) => {}
+        Shape::Vec(shapes) => {
+            for shape in shapes {
+                write_shape(out, defs, shape, clip);
+            }
+        }
+        Shape::Circle(circle) => {
+            let _ = write!(out, "<circle cx=\"{}\" cy=\"{}\" r=\"{}\"{}{}{clip}/>",
+                circle.center.x, circle.center.y, circle.radius, paint_attr("fill", circle.fill), stroke_attr(&circle.stroke));
+        }
+        Shape::Ellipse(ellipse) => {
+            let _ = write!(out, "<ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\"{}{}{clip}/>",
+                ellipse.center.x, ellipse.center.y, ellipse.radius.x, ellipse.radius.y,
+                paint_attr("fill", ellipse.fill), stroke_attr(&ellipse.stroke));
+        }
+        Shape::LineSegment { points, stroke } => {
+            let _ = write!(out, "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"{}{clip}/>",
+                points[0].x, points[0].y, points[1].x, points[1].y, stroke_attr(stroke));
+        }
+        Shape::Path(path) => {
+            let fill = if path.closed { path.fill } else { Color32::TRANSPARENT };
+            let _ = write!(out, "<path d=\"{}\"{}{}{clip}/>",
+                points_path(&path.points, path.closed), paint_attr("fill", fill), path_stroke_attr(&path.stroke));
+        }
+        Shape::Rect(rect) => {
+            let corner_radius = epaint::CornerRadiusF32::from(rect.corner_radius);
+            let mut filter = String::new();
+            if rect.blur_width > 0.0 {
+                // shadows
+                let id = format!("blur{}", defs.len());
+                let _ = write!(defs, "<filter id=\"{id}\" x=\"-50%\" y=\"-50%\" width=\"200%\" height=\"200%\"><feGaussianBlur stdDeviation=\"{}\"/></filter>", rect.blur_width / 4.0);
+                filter = format!(" filter=\"url(#{id})\"");
+            }
+            if rect.fill.a() > 0 {
+                let _ = write!(out, "<path d=\"{}\"{}{filter}{clip}/>",
+                    rounded_rect_path(rect.rect, corner_radius), paint_attr("fill", rect.fill));
+            }
+            if !rect.stroke.is_empty() {
+                let half = rect.stroke.width / 2.0;
+                let (stroke_rect, radius) = match rect.stroke_kind {
+                    StrokeKind::Inside => (rect.rect.shrink(half), corner_radius - half.into()),
+                    StrokeKind::Middle => (rect.rect, corner_radius),
+                    StrokeKind::Outside => (rect.rect.expand(half), corner_radius + half.into()),
+                };
+                let _ = write!(out, "<path d=\"{}\" fill=\"none\"{}{clip}/>",
+                    rounded_rect_path(stroke_rect, radius), stroke_attr(&rect.stroke));
+            }
+        }
+        Shape::Text(text) => {
+            if clip.is_empty() {
+                write_text(out, text);
+            } else {
+                let _ = write!(out, "<g{clip}>");
+                write_text(out, text);
+                out.push_str("</g>");
+            }
+        }
+        Shape::Mesh(mesh) => {
+            // only plain colored meshes, the graph doesn't draw any images
+            for triangle in mesh.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|idx| &mesh.vertices[triangle[idx] as usize]);
+                let _ = write!(out, "<path d=\"{}\"{}{clip}/>", points_path(&[a.pos, b.pos, c.pos], true), paint_attr("fill", a.color));
+            }
+        }
+        Shape::QuadraticBezier(bezier) => {
+            let [p0, p1, p2] = bezier.points;
+            let fill = if bezier.closed { bezier.fill } else { Color32::TRANSPARENT };
+            let _ = write!(out, "<path d=\"M{} {} Q{} {} {} {}{}\"{}{}{clip}/>",
+                p0.x, p0.y, p1.x, p1.y, p2.x, p2.y, if bezier.closed { " Z" } else { "" },
+                paint_attr("fill", fill), path_stroke_attr(&bezier.stroke));
+        }
+        Shape::CubicBezier(bezier) => {
+            let [p0, p1, p2, p3] = bezier.points;
+            let fill = if bezier.closed { bezier.fill } else { Color32::TRANSPARENT };
+            let _ = write!(out, "<path d=\"M{} {} C{} {} {} {} {} {}{}\"{}{}{clip}/>",
+                p0.x, p0.y, p1.x, p1.y, p2.x, p2.y, p3.x, p3.y, if bezier.closed { " Z" } else { "" },
+                paint_attr("fill", fill), path_stroke_attr(&bezier.stroke));
+        }
+    }
+}
+
+impl Picture {
+    fn to_svg(&self) -> String {
+        let canvas = Rect::from_min_size(Pos2::ZERO, self.size);
+        let mut defs = String::new();
+        let mut body = String::new();
+        let mut clip_ids: Vec<Rect> = vec![];
+        for clipped in self.shapes.iter() {
+            // most shapes aren't clipped by anything but the canvas
+            let clip = if clipped.clip_rect.contains_rect(canvas) {
+                String::new()
+            } else {
+                let idx = clip_ids.iter().position(|rect| *rect == clipped.clip_rect).unwrap_or_else(|| {
+                    let rect = clipped.clip_rect.intersect(canvas);
+                    let _ = write!(defs, "<clipPath id=\"clip{}\"><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/></clipPath>",
+                        clip_ids.len(), rect.min.x, rect.min.y, rect.width().max(0.0), rect.height().max(0.0));
+                    clip_ids.push(clipped.clip_rect);
+                    clip_ids.len() - 1
+                });
+                format!(" clip-path=\"url(#clip{idx})\"")
+            };
+            write_shape(&mut body, &mut defs, &clipped.shape, &clip);
+            body.push('\n');
+        }
+        let (width, height) = (self.size.x, self.size.y);
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {width} {height}\">\n<defs>{defs}</defs>\n<rect width=\"{width}\" height=\"{height}\"{}/>\n{body}</svg>\n",
+            (width * self.scale).round(), (height * self.scale).round(), paint_attr("fill", self.background),
+        )
+    }
+
+    // PNG
+
+    fn to_png(&self) -> anyhow::Result<image::RgbaImage> {
+        let pixel_size = self.size * self.scale;
+        let (width, height) = (pixel_size.x.ceil() as usize, pixel_size.y.ceil() as usize);
+        let bytes = width.saturating_mul(height).saturating_mul(4);
+        if bytes > MAX_IMAGE_BYTES {
+            anyhow::bail!("The image would be {width}x{height} pixels ({} MB), lower the scale", bytes / (1024 * 1024));
+        }
+        let mut raster = Raster::new(width, height, self.background);
+        for ClippedPrimitive { clip_rect, primitive } in self.ctx.tessellate(self.shapes.clone(), self.scale) {
+            if let Primitive::Mesh(mesh) = primitive {
+                let clip_rect = Rect::from_min_max((clip_rect.min.to_vec2() * self.scale).to_pos2(), (clip_rect.max.to_vec2() * self.scale).to_pos2());
+                raster.draw_mesh(&mesh, clip_rect, self.textures.get(&mesh.texture_id), self.scale);
+            }
+        }
+        Ok(raster.into_image())
+    }
+}
+
+// A software version of what egui's painters do: premultiplied colors, blended in gamma space, with the
+// vertex color multiplied by the texture. Pixels are kept as premultiplied bytes like a Color32, to keep big
+// images small.
+struct Raster {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 4]>,
+}
+
+fn to_rgba(color: Color32) -> [f32; 4] {
+    color.to_array().map(|channel| channel as f32 / 255.0)
+}
+
+fn sample(texture: &ColorImage, uv: Pos2) -> [f32; 4] {
+    // bilinear, like the texture options egui uses for fonts
+    let [width, height] = texture.size;
+    let x = (uv.x * width as f32 - 0.5).clamp(0.0, (width - 1) as f32);
+    let y = (uv.y * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let texel = |x: usize, y: usize| to_rgba(texture.pixels[y * width + x]);
+    let (a, b, c, d) = (texel(x0, y0), texel(x1, y0), texel(x0, y1), texel(x1, y1));
+    std::array::from_fn(|i| {
+        let top = a[i] + (b[i] - a[i]) * fx;
+        let bottom = c[i] + (d[i] - c[i]) * fx;
+        top + (bottom - top) * fy
+    })
+}
+
+fn edge(a: Pos2, b: Pos2, p: Pos2) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+impl Raster {
+    fn new(width: usize, height: usize, background: Color32) -> Self {
+        Self { width, height, pixels: vec![background.to_array(); width * height] }
+    }
+
+    fn draw_mesh(&mut self, mesh: &Mesh, clip_rect: Rect, texture: Option<&ColorImage>, scale: f32) {
+        let bounds = clip_rect.intersect(Rect::from_min_size(Pos2::ZERO, Vec2::new(self.width as f32, self.height as f32)));
+        for triangle in mesh.indices.chunks_exact(3) {
+            let vertices = [0, 1, 2].map(|idx| &mesh.vertices[triangle[idx] as usize]);
+            let positions = vertices.map(|vertex| (vertex.pos.to_vec2() * scale).to_pos2());
+            let area = edge(positions[0], positions[1], positions[2]);
+            if area == 0.0 {
+                continue;
+            }
+            let colors = vertices.map(|vertex| to_rgba(vertex.color));
+            let rect = Rect::from_points(&positions).intersect(bounds);
+            if !rect.is_positive() {
+                continue;
+            }
+            for y in rect.min.y.floor() as usize..rect.max.y.ceil() as usize {
+                for x in rect.min.x.floor() as usize..rect.max.x.ceil() as usize {
+                    let p = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                    if !bounds.contains(p) {
+                        continue;
+                    }
+                    let weights = [
+                        edge(positions[1], positions[2], p) / area,
+                        edge(positions[2], positions[0], p) / area,
+                        edge(positions[0], positions[1], p) / area,
+                    ];
+                    if weights.iter().any(|weight| *weight < 0.0) {
+                        continue;
+                    }
+                    let uv = Pos2::new(
+                        weights.iter().zip(vertices.iter()).map(|(w, vertex)| w * vertex.uv.x).sum(),
+                        weights.iter().zip(vertices.iter()).map(|(w, vertex)| w * vertex.uv.y).sum(),
+                    );
+                    let texel = texture.map_or([1.0; 4], |texture| sample(texture, uv));
+                    let src: [f32; 4] = std::array::from_fn(|i| {
+                        (weights[0] * colors[0][i] + weights[1] * colors[1][i] + weights[2] * colors[2][i]) * texel[i]
+                    });
+                    let dst = &mut self.pixels[y * self.width + x];
+                    for i in 0..4 {
+                        let blended = src[i] + dst[i] as f32 / 255.0 * (1.0 - src[3]);
+                        dst[i] = (blended.clamp(0.0, 1.0) * 255.0).round() as u8;
+                    }
+                }
+            }
+        }
+    }
+
+    fn into_image(mut self) -> image::RgbaImage {
+        // in place, so the image doesn't need a second buffer
+        for pixel in self.pixels.iter_mut() {
+            *pixel = Color32::from_rgba_premultiplied(pixel[0], pixel[1], pixel[2], pixel[3]).to_srgba_unmultiplied();
+        }
+        image::RgbaImage::from_raw(self.width as u32, self.height as u32, self.pixels.into_flattened())
+            .expect("the buffer has 4 bytes per pixel")
+    }
+}
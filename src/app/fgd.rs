@@ -0,0 +1,257 @@
+// FGD entries for graphs, so Hammer knows the inputs and outputs graphs add to their point_pulse entities,
+// instead of showing them in red. The game only has the one point_pulse class, so every graph gets a base class
+// with its inputs and outputs, and point_pulse is defined with all of them as its bases. Several graphs can be
+// added to the same FGD file, they're kept between marker comments and replaced when exported again.
+use std::fmt::Write;
+use std::path::Path;
+use rfd::{FileDialog, MessageDialog};
+use crate::compiler::linked_graph;
+use crate::typing::PulseValueType;
+use super::interface::{graph_def_path, public_methods};
+use super::{FullGraphState, PulseGraphEditor};
+
+const BEGIN_MARKER: &str = "// BEGIN pulseedit graphs - generated, changes between these lines are overwritten";
+const END_MARKER: &str = "// END pulseedit graphs";
+const GRAPH_MARKER: &str = "// graph: ";
+// same as the one in the README
+const POINT_PULSE_CLASS: &str = "tags( Logic ) iconsprite(\"editor/point_pulse.vmat\") = point_pulse : \"An entity that acts as a container for pulse graphs\"
+[
+    graph_def(string) : \"Graph path\" : \"\" :
+]";
+
+pub struct FgdEntry {
+    // graph_def path of the graph if it's known, otherwise its file name. Entries with the same key replace each other.
+    pub key: String,
+    pub class_name: String,
+    text: String,
+}
+
+fn io_type(typ: &PulseValueType) -> &'static str {
+    match typ {
+        PulseValueType::PVAL_VOID => "void",
+        PulseValueType::PVAL_INT(_) | PulseValueType::PVAL_TYPESAFE_INT(..) => "integer",
+        PulseValueType::PVAL_FLOAT(_) | PulseValueType::PVAL_GAMETIME(_) => "float",
+        PulseValueType::PVAL_BOOL | PulseValueType::PVAL_BOOL_VALUE(_) => "bool",
+        PulseValueType::PVAL_VEC3(_) | PulseValueType::PVAL_VEC3_LOCAL(_) | PulseValueType::PVAL_QANGLE(_) => "vector",
+        PulseValueType::PVAL_COLOR_RGB(_) => "color255",
+        PulseValueType::PVAL_EHANDLE(_) => "ehandle",
+        _ => "string",
+    }
+}
+
+// FGD strings can't contain quotes, and names only some characters
+fn description(text: &str) -> String {
+    text.replace('"', "'")
+}
+
+fn identifier(text: &str) -> String {
+    text.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' }).collect()
+}
+
+// Input and output names are written as they are, Hammer can't read the file if one isn't a plain identifier.
+// They can't be changed here like the class name, the game looks them up by name.
+fn check_io_name(what: &str, name: &str) -> anyhow::Result<()> {
+    let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        anyhow::bail!("{what} '{name}' can't be used in an FGD, names can only have letters, digits and underscores");
+    }
+    Ok(())
+}
+
+impl FullGraphState {
+    pub fn fgd_entry(&self) -> anyhow::Result<FgdEntry> {
+        let root = self.root_state();
+        let (graph, user_state) = linked_graph(&root.state.graph, &root.user_state)?;
+        let save_path = user_state.save_file_path.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("The graph needs to be saved first"))?;
+        let file_name = save_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let key = graph_def_path(save_path).unwrap_or_else(|| file_name.clone());
+        let class_name = format!("pulse_graph_{}", identifier(key.strip_suffix(".vpulse").unwrap_or(&key)));
+
+        let mut text = format!("@BaseClass = {class_name}\n[\n");
+        for (name, argument) in public_methods(&graph) {
+            check_io_name("The Public method node", &name)?;
+            let typ = if argument { "string" } else { "void" };
+            let _ = writeln!(text, "    input {name}({typ}) : \"Public method of the graph {}\"", description(&key));
+        }
+        for output in user_state.public_outputs.iter() {
+            check_io_name("The output", &output.name)?;
+            let _ = writeln!(text, "    output {}({}) : \"Output of the graph {}\"", output.name, io_type(&output.typ), description(&key));
+        }
+        text.push_str("]\n");
+        Ok(FgdEntry { key, class_name, text })
+    }
+}
+
+// Adds the entry to the generated part of an FGD file, or replaces the entry of the same graph. The file can be
+// empty, to make a new one.
+pub fn merge_fgd(contents: &str, entry: &FgdEntry) -> anyhow::Result<String> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let begin = lines.iter().position(|line| line.trim() == BEGIN_MARKER);
+    let end = lines.iter().position(|line| line.trim() == END_MARKER);
+    let (before, generated, after) = match (begin, end) {
+        (Some(begin), Some(end)) if begin < end => (&lines[..begin], &lines[begin + 1..end], &lines[end + 1..]),
+        (None, None) => (&lines[..], &[][..], &[][..]),
+        _ => anyhow::bail!("The markers of the generated part of the FGD are broken, fix or remove them:\n{BEGIN_MARKER}\n{END_MARKER}"),
+    };
+    // the generated point_pulse would be a second one
+    let defines_point_pulse = before.iter().chain(after).any(|line| {
+        let line = line.trim_start();
+        line.starts_with("@PointClass") && line.contains("= point_pulse ")
+    });
+    if defines_point_pulse {
+        anyhow::bail!("The FGD already defines point_pulse. Remove its definition, the one generated with the graphs replaces it");
+    }
+
+    // the entries of other graphs, without the point_pulse class after them
+    let mut entries: Vec<(String, String, String)> = vec![];
+    for line in generated {
+        if let Some(key) = line.strip_prefix(GRAPH_MARKER) {
+            entries.push((key.to_string(), String::new(), String::new()));
+            continue;
+        }
+        if line.starts_with("@PointClass") {
+            break;
+        }
+        let Some((_, class_name, text)) = entries.last_mut().filter(|_| !line.trim().is_empty()) else {
+            continue;
+        };
+        if let Some(name) = line.strip_prefix("@BaseClass = ") {
+            *class_name = name.trim().to_string();
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+    entries.retain(|(key, class_name, _)| *key != entry.key && !class_name.is_empty());
+    entries.push((entry.key.clone(), entry.class_name.clone(), entry.text.clone()));
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out = String::new();
+    for line in before {
+        out.push_str(line);
+        out.push('\n');
+    }
+    if begin.is_none() && !out.is_empty() && !out.ends_with("\n\n") {
+        out.push('\n');
+    }
+    out.push_str(BEGIN_MARKER);
+    out.push('\n');
+    let mut bases = vec!["Targetname".to_string()];
+    for (key, class_name, text) in entries {
+        let _ = write!(out, "{GRAPH_MARKER}{key}\n{text}\n");
+        bases.push(class_name);
+    }
+    let _ = writeln!(out, "@PointClass base({}) {POINT_PULSE_CLASS}", bases.join(", "));
+    out.push_str(END_MARKER);
+    out.push('\n');
+    for line in after {
+        out.push_str(line);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+pub fn add_to_fgd_file(path: &Path, entry: &FgdEntry) -> anyhow::Result<()> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {e}", path.display()))?;
+    std::fs::write(path, merge_fgd(&contents, entry)?)?;
+    Ok(())
+}
+
+impl PulseGraphEditor {
+    // Writes a new FGD with just this graph, or adds it to an existing one.
+    pub(super) fn export_fgd(&self, add_to_existing: bool) {
+        let result = self.full_state.fgd_entry().and_then(|entry| {
+            if add_to_existing {
+                let Some(path) = FileDialog::new().add_filter("FGD", &["fgd"]).pick_file() else {
+                    return Ok(());
+                };
+                return add_to_fgd_file(&path, &entry);
+            }
+            let Some(path) = FileDialog::new()
+                .add_filter("FGD", &["fgd"])
+                .set_file_name(format!("{}.fgd", entry.class_name))
+                .save_file() else {
+                return Ok(());
+            };
+            std::fs::write(path, merge_fgd("", &entry)?)?;
+            Ok(())
+        });
+        if let Err(e) = result {
+            MessageDialog::new()
+                .set_level(rfd::MessageLevel::Error)
+                .set_title("Export failed")
+                .set_buttons(rfd::MessageButtons::Ok)
+                .set_description(format!("{e:#}"))
+                .show();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eframe::egui;
+    use crate::app::types::{PulseGraphValueType, PulseNodeTemplate};
+    use super::*;
+
+    fn entry(key: &str, input: &str) -> FgdEntry {
+        let class_name = format!("pulse_graph_{key}");
+        FgdEntry {
+            key: key.to_string(),
+            text: format!("@BaseClass = {class_name}\n[\n    input {input}(void) : \"\"\n]\n"),
+            class_name,
+        }
+    }
+
+    #[test]
+    fn test_merge_fgd_replaces_entry() {
+        let hand_written = "@PointClass = my_entity : \"Not generated\"\n[\n]\n";
+        let first = merge_fgd(hand_written, &entry("a", "Old")).unwrap();
+        let both = merge_fgd(&first, &entry("b", "Other")).unwrap();
+        let replaced = merge_fgd(&both, &entry("a", "New")).unwrap();
+
+        assert!(replaced.starts_with(hand_written));
+        assert!(replaced.contains("input New(void)"));
+        assert!(!replaced.contains("input Old(void)"));
+        assert!(replaced.contains("input Other(void)"));
+        assert_eq!(replaced.matches("@BaseClass = pulse_graph_a").count(), 1);
+        assert_eq!(replaced.matches("@PointClass base(").count(), 1);
+        assert!(replaced.contains("@PointClass base(Targetname, pulse_graph_a, pulse_graph_b) "));
+        // exporting the same graph again changes nothing
+        assert_eq!(merge_fgd(&replaced, &entry("a", "New")).unwrap(), replaced);
+    }
+
+    #[test]
+    fn test_merge_fgd_broken_markers() {
+        for contents in [
+            format!("{BEGIN_MARKER}\n"),
+            format!("{END_MARKER}\n"),
+            format!("{END_MARKER}\n{BEGIN_MARKER}\n"),
+        ] {
+            let error = merge_fgd(&contents, &entry("a", "Run")).unwrap_err();
+            assert!(error.to_string().contains("markers"), "{error}");
+        }
+    }
+
+    #[test]
+    fn test_merge_fgd_existing_point_pulse() {
+        let contents = format!("@PointClass base(Targetname) {POINT_PULSE_CLASS}\n");
+        let error = merge_fgd(&contents, &entry("a", "Run")).unwrap_err();
+        assert!(error.to_string().contains("already defines point_pulse"), "{error}");
+    }
+
+    #[test]
+    fn test_fgd_entry_rejects_bad_names() {
+        let mut full_state = FullGraphState::default();
+        full_state.user_state.save_file_path = Some("graph.ron".into());
+        let method = full_state.add_node_at(PulseNodeTemplate::CellPublicMethod, egui::Pos2::ZERO, egui::vec2(200.0, 200.0));
+        let name = full_state.state.graph.nodes[method].get_input("name").unwrap();
+        full_state.state.graph.get_input_mut(name).value = PulseGraphValueType::String { value: "Run".into() };
+        assert!(full_state.fgd_entry().is_ok());
+
+        full_state.state.graph.get_input_mut(name).value = PulseGraphValueType::String { value: "Run it".into() };
+        let error = full_state.fgd_entry().err().unwrap();
+        assert!(error.to_string().contains("'Run it'"), "{error}");
+    }
+}
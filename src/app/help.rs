@@ -45,6 +45,18 @@ In the future this may be automated by this node.".into(),
         PulseNodeTemplate::CallNode => "Allows to call remote nodes from anywhere. For example a 'Function'.".into(),
         PulseNodeTemplate::ListenForEntityOutput => "Listens to an output from the provided entity in the current map, causing an action if it gets triggered. Also provides the activator entity handle.".into(),
         PulseNodeTemplate::Timeline => "Runs actions in a sequential order with a delay between each action.".into(),
+        PulseNodeTemplate::CombineFlags => "Sets the chosen flags on a flag enum value (like damage types), or removes them if 'clear' is checked.".into(),
+        PulseNodeTemplate::TestFlags => "Checks if all of the chosen flags are set on a flag enum value (like damage types).".into(),
+        PulseNodeTemplate::Reroute => "Passes the connection through without changing it, to keep long wires readable. \
+            Double click a wire to insert one.".into(),
+        PulseNodeTemplate::Macro { .. } => "A group of nodes collapsed into one. Click 'Edit macro' to change its contents. \
+            The nodes get expanded in place when compiling.".into(),
+        PulseNodeTemplate::MacroInputs => "Values and actions coming into the macro.".into(),
+        PulseNodeTemplate::MacroOutputs => "Values and actions going out of the macro.".into(),
+        PulseNodeTemplate::LibraryMacro { .. } => "A macro from an imported library. Edit the library file to change it, \
+            changes are picked up on the next compile.".into(),
+        PulseNodeTemplate::LibraryFunction { .. } => "Calls a function from an imported library. \
+            The function gets included in the compiled graph.".into(),
         PulseNodeTemplate::NewArray => "Creates a new array of the provided type. You can also add initial values if applicable to the type, otherwise they may be added later at runtime.".into(),
         PulseNodeTemplate::LibraryBindingAssigned { binding } => {
             user_state
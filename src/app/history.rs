@@ -0,0 +1,1120 @@
+// Undo history. Instead of keeping a copy of the whole state for every step, the editor is compared with a
+// snapshot of how it looked after the last step every frame, and only what changed gets recorded: nodes
+// that were added, removed or edited, moves, connections, variables and so on. Undoing a step applies the
+// "before" side of its changes, redoing it the "after" side.
+//
+// Slotmap keys can't be reused, so nodes that come back from an undo or redo get new ids. The ids in the
+// rest of the history are updated to match when that happens.
+use std::collections::HashMap;
+use eframe::egui;
+use egui_node_graph2::*;
+use crate::pulsetypes::{OutputDefinition, PulseVariable};
+use super::types::*;
+use super::{FullGraphState, PulseGraphEditor};
+
+const MAX_STEPS: usize = 100;
+// changes are recorded once nothing changed for this long, so typing a value is a single step
+const STABLE_TIME: f64 = 0.2;
+// moves smaller than this (in unzoomed units) don't count
+const MOVE_EPSILON: f32 = 0.5;
+
+#[derive(Clone, PartialEq)]
+struct NodeRecord {
+    node: Node<PulseNodeData>,
+    inputs: Vec<InputParam<PulseDataType, PulseGraphValueType>>,
+    outputs: Vec<OutputParam<PulseDataType>>,
+}
+
+impl NodeRecord {
+    fn new(graph: &PulseGraph, node: &Node<PulseNodeData>) -> Box<Self> {
+        Box::new(Self {
+            node: node.clone(),
+            inputs: node.inputs.iter().map(|(_, id)| graph.get_input(*id).clone()).collect(),
+            outputs: node.outputs.iter().map(|(_, id)| graph.get_output(*id).clone()).collect(),
+        })
+    }
+
+    // compares without making a record, this runs for every node every frame
+    fn matches(&self, graph: &PulseGraph, node: &Node<PulseNodeData>) -> bool {
+        self.node == *node
+            && self.inputs.iter().all(|input| graph.inputs.get(input.id) == Some(input))
+            && self.outputs.iter().all(|output| graph.outputs.get(output.id) == Some(output))
+    }
+
+    fn remap(&mut self, ids: &IdMap) {
+        let node = &mut self.node;
+        node.id = ids.node(node.id);
+        for (_, input_id) in node.inputs.iter_mut() {
+            *input_id = ids.input(*input_id);
+        }
+        for (_, output_id) in node.outputs.iter_mut() {
+            *output_id = ids.output(*output_id);
+        }
+        for input_id in node.user_data.added_inputs.iter_mut() {
+            *input_id = ids.input(*input_id);
+        }
+        for input in self.inputs.iter_mut() {
+            input.id = ids.input(input.id);
+            input.node = ids.node(input.node);
+            ids.remap_value(&mut input.value);
+        }
+        for output in self.outputs.iter_mut() {
+            output.id = ids.output(output.id);
+            output.node = ids.node(output.node);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct NodeLayout {
+    // unzoomed, see PanZoom::to_graph_space
+    position: egui::Pos2,
+    size: Option<egui::Vec2>,
+}
+
+impl NodeLayout {
+    fn of(state: &MyEditorState, node_id: NodeId) -> Option<Self> {
+        let position = state.node_positions.get(node_id)?;
+        Some(Self {
+            position: state.pan_zoom.to_graph_space(*position),
+            size: state.node_sizes.get(node_id).copied(),
+        })
+    }
+
+    fn is_near(&self, other: &Self) -> bool {
+        (self.position - other.position).length() < MOVE_EPSILON && self.size == other.size
+    }
+}
+
+// group frames, with their rects in the unzoomed graph space
+pub(super) fn frames_of(state: &MyEditorState) -> Vec<GroupFrame> {
+    state.group_frames.iter()
+        .map(|frame| GroupFrame {
+            rect: egui::Rect::from_min_max(
+                state.pan_zoom.to_graph_space(frame.rect.min),
+                state.pan_zoom.to_graph_space(frame.rect.max),
+            ),
+            ..frame.clone()
+        })
+        .collect()
+}
+
+fn frames_near(a: &[GroupFrame], b: &[GroupFrame]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| {
+        a.title == b.title
+            && a.color == b.color
+            && a.collapsed == b.collapsed
+            && (a.rect.min - b.rect.min).length() < MOVE_EPSILON
+            && (a.rect.max - b.rect.max).length() < MOVE_EPSILON
+    })
+}
+
+#[derive(Clone, PartialEq)]
+struct Diff<T> {
+    before: T,
+    after: T,
+}
+
+impl<T> Diff<T> {
+    fn side(&self, undo: bool) -> &T {
+        if undo { &self.before } else { &self.after }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+enum Change {
+    Node(NodeId, Diff<Option<Box<NodeRecord>>>),
+    Layout(NodeId, Diff<Option<NodeLayout>>),
+    Connections(InputId, Diff<Vec<OutputId>>),
+    ExposedNode(NodeId, Diff<Option<String>>),
+    GroupFrames(Diff<Vec<GroupFrame>>),
+    Variables(Diff<Vec<PulseVariable>>),
+    PublicOutputs(Diff<Vec<OutputDefinition>>),
+    // a macro that was added, removed, or had its graph changed, with its place in the list
+    Macro(MacroId, Diff<Option<(usize, Box<MacroDefinition>)>>),
+    // only the name or ports of a macro changed
+    MacroHeader(MacroId, Diff<MacroHeader>),
+    Libraries(Diff<Vec<LibraryImport>>),
+    // graph domain and subtype
+    GraphType(Diff<(String, String)>),
+    // entering or leaving a macro, always a step of its own
+    Navigate(Diff<Vec<MacroId>>),
+}
+
+impl Change {
+    fn remap(&mut self, ids: &IdMap) {
+        match self {
+            Change::Node(node_id, diff) => {
+                *node_id = ids.node(*node_id);
+                for record in [&mut diff.before, &mut diff.after].into_iter().flatten() {
+                    record.remap(ids);
+                }
+            }
+            Change::Layout(node_id, _) | Change::ExposedNode(node_id, _) => *node_id = ids.node(*node_id),
+            Change::Connections(input_id, diff) => {
+                *input_id = ids.input(*input_id);
+                for output_id in diff.before.iter_mut().chain(diff.after.iter_mut()) {
+                    *output_id = ids.output(*output_id);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct MacroHeader {
+    name: String,
+    inputs: Vec<MacroPort>,
+    outputs: Vec<MacroPort>,
+}
+
+impl MacroHeader {
+    fn of(def: &MacroDefinition) -> Self {
+        Self { name: def.name.clone(), inputs: def.inputs.clone(), outputs: def.outputs.clone() }
+    }
+
+    fn write(&self, macros: &mut [MacroDefinition], id: MacroId) {
+        if let Some(def) = macros.iter_mut().find(|def| def.id == id) {
+            def.name = self.name.clone();
+            def.inputs = self.inputs.clone();
+            def.outputs = self.outputs.clone();
+        }
+    }
+}
+
+fn write_macro(macros: &mut Vec<MacroDefinition>, id: MacroId, side: &Option<(usize, Box<MacroDefinition>)>) {
+    macros.retain(|def| def.id != id);
+    if let Some((idx, def)) = side {
+        macros.insert((*idx).min(macros.len()), def.as_ref().clone());
+    }
+}
+
+// What changed in the macro list. Macros are compared one by one, and only the ones that changed are copied.
+fn macro_changes(before: &[MacroDefinition], after: &[MacroDefinition]) -> Vec<Change> {
+    let mut changes = vec![];
+    for (idx, def) in after.iter().enumerate() {
+        let old = before.iter().enumerate().find(|(_, old)| old.id == def.id);
+        match old {
+            Some((_, old)) if old == def => {}
+            Some((_, old)) if old.same_graph(def) => {
+                changes.push(Change::MacroHeader(def.id, Diff { before: MacroHeader::of(old), after: MacroHeader::of(def) }));
+            }
+            _ => changes.push(Change::Macro(def.id, Diff {
+                before: old.map(|(old_idx, old)| (old_idx, Box::new(old.clone()))),
+                after: Some((idx, Box::new(def.clone()))),
+            })),
+        }
+    }
+    for (idx, old) in before.iter().enumerate() {
+        if !after.iter().any(|def| def.id == old.id) {
+            changes.push(Change::Macro(old.id, Diff { before: Some((idx, Box::new(old.clone()))), after: None }));
+        }
+    }
+    changes
+}
+
+// new ids of nodes and ports that were created again
+#[derive(Default)]
+struct IdMap {
+    nodes: HashMap<NodeId, NodeId>,
+    inputs: HashMap<InputId, InputId>,
+    outputs: HashMap<OutputId, OutputId>,
+}
+
+impl IdMap {
+    fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.inputs.is_empty() && self.outputs.is_empty()
+    }
+    fn node(&self, id: NodeId) -> NodeId {
+        self.nodes.get(&id).copied().unwrap_or(id)
+    }
+    fn input(&self, id: InputId) -> InputId {
+        self.inputs.get(&id).copied().unwrap_or(id)
+    }
+    fn output(&self, id: OutputId) -> OutputId {
+        self.outputs.get(&id).copied().unwrap_or(id)
+    }
+    fn remap_value(&self, value: &mut PulseGraphValueType) {
+        if let PulseGraphValueType::NodeChoice { node: Some(node_id) } = value {
+            *node_id = self.node(*node_id);
+        }
+    }
+}
+
+// How the editor looked after the last recorded step.
+#[derive(Clone)]
+struct Snapshot {
+    macro_stack: Vec<MacroId>,
+    nodes: HashMap<NodeId, Box<NodeRecord>>,
+    layout: HashMap<NodeId, NodeLayout>,
+    connections: HashMap<InputId, Vec<OutputId>>,
+    exposed_nodes: HashMap<NodeId, String>,
+    group_frames: Vec<GroupFrame>,
+    variables: Vec<PulseVariable>,
+    public_outputs: Vec<OutputDefinition>,
+    macros: Vec<MacroDefinition>,
+    libraries: Vec<LibraryImport>,
+    graph_type: (String, String),
+}
+
+impl Snapshot {
+    fn take(full_state: &FullGraphState) -> Self {
+        let FullGraphState { state, user_state, .. } = full_state;
+        let graph = &state.graph;
+        Self {
+            macro_stack: user_state.macro_stack.clone(),
+            nodes: graph.nodes.iter().map(|(node_id, node)| (node_id, NodeRecord::new(graph, node))).collect(),
+            layout: graph.nodes.keys()
+                .filter_map(|node_id| Some((node_id, NodeLayout::of(state, node_id)?)))
+                .collect(),
+            connections: graph.connections.iter()
+                .filter(|(_, outputs)| !outputs.is_empty())
+                .map(|(input_id, outputs)| (input_id, outputs.clone()))
+                .collect(),
+            exposed_nodes: user_state.exposed_nodes.iter().map(|(node_id, name)| (node_id, name.clone())).collect(),
+            group_frames: frames_of(state),
+            variables: user_state.variables.clone(),
+            public_outputs: user_state.public_outputs.clone(),
+            macros: user_state.macros.clone(),
+            libraries: user_state.libraries.clone(),
+            graph_type: (user_state.graph_domain.clone(), user_state.graph_subtype.clone()),
+        }
+    }
+
+    // What changed in the editor since the snapshot was taken.
+    fn changes(&self, full_state: &FullGraphState) -> Vec<Change> {
+        let FullGraphState { state, user_state, .. } = full_state;
+        let graph = &state.graph;
+        if user_state.macro_stack != self.macro_stack {
+            return vec![Change::Navigate(Diff {
+                before: self.macro_stack.clone(),
+                after: user_state.macro_stack.clone(),
+            })];
+        }
+        let mut changes = vec![];
+        for (node_id, node) in graph.nodes.iter() {
+            let before = self.nodes.get(&node_id);
+            if !before.is_some_and(|record| record.matches(graph, node)) {
+                changes.push(Change::Node(node_id, Diff {
+                    before: before.cloned(),
+                    after: Some(NodeRecord::new(graph, node)),
+                }));
+            }
+        }
+        for (node_id, record) in self.nodes.iter() {
+            if !graph.nodes.contains_key(*node_id) {
+                changes.push(Change::Node(*node_id, Diff { before: Some(record.clone()), after: None }));
+            }
+        }
+
+        for node_id in graph.nodes.keys() {
+            let (before, after) = (self.layout.get(&node_id).copied(), NodeLayout::of(state, node_id));
+            let unchanged = match (&before, &after) {
+                (Some(before), Some(after)) => before.is_near(after),
+                (before, after) => before == after,
+            };
+            if !unchanged {
+                changes.push(Change::Layout(node_id, Diff { before, after }));
+            }
+        }
+        for (node_id, layout) in self.layout.iter() {
+            if !graph.nodes.contains_key(*node_id) {
+                changes.push(Change::Layout(*node_id, Diff { before: Some(*layout), after: None }));
+            }
+        }
+
+        for (input_id, outputs) in graph.connections.iter() {
+            let before = self.connections.get(&input_id).map_or(&[][..], |before| before.as_slice());
+            if before != outputs.as_slice() {
+                changes.push(Change::Connections(input_id, Diff { before: before.to_vec(), after: outputs.clone() }));
+            }
+        }
+        for (input_id, outputs) in self.connections.iter() {
+            if graph.connections.get(*input_id).is_none() {
+                changes.push(Change::Connections(*input_id, Diff { before: outputs.clone(), after: vec![] }));
+            }
+        }
+
+        for (node_id, name) in user_state.exposed_nodes.iter() {
+            if self.exposed_nodes.get(&node_id) != Some(name) {
+                changes.push(Change::ExposedNode(node_id, Diff {
+                    before: self.exposed_nodes.get(&node_id).cloned(),
+                    after: Some(name.clone()),
+                }));
+            }
+        }
+        for (node_id, name) in self.exposed_nodes.iter() {
+            if !user_state.exposed_nodes.contains_key(*node_id) {
+                changes.push(Change::ExposedNode(*node_id, Diff { before: Some(name.clone()), after: None }));
+            }
+        }
+
+        let group_frames = frames_of(state);
+        if !frames_near(&self.group_frames, &group_frames) {
+            changes.push(Change::GroupFrames(Diff { before: self.group_frames.clone(), after: group_frames }));
+        }
+        if self.variables != user_state.variables {
+            changes.push(Change::Variables(Diff { before: self.variables.clone(), after: user_state.variables.clone() }));
+        }
+        if self.public_outputs != user_state.public_outputs {
+            changes.push(Change::PublicOutputs(Diff {
+                before: self.public_outputs.clone(),
+                after: user_state.public_outputs.clone(),
+            }));
+        }
+        if self.macros != user_state.macros {
+            changes.extend(macro_changes(&self.macros, &user_state.macros));
+        }
+        if self.libraries != user_state.libraries {
+            changes.push(Change::Libraries(Diff { before: self.libraries.clone(), after: user_state.libraries.clone() }));
+        }
+        let graph_type = (user_state.graph_domain.clone(), user_state.graph_subtype.clone());
+        if self.graph_type != graph_type {
+            changes.push(Change::GraphType(Diff { before: self.graph_type.clone(), after: graph_type }));
+        }
+        changes
+    }
+
+    // updates the snapshot with the "after" side of recorded changes
+    fn record(&mut self, changes: &[Change]) {
+        fn set<K: std::hash::Hash + Eq, V: Clone>(map: &mut HashMap<K, V>, key: K, value: &Option<V>) {
+            match value {
+                Some(value) => map.insert(key, value.clone()),
+                None => map.remove(&key),
+            };
+        }
+        for change in changes {
+            match change {
+                Change::Node(node_id, diff) => set(&mut self.nodes, *node_id, &diff.after),
+                Change::Layout(node_id, diff) => set(&mut self.layout, *node_id, &diff.after),
+                Change::Connections(input_id, diff) => {
+                    let outputs = Some(diff.after.clone()).filter(|outputs| !outputs.is_empty());
+                    set(&mut self.connections, *input_id, &outputs);
+                }
+                Change::ExposedNode(node_id, diff) => set(&mut self.exposed_nodes, *node_id, &diff.after),
+                Change::GroupFrames(diff) => self.group_frames = diff.after.clone(),
+                Change::Variables(diff) => self.variables = diff.after.clone(),
+                Change::PublicOutputs(diff) => self.public_outputs = diff.after.clone(),
+                Change::Macro(id, diff) => write_macro(&mut self.macros, *id, &diff.after),
+                Change::MacroHeader(id, diff) => diff.after.write(&mut self.macros, *id),
+                Change::Libraries(diff) => self.libraries = diff.after.clone(),
+                Change::GraphType(diff) => self.graph_type = diff.after.clone(),
+                Change::Navigate(diff) => self.macro_stack = diff.after.clone(),
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HistoryStep {
+    pub label: String,
+    // tells the steps apart, to know if the graph is back where it was saved
+    id: u64,
+    // the graph the changes were made in, ids from other graphs may look the same
+    macro_stack: Vec<MacroId>,
+    changes: Vec<Change>,
+}
+
+impl HistoryStep {
+    fn remap(&mut self, ids: &IdMap) {
+        for change in self.changes.iter_mut() {
+            change.remap(ids);
+        }
+    }
+
+    // Puts the editor in the state from before (undo) or after the step. Returns the ids of the nodes
+    // and ports that had to be created again.
+    fn apply(&mut self, full_state: &mut FullGraphState, undo: bool) -> IdMap {
+        if let [Change::Navigate(diff)] = self.changes.as_slice() {
+            navigate(full_state, diff.side(undo));
+            return IdMap::default();
+        }
+        let ids = self.restore_nodes(full_state, undo);
+        if !ids.is_empty() {
+            self.remap(&ids);
+        }
+        let FullGraphState { state, user_state, .. } = full_state;
+        // node contents first, then removed nodes, then everything that refers to the nodes
+        for change in self.changes.iter() {
+            if let Change::Node(_, diff) = change {
+                if let Some(record) = diff.side(undo) {
+                    write_node(&mut state.graph, record);
+                }
+            }
+        }
+        for change in self.changes.iter() {
+            if let Change::Node(node_id, diff) = change {
+                if diff.side(undo).is_none() && state.graph.nodes.contains_key(*node_id) {
+                    state.graph.remove_node(*node_id);
+                    state.node_positions.remove(*node_id);
+                    state.node_sizes.remove(*node_id);
+                    state.node_order.retain(|id| id != node_id);
+                    state.selected_nodes.retain(|id| id != node_id);
+                }
+            }
+        }
+        for change in self.changes.iter() {
+            match change {
+                Change::Node(..) | Change::Navigate(_) => {}
+                Change::Layout(node_id, diff) => match diff.side(undo) {
+                    Some(layout) if state.graph.nodes.contains_key(*node_id) => {
+                        state.node_positions.insert(*node_id, state.pan_zoom.from_graph_space(layout.position));
+                        match layout.size {
+                            Some(size) => state.node_sizes.insert(*node_id, size),
+                            None => state.node_sizes.remove(*node_id),
+                        };
+                    }
+                    _ => {
+                        state.node_positions.remove(*node_id);
+                        state.node_sizes.remove(*node_id);
+                    }
+                },
+                Change::Connections(input_id, diff) => {
+                    let graph = &mut state.graph;
+                    if !graph.inputs.contains_key(*input_id) {
+                        continue;
+                    }
+                    let outputs: Vec<OutputId> = diff.side(undo).iter()
+                        .copied()
+                        .filter(|output_id| graph.outputs.contains_key(*output_id))
+                        .collect();
+                    graph.connections.insert(*input_id, outputs);
+                }
+                Change::ExposedNode(node_id, diff) => match diff.side(undo) {
+                    Some(name) => {
+                        user_state.exposed_nodes.insert(*node_id, name.clone());
+                    }
+                    None => {
+                        user_state.exposed_nodes.remove(*node_id);
+                    }
+                },
+                Change::GroupFrames(diff) => {
+                    let pan_zoom = &state.pan_zoom;
+                    state.group_frames = diff.side(undo).iter()
+                        .map(|frame| GroupFrame {
+                            rect: egui::Rect::from_min_max(
+                                pan_zoom.from_graph_space(frame.rect.min),
+                                pan_zoom.from_graph_space(frame.rect.max),
+                            ),
+                            ..frame.clone()
+                        })
+                        .collect();
+                }
+                Change::Variables(diff) => user_state.variables = diff.side(undo).clone(),
+                Change::PublicOutputs(diff) => user_state.public_outputs = diff.side(undo).clone(),
+                Change::Macro(id, diff) => write_macro(&mut user_state.macros, *id, diff.side(undo)),
+                Change::MacroHeader(id, diff) => diff.side(undo).write(&mut user_state.macros, *id),
+                Change::Libraries(diff) => user_state.libraries = diff.side(undo).clone(),
+                Change::GraphType(diff) => {
+                    (user_state.graph_domain, user_state.graph_subtype) = diff.side(undo).clone();
+                }
+            }
+        }
+        ids
+    }
+
+    // Creates the nodes and ports that the step needs but that don't exist. Their contents are filled in
+    // by write_node, once the ids in the step are updated.
+    fn restore_nodes(&self, full_state: &mut FullGraphState, undo: bool) -> IdMap {
+        let mut ids = IdMap::default();
+        let state = &mut full_state.state;
+        for change in self.changes.iter() {
+            let Change::Node(node_id, diff) = change else {
+                continue;
+            };
+            let Some(record) = diff.side(undo) else {
+                continue;
+            };
+            let graph = &mut state.graph;
+            let node_id = if graph.nodes.contains_key(*node_id) {
+                *node_id
+            } else {
+                let new_id = graph.add_node(record.node.label.clone(), record.node.user_data.clone(), |_, _| {});
+                ids.nodes.insert(*node_id, new_id);
+                state.node_order.push(new_id);
+                new_id
+            };
+            for input in record.inputs.iter() {
+                if !graph.inputs.contains_key(input.id) {
+                    let new_id = graph.inputs.insert_with_key(|id| InputParam { id, node: node_id, ..input.clone() });
+                    ids.inputs.insert(input.id, new_id);
+                }
+            }
+            for output in record.outputs.iter() {
+                if !graph.outputs.contains_key(output.id) {
+                    let new_id = graph.outputs.insert_with_key(|id| OutputParam { id, node: node_id, ..output.clone() });
+                    ids.outputs.insert(output.id, new_id);
+                }
+            }
+        }
+        if !ids.is_empty() {
+            // nodes pointing to the node that came back
+            for input in state.graph.inputs.values_mut() {
+                ids.remap_value(&mut input.value);
+            }
+        }
+        ids
+    }
+}
+
+// Sets a node that exists to what's in the record, removing the ports it doesn't have.
+fn write_node(graph: &mut PulseGraph, record: &NodeRecord) {
+    let node_id = record.node.id;
+    let Some(node) = graph.nodes.get(node_id) else {
+        return;
+    };
+    let stale_inputs: Vec<InputId> = node.inputs.iter()
+        .map(|(_, id)| *id)
+        .filter(|id| !record.inputs.iter().any(|input| input.id == *id))
+        .collect();
+    let stale_outputs: Vec<OutputId> = node.outputs.iter()
+        .map(|(_, id)| *id)
+        .filter(|id| !record.outputs.iter().any(|output| output.id == *id))
+        .collect();
+    for input_id in stale_inputs {
+        graph.remove_input_param(input_id);
+    }
+    for output_id in stale_outputs {
+        graph.remove_output_param(output_id);
+    }
+    graph.nodes[node_id] = record.node.clone();
+    for input in record.inputs.iter() {
+        graph.inputs[input.id] = input.clone();
+    }
+    for output in record.outputs.iter() {
+        graph.outputs[output.id] = output.clone();
+    }
+}
+
+fn navigate(full_state: &mut FullGraphState, macro_stack: &[MacroId]) {
+    while !macro_stack.starts_with(&full_state.user_state.macro_stack) {
+        full_state.exit_macro();
+    }
+    let depth = full_state.user_state.macro_stack.len();
+    for id in macro_stack[depth..].iter() {
+        if let Err(e) = full_state.enter_macro(*id) {
+            println!("[UI] Warning: Failed to open macro: {e}");
+            break;
+        }
+    }
+}
+
+// Short description of a step for the history list. `snapshot` is from before the step.
+fn describe(changes: &[Change], snapshot: &Snapshot, user_state: &PulseGraphState) -> String {
+    let count = |n: usize, one: String, many: &str| if n == 1 { one } else { format!("{n} {many}") };
+    let mut added = vec![];
+    let mut removed = vec![];
+    let mut edited = vec![];
+    let mut moved = vec![];
+    for change in changes {
+        match change {
+            Change::Navigate(diff) => {
+                return match diff.after.last() {
+                    Some(id) if diff.after.len() > diff.before.len() => {
+                        let name = user_state.find_macro(*id).map_or("macro", |macro_def| &macro_def.name);
+                        format!("Open {name}")
+                    }
+                    _ => "Leave macro".to_string(),
+                };
+            }
+            Change::Node(_, Diff { before: None, after: Some(record) }) => added.push(record),
+            Change::Node(_, Diff { before: Some(record), after: None }) => removed.push(record),
+            Change::Node(_, Diff { before: Some(before), after: Some(after) }) => edited.push((before, after)),
+            Change::Layout(node_id, Diff { before: Some(_), after: Some(_) }) => moved.push(*node_id),
+            _ => {}
+        }
+    }
+    if !removed.is_empty() {
+        return count(removed.len(), format!("Delete {}", removed[0].node.label), "nodes deleted");
+    }
+    if !added.is_empty() {
+        return count(added.len(), format!("Add {}", added[0].node.label), "nodes added");
+    }
+    if let [(before, after)] = edited.as_slice() {
+        let changed_inputs: Vec<&str> = after.node.inputs.iter()
+            .filter(|(_, id)| {
+                let before_input = before.inputs.iter().find(|input| input.id == *id);
+                let after_input = after.inputs.iter().find(|input| input.id == *id);
+                before_input.map(|input| &input.value) != after_input.map(|input| &input.value)
+            })
+            .map(|(name, _)| name.as_str())
+            .collect();
+        return match changed_inputs.as_slice() {
+            [name] if before.node.inputs == after.node.inputs => format!("Set {name} of {}", after.node.label),
+            _ => format!("Edit {}", after.node.label),
+        };
+    }
+    if !edited.is_empty() {
+        return format!("Edit {} nodes", edited.len());
+    }
+    let first_other = changes.iter().find(|change| !matches!(change, Change::Layout(..)));
+    match first_other {
+        None if !moved.is_empty() => {
+            let label = snapshot.nodes.get(&moved[0]).map_or("node", |record| &record.node.label);
+            count(moved.len(), format!("Move {label}"), "nodes moved")
+        }
+        None => "Edit layout".to_string(),
+        Some(Change::Connections(..)) => {
+            let (mut before, mut after) = (0, 0);
+            for change in changes {
+                if let Change::Connections(_, diff) = change {
+                    before += diff.before.len();
+                    after += diff.after.len();
+                }
+            }
+            match after.cmp(&before) {
+                std::cmp::Ordering::Greater => "Connect".to_string(),
+                std::cmp::Ordering::Less => "Disconnect".to_string(),
+                std::cmp::Ordering::Equal => "Change connections".to_string(),
+            }
+        }
+        Some(Change::ExposedNode(..)) => "Rename node".to_string(),
+        Some(Change::GroupFrames(diff)) => match diff.after.len().cmp(&diff.before.len()) {
+            std::cmp::Ordering::Greater => "Add frame".to_string(),
+            std::cmp::Ordering::Less => "Remove frame".to_string(),
+            std::cmp::Ordering::Equal => "Edit frame".to_string(),
+        },
+        Some(Change::Variables(diff)) => describe_list("variable", &diff.before, &diff.after, |var| &var.name),
+        Some(Change::PublicOutputs(diff)) => describe_list("output", &diff.before, &diff.after, |output| &output.name),
+        Some(Change::Macro(_, Diff { before: None, .. })) => "Add macro".to_string(),
+        Some(Change::Macro(_, Diff { before: Some((_, def)), after: None })) => format!("Remove macro {}", def.name),
+        Some(Change::Macro(_, Diff { after: Some((_, def)), .. })) => format!("Edit macro {}", def.name),
+        Some(Change::MacroHeader(_, diff)) => format!("Edit macro {}", diff.after.name),
+        Some(Change::Libraries(_)) => "Change libraries".to_string(),
+        Some(Change::GraphType(_)) => "Change graph type".to_string(),
+        Some(_) => "Edit graph".to_string(),
+    }
+}
+
+fn describe_list<T: PartialEq>(what: &str, before: &[T], after: &[T], name: impl Fn(&T) -> &String) -> String {
+    if after.len() > before.len() {
+        return format!("Add {what}");
+    }
+    if after.len() < before.len() {
+        return match before.iter().find(|item| !after.contains(item)) {
+            Some(item) => format!("Remove {what} {}", name(item)),
+            None => format!("Remove {what}"),
+        };
+    }
+    match after.iter().zip(before.iter()).find(|(after, before)| after != before) {
+        Some((item, _)) => format!("Edit {what} {}", name(item)),
+        None => format!("Reorder {what}s"),
+    }
+}
+
+// changes that were seen but aren't recorded yet, because they may still be going on
+#[derive(Clone)]
+struct Pending {
+    changes: Vec<Change>,
+    since: f64,
+}
+
+#[derive(Default, Clone)]
+pub struct History {
+    snapshot: Option<Snapshot>,
+    pending: Option<Pending>,
+    undo: Vec<HistoryStep>,
+    redo: Vec<HistoryStep>,
+    next_step_id: u64,
+    // the last applied step when the graph was saved, None if it wasn't saved since the history started
+    saved_at: Option<Option<u64>>,
+    // false when the graph changed since take_changed() was last called
+    checked: bool,
+}
+
+impl History {
+    // history of a graph that matches its file
+    pub fn saved() -> Self {
+        Self { saved_at: Some(None), ..Self::default() }
+    }
+
+    // Called every frame. Records what changed once it stops changing. `busy` holds off recording, like
+    // while a mouse button is held down.
+    pub fn update(&mut self, full_state: &FullGraphState, time: f64, busy: bool) {
+        let Some(snapshot) = &self.snapshot else {
+            self.snapshot = Some(Snapshot::take(full_state));
+            return;
+        };
+        let changes = snapshot.changes(full_state);
+        if changes.is_empty() {
+            self.pending = None;
+            return;
+        }
+        let navigated = matches!(changes.as_slice(), [Change::Navigate(_)]);
+        if navigated {
+            // edits from just before, they were made in the graph that was left
+            if let Some(pending) = self.pending.take() {
+                self.push(full_state, pending.changes, None);
+            }
+        }
+        match &self.pending {
+            // leaving or entering a macro happens at once, anything else gets to settle first
+            _ if navigated => {}
+            Some(pending) if pending.changes == changes => {
+                if busy || time - pending.since < STABLE_TIME {
+                    return;
+                }
+            }
+            _ => {
+                self.pending = Some(Pending { changes, since: time });
+                return;
+            }
+        }
+        self.push(full_state, changes, None);
+    }
+
+    // Records the changes made so far as a step right away.
+    pub fn commit(&mut self, full_state: &FullGraphState, label: Option<&str>) {
+        let Some(snapshot) = &self.snapshot else {
+            self.snapshot = Some(Snapshot::take(full_state));
+            return;
+        };
+        let changes = snapshot.changes(full_state);
+        if !changes.is_empty() {
+            self.push(full_state, changes, label);
+        }
+    }
+
+    fn push(&mut self, full_state: &FullGraphState, changes: Vec<Change>, label: Option<&str>) {
+        let label = match (label, &self.snapshot) {
+            (Some(label), _) => label.to_string(),
+            (None, Some(snapshot)) => describe(&changes, snapshot, &full_state.user_state),
+            (None, None) => "Edit graph".to_string(),
+        };
+        // pending edits can get pushed after navigating away, they belong to the graph from the snapshot
+        let macro_stack = self.snapshot.as_ref()
+            .map_or(&full_state.user_state.macro_stack, |snapshot| &snapshot.macro_stack)
+            .clone();
+        match &mut self.snapshot {
+            Some(snapshot) if !matches!(changes.as_slice(), [Change::Navigate(_)]) => snapshot.record(&changes),
+            // the whole graph was swapped
+            _ => self.snapshot = Some(Snapshot::take(full_state)),
+        }
+        self.pending = None;
+        self.redo.clear();
+        self.undo.push(HistoryStep { label, id: self.next_step_id, macro_stack, changes });
+        self.next_step_id += 1;
+        self.checked = false;
+        if self.undo.len() > MAX_STEPS {
+            self.undo.remove(0);
+        }
+    }
+
+    fn position(&self) -> Option<u64> {
+        self.undo.last().map(|step| step.id)
+    }
+
+    pub fn mark_saved(&mut self, full_state: &FullGraphState) {
+        // edits that are still going on got saved too
+        self.commit(full_state, None);
+        self.saved_at = Some(self.position());
+    }
+
+    // true if there are changes since the last save, including ones that aren't recorded yet
+    pub fn is_dirty(&self) -> bool {
+        self.pending.is_some() || self.saved_at != Some(self.position())
+    }
+
+    // True once after a step was recorded, undone or redone, and for a new history. For work that only
+    // needs to happen when the graph changed.
+    pub fn take_changed(&mut self) -> bool {
+        !std::mem::replace(&mut self.checked, true)
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn has_undo(&self) -> bool {
+        !self.undo.is_empty() || self.pending.is_some()
+    }
+
+    pub fn has_redo(&self) -> bool {
+        !self.redo.is_empty() && self.pending.is_none()
+    }
+
+    pub fn undo_label(&self) -> Option<&str> {
+        self.undo.last().map(|step| step.label.as_str())
+    }
+
+    pub fn redo_label(&self) -> Option<&str> {
+        self.redo.last().map(|step| step.label.as_str())
+    }
+
+    pub fn undo(&mut self, full_state: &mut FullGraphState) {
+        // whatever is still going on becomes a step of its own first
+        self.commit(full_state, None);
+        if let Some(mut step) = self.undo.pop() {
+            let ids = step.apply(full_state, true);
+            let macro_stack = step.macro_stack.clone();
+            self.redo.push(step);
+            self.finish_apply(full_state, &macro_stack, ids);
+        }
+    }
+
+    pub fn redo(&mut self, full_state: &mut FullGraphState) {
+        self.commit(full_state, None);
+        if let Some(mut step) = self.redo.pop() {
+            let ids = step.apply(full_state, false);
+            let macro_stack = step.macro_stack.clone();
+            self.undo.push(step);
+            self.finish_apply(full_state, &macro_stack, ids);
+        }
+    }
+
+    // `macro_stack` is the graph the applied step was made in, only steps from that graph use its ids
+    fn finish_apply(&mut self, full_state: &FullGraphState, macro_stack: &[MacroId], ids: IdMap) {
+        if !ids.is_empty() {
+            for step in self.undo.iter_mut().chain(self.redo.iter_mut()) {
+                if step.macro_stack == macro_stack {
+                    step.remap(&ids);
+                }
+            }
+        }
+        self.snapshot = Some(Snapshot::take(full_state));
+        self.pending = None;
+        self.checked = false;
+    }
+
+    // Undoes or redoes until `position` steps are applied.
+    pub fn go_to(&mut self, full_state: &mut FullGraphState, position: usize) {
+        self.commit(full_state, None);
+        while self.undo.len() > position {
+            self.undo(full_state);
+        }
+        while self.undo.len() < position && !self.redo.is_empty() {
+            self.redo(full_state);
+        }
+    }
+}
+
+impl PulseGraphEditor {
+    pub(super) fn history_ui(&mut self, ui: &mut egui::Ui) {
+        let mut go_to = None;
+        ui.horizontal(|ui| {
+            ui.heading("History");
+            if ui.button("X").on_hover_text("Close").clicked() {
+                self.show_history = false;
+            }
+        });
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            let history = &self.history;
+            let position = history.undo.len();
+            if ui.selectable_label(position == 0 && history.pending.is_none(), "Start").clicked() {
+                go_to = Some(0);
+            }
+            for (idx, step) in history.undo.iter().enumerate() {
+                let current = idx + 1 == position && history.pending.is_none();
+                if ui.selectable_label(current, &step.label).clicked() {
+                    go_to = Some(idx + 1);
+                }
+            }
+            if history.pending.is_some() {
+                ui.add_enabled(false, egui::Button::selectable(true, "(editing)"));
+            }
+            // undone steps, can still be redone
+            for (idx, step) in history.redo.iter().rev().enumerate() {
+                let label = egui::RichText::new(&step.label).weak();
+                if ui.selectable_label(false, label).clicked() {
+                    go_to = Some(position + idx + 1);
+                }
+            }
+        });
+        if let Some(position) = go_to {
+            self.history.go_to(&mut self.full_state, position);
+            self.state_mut().connection_in_progress = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_log(full_state: &mut FullGraphState, x: f32) -> NodeId {
+        full_state.add_node_at(PulseNodeTemplate::DebugLog, egui::pos2(x, 0.0), egui::vec2(100.0, 50.0))
+    }
+
+    fn connect(full_state: &mut FullGraphState, from: NodeId, to: NodeId) {
+        let graph = &mut full_state.state.graph;
+        let output = graph.nodes[from].get_output("outAction").unwrap();
+        let input = graph.nodes[to].get_input("ActionIn").unwrap();
+        graph.add_connection(output, input, 0);
+    }
+
+    fn remove(full_state: &mut FullGraphState, node_id: NodeId) {
+        let state = &mut full_state.state;
+        state.graph.remove_node(node_id);
+        state.node_positions.remove(node_id);
+        state.node_sizes.remove(node_id);
+        state.node_order.retain(|id| *id != node_id);
+    }
+
+    fn position(full_state: &FullGraphState, node_id: NodeId) -> egui::Pos2 {
+        full_state.state.node_positions[node_id]
+    }
+
+    // node positions and how they are connected, which doesn't depend on the node ids
+    fn outline(full_state: &FullGraphState) -> Vec<(egui::Pos2, Vec<egui::Pos2>)> {
+        let graph = &full_state.state.graph;
+        let mut nodes: Vec<_> = graph.nodes.iter()
+            .map(|(node_id, node)| {
+                let mut next: Vec<_> = node.outputs.iter()
+                    .flat_map(|(_, output_id)| graph.iter_connections()
+                        .filter(move |(_, output)| output == output_id)
+                        .map(|(input, _)| position(full_state, graph.get_input(input).node)))
+                    .collect();
+                next.sort_by(|a, b| a.x.total_cmp(&b.x));
+                (position(full_state, node_id), next)
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.0.x.total_cmp(&b.0.x));
+        nodes
+    }
+
+    #[test]
+    fn test_undo_redo_restores_graph() {
+        let mut full_state = FullGraphState::default();
+        let mut history = History::default();
+        history.commit(&full_state, None);
+        let mut outlines = vec![outline(&full_state)];
+
+        let a = add_log(&mut full_state, 0.0);
+        let b = add_log(&mut full_state, 200.0);
+        history.commit(&full_state, Some("Add"));
+        outlines.push(outline(&full_state));
+        connect(&mut full_state, a, b);
+        history.commit(&full_state, Some("Connect"));
+        outlines.push(outline(&full_state));
+        let c = add_log(&mut full_state, 400.0);
+        connect(&mut full_state, b, c);
+        history.commit(&full_state, Some("Add and connect"));
+        outlines.push(outline(&full_state));
+        remove(&mut full_state, b);
+        history.commit(&full_state, Some("Delete"));
+        outlines.push(outline(&full_state));
+        remove(&mut full_state, a);
+        history.commit(&full_state, Some("Delete"));
+        outlines.push(outline(&full_state));
+
+        // all the way back and forth twice, the restored nodes get new ids every time
+        for _ in 0..2 {
+            for expected in outlines.iter().rev().skip(1) {
+                history.undo(&mut full_state);
+                assert_eq!(outline(&full_state), *expected);
+            }
+            for expected in outlines.iter().skip(1) {
+                history.redo(&mut full_state);
+                assert_eq!(outline(&full_state), *expected);
+            }
+        }
+        history.go_to(&mut full_state, 3);
+        assert_eq!(outline(&full_state), outlines[3]);
+    }
+
+    #[test]
+    fn test_undo_only_remaps_ids_in_same_graph() {
+        let mut full_state = FullGraphState::default();
+        let collapsed = add_log(&mut full_state, 0.0);
+        full_state.collapse_to_macro(&[collapsed]).unwrap();
+        let macro_id = full_state.user_state.macros[0].id;
+        let mut history = History::default();
+        history.commit(&full_state, None);
+
+        full_state.enter_macro(macro_id).unwrap();
+        history.commit(&full_state, None);
+        let inner = add_log(&mut full_state, 500.0);
+        history.commit(&full_state, Some("Add"));
+        full_state.state.node_positions[inner] = egui::pos2(700.0, 0.0);
+        history.commit(&full_state, Some("Move"));
+        full_state.exit_macro();
+        history.commit(&full_state, None);
+
+        // a node in the top level graph with the same id as the one in the macro
+        let outer = std::iter::repeat_with(|| add_log(&mut full_state, 0.0))
+            .take(16)
+            .find(|node_id| *node_id == inner)
+            .expect("slotmaps should hand out the same ids");
+        history.commit(&full_state, Some("Add"));
+        let node_count = full_state.state.graph.nodes.len();
+        remove(&mut full_state, outer);
+        history.commit(&full_state, Some("Delete"));
+
+        // brings back `outer` with a new id, the macro steps must keep pointing at `inner`
+        history.undo(&mut full_state);
+        history.undo(&mut full_state);
+        history.undo(&mut full_state);
+        assert_eq!(full_state.user_state.macro_stack, vec![macro_id]);
+        history.undo(&mut full_state);
+        assert_eq!(position(&full_state, inner), egui::pos2(500.0, 0.0));
+        for _ in 0..5 {
+            history.redo(&mut full_state);
+        }
+        assert!(full_state.user_state.macro_stack.is_empty());
+        assert_eq!(full_state.state.graph.nodes.len(), node_count - 1);
+    }
+
+    #[test]
+    fn test_dirty_after_save() {
+        let mut full_state = FullGraphState::default();
+        let mut history = History::saved();
+        history.update(&full_state, 0.0, false);
+        assert!(!history.is_dirty());
+
+        add_log(&mut full_state, 0.0);
+        history.update(&full_state, 1.0, false);
+        // not recorded yet, still counts
+        assert!(history.is_dirty());
+        history.mark_saved(&full_state);
+        assert!(!history.is_dirty());
+
+        add_log(&mut full_state, 200.0);
+        history.commit(&full_state, None);
+        assert!(history.is_dirty());
+        history.undo(&mut full_state);
+        assert!(!history.is_dirty());
+        history.undo(&mut full_state);
+        assert!(history.is_dirty());
+        history.redo(&mut full_state);
+        assert!(!history.is_dirty());
+
+        // the saved step can't come back once a different edit replaced it
+        history.undo(&mut full_state);
+        add_log(&mut full_state, 400.0);
+        history.commit(&full_state, None);
+        history.undo(&mut full_state);
+        assert!(history.is_dirty());
+    }
+
+    #[test]
+    fn test_macro_changes_are_per_macro() {
+        let mut full_state = FullGraphState::default();
+        let first = add_log(&mut full_state, 0.0);
+        let second = add_log(&mut full_state, 200.0);
+        let mut history = History::default();
+        history.commit(&full_state, None);
+        full_state.collapse_to_macro(&[first]).unwrap();
+        history.commit(&full_state, Some("Collapse"));
+        full_state.collapse_to_macro(&[second]).unwrap();
+        history.commit(&full_state, Some("Collapse"));
+        let names = |full_state: &FullGraphState| full_state.user_state.macros.iter().map(|def| def.name.clone()).collect::<Vec<_>>();
+        let before = names(&full_state);
+
+        full_state.user_state.macros[0].name = "Renamed".into();
+        history.commit(&full_state, None);
+        // the other macro isn't part of the step, and the renamed one only with its name and ports
+        let step = history.undo.last().unwrap();
+        assert!(matches!(step.changes.as_slice(), [Change::MacroHeader(id, _)] if *id == full_state.user_state.macros[0].id));
+        assert_eq!(step.label, "Edit macro Renamed");
+        let renamed = names(&full_state);
+
+        history.undo(&mut full_state);
+        assert_eq!(names(&full_state), before);
+        history.undo(&mut full_state);
+        assert_eq!(names(&full_state), before[..1]);
+        history.redo(&mut full_state);
+        history.redo(&mut full_state);
+        assert_eq!(names(&full_state), renamed);
+    }
+}
@@ -5,6 +5,9 @@ use eframe::egui::{self, ComboBox, DragValue};
 use super::types::*;
 use crate::typing::*;
 use super::help;
+use super::validation;
+use super::diff::ChangeKind;
+use super::document::{DocumentConnection, NodeUid};
 use crate::pulsetypes::*;
 use crate::bindings::FunctionBinding;
 use crate::app::help::help_hover_text;
@@ -24,6 +27,14 @@ impl PulseGraphState {
         self.variables = other.variables;
         self.exposed_nodes = other.exposed_nodes;
         self.outputs_dropdown_choices = other.outputs_dropdown_choices;
+        self.macros = other.macros;
+        self.macro_stack = other.macro_stack;
+        self.libraries = other.libraries;
+        self.loaded_libraries = other.loaded_libraries;
+        self.graph_domain = other.graph_domain;
+        self.graph_subtype = other.graph_subtype;
+        self.instance_id = other.instance_id;
+        self.next_node_uid = other.next_node_uid;
         // rewrite everything but the save file path and bindings
     }
     pub fn get_library_binding_from_index(&self, index: LibraryBindingIndex) -> Option<&FunctionBinding> {
@@ -33,7 +44,18 @@ impl PulseGraphState {
     pub fn eq_limited(&self, other: &Self) -> bool {
         self.public_outputs == other.public_outputs &&
         self.variables == other.variables &&
-        self.exposed_nodes == other.exposed_nodes
+        self.exposed_nodes == other.exposed_nodes &&
+        self.macros == other.macros &&
+        self.macro_stack == other.macro_stack &&
+        self.libraries == other.libraries
+    }
+    pub fn find_macro(&self, id: MacroId) -> Option<&MacroDefinition> {
+        self.macros.iter().find(|m| m.id == id)
+    }
+    pub fn new_node_uid(&mut self) -> NodeUid {
+        let uid = self.next_node_uid.max(1);
+        self.next_node_uid = uid + 1;
+        uid
     }
 }
 
@@ -284,7 +306,7 @@ impl NodeTemplateTrait for PulseNodeTemplate {
     type DataType = PulseDataType;
     type ValueType = PulseGraphValueType;
     type UserState = PulseGraphState;
-    type CategoryType = &'static str;
+    type CategoryType = String;
 
     fn node_finder_label(&self, _user_state: &mut Self::UserState) -> Cow<'_, str> {
         match self {
@@ -344,12 +366,31 @@ impl NodeTemplateTrait for PulseNodeTemplate {
             PulseNodeTemplate::RandomFloat => "Random float".into(),
             PulseNodeTemplate::RandomInt => "Random int".into(),
             PulseNodeTemplate::EntOutputHandler => "Entity Output Handler".into(),
+            PulseNodeTemplate::CombineFlags => "Combine flags".into(),
+            PulseNodeTemplate::TestFlags => "Test flags".into(),
+            PulseNodeTemplate::Reroute => "Reroute".into(),
+            PulseNodeTemplate::Macro { id } => {
+                _user_state.find_macro(*id)
+                    .map_or("[INVALID MACRO]".into(), |m| m.name.clone().into())
+            }
+            PulseNodeTemplate::MacroInputs => "Macro inputs".into(),
+            PulseNodeTemplate::MacroOutputs => "Macro outputs".into(),
+            PulseNodeTemplate::LibraryMacro { library, id } => {
+                _user_state.find_library(*library)
+                    .and_then(|lib| lib.find_macro(*id))
+                    .map_or("[INVALID MACRO]".into(), |m| m.name.clone().into())
+            }
+            PulseNodeTemplate::LibraryFunction { library, function } => {
+                _user_state.find_library(*library)
+                    .and_then(|lib| lib.find_function(*function))
+                    .map_or("[INVALID FUNCTION]".into(), |(name, _)| format!("Call {name}").into())
+            }
         }
     }
 
     // this is what allows the library to show collapsible lists in the node finder.
-    fn node_finder_categories(&self, _user_state: &mut Self::UserState) -> Vec<&'static str> {
-        match self {
+    fn node_finder_categories(&self, _user_state: &mut Self::UserState) -> Vec<String> {
+        let categories = match self {
             PulseNodeTemplate::CellPublicMethod
             | PulseNodeTemplate::EventHandler
             | PulseNodeTemplate::GraphHook
@@ -372,7 +413,9 @@ impl NodeTemplateTrait for PulseNodeTemplate {
             PulseNodeTemplate::Operation 
             | PulseNodeTemplate::ScaleVector
             | PulseNodeTemplate::RandomFloat
-            | PulseNodeTemplate::RandomInt => vec!["Math"],
+            | PulseNodeTemplate::RandomInt
+            | PulseNodeTemplate::CombineFlags
+            | PulseNodeTemplate::TestFlags => vec!["Math"],
             PulseNodeTemplate::ConcatString => vec!["String"],
             PulseNodeTemplate::CellWait | PulseNodeTemplate::Timeline => vec!["Timing"],
             PulseNodeTemplate::GetVar 
@@ -395,7 +438,8 @@ impl NodeTemplateTrait for PulseNodeTemplate {
             | PulseNodeTemplate::WhileLoop
             | PulseNodeTemplate::ForEach => vec!["Loops"],
             PulseNodeTemplate::SoundEventStart => vec!["Sound"],
-            PulseNodeTemplate::Comment => vec!["Editor"],
+            PulseNodeTemplate::Comment
+            | PulseNodeTemplate::Reroute => vec!["Editor"],
             PulseNodeTemplate::SetAnimGraphParam => vec!["Animation"],
             PulseNodeTemplate::ConstantBool
             | PulseNodeTemplate::ConstantFloat
@@ -403,7 +447,17 @@ impl NodeTemplateTrait for PulseNodeTemplate {
             | PulseNodeTemplate::ConstantVec3
             | PulseNodeTemplate::ConstantInt 
             | PulseNodeTemplate::NewArray => vec!["Constants"],
-        }
+            PulseNodeTemplate::Macro { .. }
+            | PulseNodeTemplate::MacroInputs
+            | PulseNodeTemplate::MacroOutputs => vec!["Macros"],
+            // imported definitions are listed under the name of their library
+            PulseNodeTemplate::LibraryMacro { library, .. }
+            | PulseNodeTemplate::LibraryFunction { library, .. } => {
+                return vec![_user_state.find_library_import(*library)
+                    .map_or("Library".to_string(), |lib| format!("Library: {}", lib.name))];
+            }
+        };
+        categories.into_iter().map(String::from).collect()
     }
 
     fn node_graph_label(&self, user_state: &mut Self::UserState) -> String {
@@ -422,9 +476,10 @@ impl NodeTemplateTrait for PulseNodeTemplate {
         }
     }
 
-    fn user_data(&self, _user_state: &mut Self::UserState) -> Self::NodeData {
+    fn user_data(&self, user_state: &mut Self::UserState) -> Self::NodeData {
         PulseNodeData {
             template: *self,
+            uid: user_state.new_node_uid(),
             custom_named_outputs: Default::default(),
             added_parameters: Default::default(),
             input_hint_text: None,
@@ -594,6 +649,19 @@ impl NodeTemplateTrait for PulseNodeTemplate {
                 true,
             );
         };
+        let input_schema_enum = |graph: &mut PulseGraph, name: &str, enum_type: SchemaEnumType, kind: InputParamKind| {
+            graph.add_input_param(
+                node_id,
+                name.to_string(),
+                PulseDataType::SchemaEnum,
+                PulseGraphValueType::SchemaEnum {
+                    enum_type,
+                    value: SchemaEnumValue::default_from_type(&enum_type),
+                },
+                kind,
+                true,
+            );
+        };
         let output_scalar = |graph: &mut PulseGraph, name: &str| {
             graph.add_output_param(node_id, name.to_string(), PulseDataType::Scalar);
         };
@@ -1079,6 +1147,74 @@ impl NodeTemplateTrait for PulseNodeTemplate {
                 //input_typ(graph, "expectedType", PulseValueType::PVAL_ANY);
                 output_action(graph, "outAction");
             }
+            PulseNodeTemplate::CombineFlags => {
+                input_typ(graph, "type", PulseValueType::PVAL_SCHEMA_ENUM(SchemaEnumType::DamageTypes));
+                input_schema_enum(graph, "value", SchemaEnumType::DamageTypes, InputParamKind::ConnectionOrConstant);
+                input_schema_enum(graph, "flags", SchemaEnumType::DamageTypes, InputParamKind::ConstantOnly);
+                input_bool(graph, "clear", InputParamKind::ConstantOnly);
+                graph.add_output_param(node_id, "out".to_string(), PulseDataType::SchemaEnum);
+            }
+            PulseNodeTemplate::TestFlags => {
+                input_typ(graph, "type", PulseValueType::PVAL_SCHEMA_ENUM(SchemaEnumType::DamageTypes));
+                input_schema_enum(graph, "value", SchemaEnumType::DamageTypes, InputParamKind::ConnectionOrConstant);
+                input_schema_enum(graph, "flags", SchemaEnumType::DamageTypes, InputParamKind::ConstantOnly);
+                output_bool(graph, "out");
+            }
+            PulseNodeTemplate::Macro { .. } | PulseNodeTemplate::LibraryMacro { .. } => {
+                let macro_def = match self {
+                    PulseNodeTemplate::LibraryMacro { library, id } => {
+                        user_state.find_library(*library).and_then(|lib| lib.find_macro(*id))
+                    }
+                    PulseNodeTemplate::Macro { id } => user_state.find_macro(*id),
+                    _ => None,
+                };
+                if let Some(macro_def) = macro_def {
+                    for port in macro_def.inputs.iter() {
+                        graph.add_input_param(
+                            node_id,
+                            port.name.clone(),
+                            port.typ.clone(),
+                            port.value.clone(),
+                            port.kind,
+                            true,
+                        );
+                    }
+                    for port in macro_def.outputs.iter() {
+                        graph.add_output_param(node_id, port.name.clone(), port.typ.clone());
+                    }
+                }
+            }
+            PulseNodeTemplate::Reroute => {
+                graph.add_input_param(
+                    node_id,
+                    "in".into(),
+                    PulseDataType::Any,
+                    PulseGraphValueType::Any,
+                    InputParamKind::ConnectionOnly,
+                    true,
+                );
+                graph.add_output_param(node_id, "out".into(), PulseDataType::Any);
+            }
+            // ports are created together with the macro
+            PulseNodeTemplate::MacroInputs | PulseNodeTemplate::MacroOutputs => {}
+            // behaves like a CallNode pointed at the library function, it's resolved by its uid when compiling
+            PulseNodeTemplate::LibraryFunction { library, function } => {
+                let function_name = user_state.find_library(*library)
+                    .and_then(|lib| lib.find_function(*function))
+                    .map(|(name, _)| name.clone())
+                    .unwrap_or_default();
+                graph.add_input_param(
+                    node_id,
+                    "functionName".into(),
+                    PulseDataType::String,
+                    PulseGraphValueType::String { value: function_name },
+                    InputParamKind::ConstantOnly,
+                    true,
+                );
+                input_action(graph);
+                input_bool(graph, "Async", InputParamKind::ConstantOnly);
+                output_action(graph, "outAction");
+            }
         }
     }
 }
@@ -1141,7 +1277,12 @@ impl NodeTemplateIter for AllMyNodeTemplates {
             PulseNodeTemplate::RandomInt,
             PulseNodeTemplate::RandomFloat,
             PulseNodeTemplate::EntOutputHandler,
+            PulseNodeTemplate::CombineFlags,
+            PulseNodeTemplate::TestFlags,
+            PulseNodeTemplate::Reroute,
         ];
+        templates.extend(self.macro_ids.iter().map(|id| PulseNodeTemplate::Macro { id: *id }));
+        templates.extend(self.library_templates.iter().copied());
         templates.extend(
                 (0..self.game_function_count).map(|i| PulseNodeTemplate::LibraryBindingAssigned {
                 // ! If we skip an ID in the actual bindings list then it could cause problems!
@@ -1185,6 +1326,48 @@ pub fn type_selection_widget(
         });
 }
 
+// single choice combobox, or a list of checkboxes for flag enums. Returns true if the value changed.
+pub fn schema_enum_value_widget(
+    ui: &mut egui::Ui,
+    id_salt: impl std::hash::Hash,
+    enum_type: &SchemaEnumType,
+    value: &mut SchemaEnumValue,
+) -> bool {
+    let mut changed = false;
+    let combo = ComboBox::from_id_salt(id_salt)
+        .width(0.0)
+        .selected_text(value.get_ui_name());
+    if enum_type.is_flags() {
+        // keep the popup open while toggling the checkboxes
+        combo.close_behavior(egui::PopupCloseBehavior::CloseOnClickOutside)
+            .show_ui(ui, |ui| {
+                let mut flags = value.get_flags();
+                for choice in enum_type.get_all_types_as_enums() {
+                    let mut checked = flags.contains(&choice);
+                    if ui.checkbox(&mut checked, choice.get_ui_name()).changed() {
+                        if checked {
+                            flags.push(choice);
+                        } else {
+                            flags.retain(|f| *f != choice);
+                        }
+                        changed = true;
+                    }
+                }
+                if changed {
+                    *value = SchemaEnumValue::from_flags(*enum_type, flags);
+                }
+            });
+    } else {
+        combo.show_ui(ui, |ui| {
+            for choice in enum_type.get_all_types_as_enums() {
+                let str = choice.get_ui_name();
+                changed |= ui.selectable_value::<SchemaEnumValue>(value, choice, str).changed();
+            }
+        });
+    }
+    changed
+}
+
 impl WidgetValueTrait for PulseGraphValueType {
     type Response = PulseGraphResponse;
     type UserState = PulseGraphState;
@@ -1329,6 +1512,8 @@ impl WidgetValueTrait for PulseGraphValueType {
                             PulseNodeTemplate::CompareOutput => PulseValueType::get_comparable_types(),
                             PulseNodeTemplate::Operation => PulseValueType::get_operatable_types(),
                             PulseNodeTemplate::ScaleVector => PulseValueType::get_vector_types(),
+                            PulseNodeTemplate::CombineFlags
+                            | PulseNodeTemplate::TestFlags => PulseValueType::get_flag_enum_types(),
                             _ => PulseValueType::get_variable_supported_types(),
                         };
                         let callback = |new_type: PulseValueType| {
@@ -1441,15 +1626,7 @@ impl WidgetValueTrait for PulseGraphValueType {
                 PulseGraphValueType::SchemaEnum { enum_type, value } => {
                     ui.horizontal(|ui| {
                         ui.label(param_name);
-                        ComboBox::from_id_salt((node_id, param_name))
-                            .width(0.0)
-                            .selected_text(value.get_ui_name())
-                            .show_ui(ui, |ui| {
-                                for choice in enum_type.get_all_types_as_enums().iter() {
-                                    let str = choice.get_ui_name();
-                                    ui.selectable_value::<SchemaEnumValue>(value, choice.clone(), str);
-                                }
-                            });
+                        schema_enum_value_widget(ui, (node_id, param_name), enum_type, value);
                     });
                 }
                 PulseGraphValueType::CommentBox { value } => {
@@ -1532,6 +1709,13 @@ impl NodeDataTrait for PulseNodeData {
         if !help_text.is_empty() {
             _ui.label("ℹ").on_hover_text(help_text);
         }
+        if let Some(severity) = validation::node_severity(&user_state.node_issues, _node_id) {
+            let problems = user_state.node_issues[_node_id].iter()
+                .map(|issue| issue.message.as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+            _ui.label(egui::RichText::new("⚠").color(severity.color())).on_hover_text(problems);
+        }
         if let Some(node_name) = user_state.exposed_nodes.get_mut(_node_id) {
             _ui.text_edit_singleline(node_name);
         }
@@ -1600,11 +1784,44 @@ impl NodeDataTrait for PulseNodeData {
                     }
                 }
             }
+            PulseNodeTemplate::Macro { id } if ui.button("Edit macro").clicked() => {
+                responses.push(NodeResponse::User(PulseGraphResponse::OpenMacro(id)));
+            }
             _ => { /* no custom bottom ui */ }
         }
         responses
     }
 
+    fn dimmed(
+        &self,
+        node_id: NodeId,
+        _graph: &Graph<Self, Self::DataType, Self::ValueType>,
+        user_state: &mut Self::UserState,
+    ) -> bool {
+        let removed = user_state.diff.as_ref().is_some_and(|diff| {
+            diff.node_change(user_state.macro_stack.last().copied(), self.uid) == Some(ChangeKind::Removed)
+        });
+        removed || user_state.unreachable_nodes.contains(&node_id)
+    }
+
+    fn connection_color(
+        &self,
+        input: InputId,
+        output: OutputId,
+        graph: &Graph<Self, Self::DataType, Self::ValueType>,
+        user_state: &mut Self::UserState,
+    ) -> Option<Color32> {
+        let diff = user_state.diff.as_ref()?;
+        let (input_node, output_node) = (graph.get_input(input).node, graph.get_output(output).node);
+        let (input_name, _) = graph[input_node].inputs.iter().find(|(_, id)| *id == input)?;
+        let (output_name, _) = graph[output_node].outputs.iter().find(|(_, id)| *id == output)?;
+        let connection = DocumentConnection {
+            from: (graph[output_node].user_data.uid, output_name.clone()),
+            to: (graph[input_node].user_data.uid, input_name.clone()),
+        };
+        diff.connection_change(user_state.macro_stack.last().copied(), &connection).map(|kind| kind.color())
+    }
+
     fn titlebar_color(
         &self,
         _ui: &egui::Ui,
@@ -1612,6 +1829,14 @@ impl NodeDataTrait for PulseNodeData {
         _graph: &Graph<Self, Self::DataType, Self::ValueType>,
         _user_state: &mut Self::UserState,
     ) -> Option<Color32> {
+        if let Some(diff) = &_user_state.diff {
+            if let Some(kind) = diff.node_change(_user_state.macro_stack.last().copied(), self.uid) {
+                return Some(kind.color());
+            }
+        }
+        if validation::node_severity(&_user_state.node_issues, _node_id) == Some(validation::IssueSeverity::Error) {
+            return Some(Color32::from_rgb(140, 35, 35));
+        }
         match self.template {
             PulseNodeTemplate::CellPublicMethod
             | PulseNodeTemplate::EventHandler
@@ -1662,11 +1887,19 @@ impl NodeDataTrait for PulseNodeData {
             | PulseNodeTemplate::NewArray => Some(Color32::from_rgb(77, 100, 105)),
             PulseNodeTemplate::ConcatString
             | PulseNodeTemplate::Comment
+            | PulseNodeTemplate::Reroute
             | PulseNodeTemplate::SetAnimGraphParam
             | PulseNodeTemplate::ReturnValue
             | PulseNodeTemplate::ScaleVector
             | PulseNodeTemplate::RandomFloat
             | PulseNodeTemplate::RandomInt => None,
+            PulseNodeTemplate::CombineFlags
+            | PulseNodeTemplate::TestFlags => Some(Color32::from_rgb(29, 181, 184)),
+            PulseNodeTemplate::Macro { .. }
+            | PulseNodeTemplate::MacroInputs
+            | PulseNodeTemplate::MacroOutputs
+            | PulseNodeTemplate::LibraryMacro { .. } => Some(Color32::from_rgb(120, 120, 40)),
+            PulseNodeTemplate::LibraryFunction { .. } => Some(Color32::from_rgb(28, 67, 150)),
         }
     }
 
@@ -1699,10 +1932,7 @@ impl NodeDataTrait for PulseNodeData {
 impl Default for EditorConfig {
     fn default() -> Self {
         EditorConfig {
-            // TODO: it could be python3 on Linux
-            python_interpreter: String::from("python"),
-            assetassembler_path: PathBuf::from(""),
-            red2_template_path: PathBuf::from("graph_red2_template.kv3"),
+            red2_template_path: std::path::PathBuf::from("graph_red2_template.kv3"),
         }
     }
 }
@@ -1711,12 +1941,63 @@ fn slotmap_eq <K: slotmap::Key, T: PartialEq>(a: &slotmap::SlotMap<K, T>, b: &sl
     a.len() == b.len() && a.iter().all(|(key, value)| b.get(key) == Some(value))
 }
 
+// Node positions are scaled by the zoom, so compare them relative to one of the nodes and unscaled.
+// That way zooming doesn't count as a change, but moving nodes and frames around does.
+fn layout_eq(a: &MyEditorState, b: &MyEditorState) -> bool {
+    if a.node_positions.len() != b.node_positions.len() {
+        return false;
+    }
+    let (ref_a, ref_b) = match a.node_positions.iter().next() {
+        Some((ref_id, ref_a)) => match b.node_positions.get(ref_id) {
+            Some(ref_b) => (ref_a, ref_b),
+            None => return false,
+        },
+        None => (&egui::Pos2::ZERO, &egui::Pos2::ZERO),
+    };
+    let (zoom_a, zoom_b) = (a.pan_zoom.zoom, b.pan_zoom.zoom);
+    let frames_eq = a.group_frames.len() == b.group_frames.len()
+        && a.group_frames.iter().zip(b.group_frames.iter()).all(|(frame_a, frame_b)| {
+            frame_a.title == frame_b.title
+                && frame_a.color == frame_b.color
+                && frame_a.collapsed == frame_b.collapsed
+                && ((frame_a.rect.min - *ref_a) / zoom_a - (frame_b.rect.min - *ref_b) / zoom_b).length() < 0.5
+                && (frame_a.rect.size() / zoom_a - frame_b.rect.size() / zoom_b).length() < 0.5
+        });
+    frames_eq && a.node_positions.iter().all(|(node_id, pos_a)| {
+        b.node_positions.get(node_id).is_some_and(|pos_b| {
+            ((*pos_a - *ref_a) / zoom_a - (*pos_b - *ref_b) / zoom_b).length() < 0.5
+        })
+    })
+}
+
+impl PartialEq for MacroDefinition {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id &&
+        self.name == other.name &&
+        self.inputs == other.inputs &&
+        self.outputs == other.outputs &&
+        self.same_graph(other)
+    }
+}
+
+impl MacroDefinition {
+    // compares everything but the name and ports
+    pub fn same_graph(&self, other: &Self) -> bool {
+        self.exposed_nodes == other.exposed_nodes &&
+        self.state.graph.connections == other.state.graph.connections &&
+        slotmap_eq(&self.state.graph.nodes, &other.state.graph.nodes) &&
+        slotmap_eq(&self.state.graph.inputs, &other.state.graph.inputs) &&
+        slotmap_eq(&self.state.graph.outputs, &other.state.graph.outputs)
+    }
+}
+
 impl PartialEq for FullGraphState {
     fn eq(&self, other: &Self) -> bool {
         self.state.graph.connections == other.state.graph.connections &&
         slotmap_eq(&self.state.graph.nodes, &other.state.graph.nodes) &&
         slotmap_eq(&self.state.graph.inputs, &other.state.graph.inputs) &&
         slotmap_eq(&self.state.graph.outputs, &other.state.graph.outputs) &&
+        layout_eq(&self.state, &other.state) &&
         // user_state has PartialEq derived, but for purposes of undo we only want to compare some fields that are relevant to us.
         self.user_state.eq_limited(&other.user_state)
     }
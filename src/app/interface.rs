@@ -0,0 +1,201 @@
+// A Markdown summary of how a graph is used from a map: the inputs it adds to its point_pulse entity, the
+// outputs it fires, and the game events and entity outputs it reacts to. For level designers that only use
+// the graph and don't want to read it.
+use std::fmt::Write;
+use std::path::Path;
+use egui_node_graph2::*;
+use rfd::{FileDialog, MessageDialog};
+use crate::compiler::linked_graph;
+use crate::pulsetypes::PulseVariable;
+use crate::typing::PulseValueType;
+use super::types::*;
+use super::{FullGraphState, PulseGraphEditor};
+
+// The path to put in graph_def, relative to the addon. Only known if the graph is saved in an addon's content
+// directory, e.g. content/csgo_addons/ADDON/scripts/vscripts/graph.ron -> scripts/vscripts/graph.vpulse
+pub fn graph_def_path(save_path: &Path) -> Option<String> {
+    let components: Vec<_> = save_path.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+    let content = components.iter().rposition(|c| c == "content")?;
+    // skip the addons directory and the addon
+    let inner = components.get(content + 3..).filter(|inner| !inner.is_empty())?;
+    let path = Path::new(&inner.join("/")).with_extension("vpulse");
+    Some(path.to_string_lossy().replace('\\', "/"))
+}
+
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+fn type_name(typ: &PulseValueType) -> &'static str {
+    match typ {
+        PulseValueType::PVAL_VOID => "none",
+        typ => typ.get_ui_name(),
+    }
+}
+
+fn default_text(var: &PulseVariable) -> String {
+    match &var.typ_and_default_value {
+        PulseValueType::PVAL_INT(Some(value)) => value.to_string(),
+        PulseValueType::PVAL_FLOAT(Some(value)) => value.to_string(),
+        PulseValueType::PVAL_STRING(Some(value))
+        | PulseValueType::PVAL_SNDEVT_NAME(Some(value)) => format!("\"{value}\""),
+        PulseValueType::PVAL_BOOL_VALUE(Some(value)) => value.to_string(),
+        PulseValueType::PVAL_VEC2(Some(value)) => format!("{} {}", value.x, value.y),
+        PulseValueType::PVAL_VEC3(Some(value))
+        | PulseValueType::PVAL_VEC3_LOCAL(Some(value))
+        | PulseValueType::PVAL_QANGLE(Some(value))
+        | PulseValueType::PVAL_COLOR_RGB(Some(value)) => format!("{} {} {}", value.x, value.y, value.z),
+        PulseValueType::PVAL_VEC4(Some(value)) => format!("{} {} {} {}", value.x, value.y, value.z, value.w),
+        PulseValueType::PVAL_SCHEMA_ENUM(_) => var.default_value_buffer.clone(),
+        _ => String::new(),
+    }
+}
+
+fn constant(graph: &PulseGraph, node: &Node<PulseNodeData>, name: &str) -> String {
+    node.get_input(name).ok()
+        .and_then(|input_id| graph.inputs[input_id].value.clone().try_to_string().ok())
+        .unwrap_or_default()
+}
+
+// Names of the inputs the graph adds to its entity, and if their argument is used. The argument is always a
+// string, and only worth passing if something uses it.
+pub(super) fn public_methods(graph: &PulseGraph) -> Vec<(String, bool)> {
+    let mut methods: Vec<(String, bool)> = graph.nodes.values()
+        .filter(|node| matches!(node.user_data.template, PulseNodeTemplate::CellPublicMethod))
+        .map(|node| {
+            let argument = node.get_output("argument1")
+                .is_ok_and(|output_id| graph.iter_connections().any(|(_, output)| output == output_id));
+            (constant(graph, node, "name"), argument)
+        })
+        .collect();
+    // node order changes between saves, keep what's generated from it the same
+    methods.sort();
+    methods
+}
+
+fn write_table(out: &mut String, header: &[&str], rows: &[Vec<String>]) {
+    let _ = writeln!(out, "| {} |", header.join(" | "));
+    let _ = writeln!(out, "|{}", "---|".repeat(header.len()));
+    for row in rows {
+        let cells: Vec<String> = row.iter().map(|text| cell(text)).collect();
+        let _ = writeln!(out, "| {} |", cells.join(" | "));
+    }
+    out.push('\n');
+}
+
+impl FullGraphState {
+    pub fn interface_markdown(&self) -> anyhow::Result<String> {
+        let root = self.root_state();
+        // entry points can be inside macros too
+        let (graph, user_state) = linked_graph(&root.state.graph, &root.user_state)?;
+        let save_path = user_state.save_file_path.as_deref();
+        let title = save_path.and_then(|path| path.file_stem())
+            .map_or("Untitled graph".to_string(), |name| name.to_string_lossy().to_string());
+
+        let methods: Vec<Vec<String>> = public_methods(&graph).into_iter()
+            .map(|(name, argument)| vec![format!("`{name}`"), if argument { "String" } else { "none" }.to_string()])
+            .collect();
+        let mut events = vec![];
+        let mut entity_outputs = vec![];
+        for node in graph.nodes.values() {
+            match node.user_data.template {
+                PulseNodeTemplate::EventHandler => {
+                    let event = node.get_input("event").ok()
+                        .and_then(|input_id| graph.inputs[input_id].value.clone().try_event_binding_id().ok())
+                        .and_then(|id| user_state.bindings.find_event_by_id(id));
+                    let Some(event) = event else {
+                        events.push(vec!["Unknown event".to_string(), String::new()]);
+                        continue;
+                    };
+                    let params: Vec<String> = event.inparams.iter().flatten()
+                        .map(|param| format!("`{}` ({})", param.name, type_name(&param.pulsetype)))
+                        .collect();
+                    events.push(vec![format!("{} (`{}`)", event.displayname, event.libname), params.join(", ")]);
+                }
+                PulseNodeTemplate::EntOutputHandler => {
+                    entity_outputs.push(vec![
+                        format!("`{}`", constant(&graph, node, "entityName")),
+                        format!("`{}`", constant(&graph, node, "outputName")),
+                    ]);
+                }
+                PulseNodeTemplate::ListenForEntityOutput => {
+                    // started on an entity the graph picks while it runs
+                    entity_outputs.push(vec![
+                        "picked by the graph".to_string(),
+                        format!("`{}`", constant(&graph, node, "outputName")),
+                    ]);
+                }
+                _ => {}
+            }
+        }
+        events.sort();
+        entity_outputs.sort();
+        let outputs: Vec<Vec<String>> = user_state.public_outputs.iter()
+            .map(|output| vec![format!("`{}`", output.name), type_name(&output.typ).to_string()])
+            .collect();
+        let variables: Vec<Vec<String>> = user_state.variables.iter()
+            .map(|var| vec![format!("`{}`", var.name), type_name(&var.typ_and_default_value).to_string(), default_text(var)])
+            .collect();
+
+        let mut out = String::new();
+        let _ = writeln!(out, "# {title}\n");
+        let _ = writeln!(out, "Entity I/O interface of the Pulse graph `{title}` ({}, `{}`).\n", user_state.graph_domain, user_state.graph_subtype);
+        out.push_str("## Setup\n\n");
+        match save_path.and_then(graph_def_path) {
+            Some(path) => {
+                let _ = writeln!(out, "Add a `point_pulse` entity to the map and set its **Graph path** (`graph_def`) to `{path}`.\n");
+            }
+            None => out.push_str("Add a `point_pulse` entity to the map and set its **Graph path** (`graph_def`) to the compiled `.vpulse` file of this graph, relative to the addon's directory.\n\n"),
+        }
+        if !methods.is_empty() {
+            out.push_str("## Inputs\n\nInputs the graph adds to its entity. Fire them from the map's I/O, or with `ent_fire <entity name> <input> [argument]`.\n\n");
+            write_table(&mut out, &["Input", "Argument"], &methods);
+        }
+        if !outputs.is_empty() {
+            out.push_str("## Outputs\n\nOutputs of the graph's entity, fired by the graph. Connect them to other entities in Hammer.\n\n");
+            write_table(&mut out, &["Output", "Parameter"], &outputs);
+        }
+        if !events.is_empty() {
+            out.push_str("## Game events\n\nThe graph runs when these game events happen.\n\n");
+            write_table(&mut out, &["Event", "Parameters"], &events);
+        }
+        if !entity_outputs.is_empty() {
+            out.push_str("## Entity outputs\n\nThe graph runs when these entities fire these outputs. Entities with these names have to be in the map.\n\n");
+            write_table(&mut out, &["Entity", "Output"], &entity_outputs);
+        }
+        if !variables.is_empty() {
+            out.push_str("## Variables\n\n");
+            write_table(&mut out, &["Variable", "Type", "Default"], &variables);
+        }
+        if methods.is_empty() && events.is_empty() && entity_outputs.is_empty() {
+            out.push_str("The graph has no inputs, and doesn't react to any events or entity outputs.\n");
+        }
+        Ok(out.trim_end().to_string() + "\n")
+    }
+}
+
+impl PulseGraphEditor {
+    pub(super) fn export_interface(&self) {
+        let result = self.full_state.interface_markdown().and_then(|markdown| {
+            let file_name = self.user_state().save_file_path.as_ref()
+                .and_then(|path| path.file_stem())
+                .map_or("graph".to_string(), |name| name.to_string_lossy().to_string());
+            let Some(path) = FileDialog::new()
+                .add_filter("Markdown", &["md"])
+                .set_file_name(format!("{file_name}.md"))
+                .save_file() else {
+                return Ok(());
+            };
+            std::fs::write(path, markdown)?;
+            Ok(())
+        });
+        if let Err(e) = result {
+            MessageDialog::new()
+                .set_level(rfd::MessageLevel::Error)
+                .set_title("Export failed")
+                .set_buttons(rfd::MessageButtons::Ok)
+                .set_description(format!("{e:#}"))
+                .show();
+        }
+    }
+}
@@ -0,0 +1,263 @@
+// Automatic layout. Nodes are put into columns (layers) following the action flow from left to right,
+// starting at the inflow nodes. Nodes that only provide values go in the column right before the node
+// that uses them. The order inside of the columns is picked to reduce the number of crossing connections.
+use std::collections::HashMap;
+use eframe::egui;
+use egui_node_graph2::*;
+use super::types::*;
+use super::FullGraphState;
+
+const COLUMN_GAP: f32 = 80.0;
+const ROW_GAP: f32 = 30.0;
+// number of up and down passes when ordering the nodes in the columns
+const ORDERING_PASSES: usize = 4;
+
+struct LayoutGraph {
+    nodes: Vec<NodeId>,
+    // connections between the nodes, as indices into `nodes`. Connections that would form a cycle are left out.
+    preds: Vec<Vec<usize>>,
+    succs: Vec<Vec<usize>>,
+    // nodes that have no action connections, only values
+    value_only: Vec<bool>,
+    // order in which the nodes were first reached from the inflow nodes, parents before children
+    topo_order: Vec<usize>,
+}
+
+impl FullGraphState {
+    // Arranges the given nodes, or the whole graph if none are given.
+    // The top left corner of the arranged nodes stays where it was.
+    pub fn auto_layout(&mut self, node_ids: &[NodeId]) {
+        let state = &self.state;
+        let mut nodes: Vec<NodeId> = if node_ids.is_empty() {
+            state.node_order.clone()
+        } else {
+            node_ids.to_vec()
+        };
+        // comments are placed by hand next to the things they describe, leave them alone
+        nodes.retain(|id| state.graph.nodes.get(*id)
+            .is_some_and(|node| node.user_data.template != PulseNodeTemplate::Comment)
+            && state.node_positions.contains_key(*id));
+        if nodes.is_empty() {
+            return;
+        }
+        let origin = nodes.iter()
+            .map(|id| state.node_positions[*id])
+            .fold(egui::pos2(f32::MAX, f32::MAX), |acc, pos| acc.min(pos));
+
+        let layout = LayoutGraph::new(&state.graph, nodes);
+        let layers = layout.assign_layers();
+        let columns = layout.order_columns(&layers);
+
+        let zoom = state.pan_zoom.zoom;
+        let sizes: Vec<egui::Vec2> = layout.nodes.iter()
+            .map(|id| *state.node_sizes.get(*id).unwrap_or(&egui::vec2(200.0, 200.0)) * zoom)
+            .collect();
+        let positions = layout.place(&columns, &sizes, origin, zoom);
+        for (idx, pos) in positions.into_iter().enumerate() {
+            self.state.node_positions.insert(layout.nodes[idx], pos);
+        }
+    }
+}
+
+impl LayoutGraph {
+    fn new(graph: &PulseGraph, nodes: Vec<NodeId>) -> Self {
+        let index: HashMap<NodeId, usize> = nodes.iter().enumerate().map(|(idx, id)| (*id, idx)).collect();
+        let mut edges: Vec<(usize, usize)> = vec![];
+        let mut has_action = vec![false; nodes.len()];
+        let mut action_inputs = vec![false; nodes.len()];
+        for (input_id, output_ids) in graph.iter_connection_groups() {
+            let Some(&to) = index.get(&graph.get_input(input_id).node) else {
+                continue;
+            };
+            for output_id in output_ids {
+                let output = graph.get_output(output_id);
+                let Some(&from) = index.get(&output.node) else {
+                    continue;
+                };
+                if from == to {
+                    continue;
+                }
+                if output.typ == PulseDataType::Action {
+                    has_action[from] = true;
+                    has_action[to] = true;
+                    action_inputs[to] = true;
+                }
+                if !edges.contains(&(from, to)) {
+                    edges.push((from, to));
+                }
+            }
+        }
+        let mut out_edges = vec![vec![]; nodes.len()];
+        for (from, to) in edges.iter() {
+            out_edges[*from].push(*to);
+        }
+
+        // start from the inflow nodes, then from anything nothing flows into, and finally whatever is left
+        // (nodes in a cycle that isn't reachable otherwise)
+        let is_inflow = |idx: usize| matches!(
+            graph.nodes[nodes[idx]].user_data.template,
+            PulseNodeTemplate::CellPublicMethod | PulseNodeTemplate::EventHandler
+            | PulseNodeTemplate::GraphHook | PulseNodeTemplate::EntOutputHandler
+        );
+        let has_incoming = |idx: usize| edges.iter().any(|(_, to)| *to == idx);
+        let mut roots: Vec<usize> = (0..nodes.len()).filter(|idx| is_inflow(*idx)).collect();
+        roots.extend((0..nodes.len()).filter(|idx| !is_inflow(*idx) && !has_incoming(*idx) && !action_inputs[*idx]));
+        roots.extend(0..nodes.len());
+
+        // depth first search, connections going back to a node that is still being visited close a cycle
+        // and are dropped
+        let mut preds = vec![vec![]; nodes.len()];
+        let mut succs = vec![vec![]; nodes.len()];
+        let mut visited = vec![false; nodes.len()];
+        let mut on_stack = vec![false; nodes.len()];
+        let mut post_order = vec![];
+        for root in roots {
+            if visited[root] {
+                continue;
+            }
+            visited[root] = true;
+            on_stack[root] = true;
+            let mut stack = vec![(root, 0usize)];
+            while let Some((node, next_edge)) = stack.last_mut() {
+                let node = *node;
+                if let Some(&to) = out_edges[node].get(*next_edge) {
+                    *next_edge += 1;
+                    if on_stack[to] {
+                        continue;
+                    }
+                    preds[to].push(node);
+                    succs[node].push(to);
+                    if !visited[to] {
+                        visited[to] = true;
+                        on_stack[to] = true;
+                        stack.push((to, 0));
+                    }
+                } else {
+                    on_stack[node] = false;
+                    post_order.push(node);
+                    stack.pop();
+                }
+            }
+        }
+        post_order.reverse();
+
+        Self {
+            nodes,
+            preds,
+            succs,
+            value_only: has_action.into_iter().map(|action| !action).collect(),
+            topo_order: post_order,
+        }
+    }
+
+    fn assign_layers(&self) -> Vec<usize> {
+        // longest path from the start, so everything ends up to the right of what flows into it
+        let mut layers = vec![0usize; self.nodes.len()];
+        for &node in self.topo_order.iter() {
+            layers[node] = self.preds[node].iter().map(|pred| layers[*pred] + 1).max().unwrap_or(0);
+        }
+        // pull the value nodes right next to the first node that uses them
+        for &node in self.topo_order.iter().rev() {
+            if !self.value_only[node] {
+                continue;
+            }
+            if let Some(closest) = self.succs[node].iter().map(|succ| layers[*succ]).min() {
+                layers[node] = closest.saturating_sub(1).max(layers[node]);
+            }
+        }
+        layers
+    }
+
+    // Groups the nodes into columns, ordered top to bottom.
+    fn order_columns(&self, layers: &[usize]) -> Vec<Vec<usize>> {
+        let column_count = layers.iter().max().map_or(0, |max| max + 1);
+        let mut columns = vec![vec![]; column_count];
+        for &node in self.topo_order.iter() {
+            columns[layers[node]].push(node);
+        }
+        let mut rows = vec![0.0f32; self.nodes.len()];
+        let update_rows = |columns: &Vec<Vec<usize>>, rows: &mut Vec<f32>| {
+            for column in columns.iter() {
+                for (row, node) in column.iter().enumerate() {
+                    rows[*node] = row as f32;
+                }
+            }
+        };
+        update_rows(&columns, &mut rows);
+
+        // barycenter heuristic: sort every column by the average row of the connected nodes in the columns
+        // before it, then the same going backwards
+        for pass in 0..ORDERING_PASSES * 2 {
+            let forward = pass % 2 == 0;
+            let column_ids: Vec<usize> = if forward {
+                (1..column_count).collect()
+            } else {
+                (0..column_count.saturating_sub(1)).rev().collect()
+            };
+            for col in column_ids {
+                let neighbours = if forward { &self.preds } else { &self.succs };
+                let mut keys: Vec<(usize, f32)> = columns[col].iter()
+                    .map(|node| {
+                        let connected = &neighbours[*node];
+                        let key = if connected.is_empty() {
+                            rows[*node]
+                        } else {
+                            connected.iter().map(|other| rows[*other]).sum::<f32>() / connected.len() as f32
+                        };
+                        (*node, key)
+                    })
+                    .collect();
+                keys.sort_by(|a, b| a.1.total_cmp(&b.1));
+                columns[col] = keys.into_iter().map(|(node, _)| node).collect();
+                for (row, node) in columns[col].iter().enumerate() {
+                    rows[*node] = row as f32;
+                }
+            }
+        }
+        columns
+    }
+
+    fn place(&self, columns: &[Vec<usize>], sizes: &[egui::Vec2], origin: egui::Pos2, zoom: f32) -> Vec<egui::Pos2> {
+        let mut positions = vec![origin; self.nodes.len()];
+        let mut x = origin.x;
+        for column in columns.iter() {
+            for node in column.iter() {
+                positions[*node].x = x;
+            }
+            x += column.iter().map(|node| sizes[*node].x).fold(0.0, f32::max) + COLUMN_GAP * zoom;
+        }
+
+        // stacks a column top to bottom, trying to put every node at the wanted height without overlapping
+        let stack_column = |column: &[usize], wanted: &dyn Fn(usize) -> Option<f32>, positions: &mut Vec<egui::Pos2>| {
+            let mut next_free = origin.y;
+            for node in column.iter() {
+                let y = wanted(*node).map_or(next_free, |wanted| wanted.max(next_free));
+                positions[*node].y = y;
+                next_free = y + sizes[*node].y + ROW_GAP * zoom;
+            }
+        };
+        // line the nodes up with the ones flowing into them
+        for column in columns.iter() {
+            let placed = positions.clone();
+            let wanted = |node: usize| {
+                let preds = &self.preds[node];
+                (!preds.is_empty())
+                    .then(|| preds.iter().map(|pred| placed[*pred].y).sum::<f32>() / preds.len() as f32)
+            };
+            stack_column(column, &wanted, &mut positions);
+        }
+        // and the value nodes with the ones using them
+        for column in columns.iter().rev() {
+            let placed = positions.clone();
+            let wanted = |node: usize| {
+                let succs = &self.succs[node];
+                if !self.value_only[node] || succs.is_empty() {
+                    return Some(placed[node].y);
+                }
+                Some(succs.iter().map(|succ| placed[*succ].y).sum::<f32>() / succs.len() as f32)
+            };
+            stack_column(column, &wanted, &mut positions);
+        }
+        positions
+    }
+}
@@ -0,0 +1,121 @@
+// Library imports. A library is a regular graph file, its macros and named functions
+// can be used by the graphs that import it.
+use std::path::{Path, PathBuf};
+use std::fs;
+use egui_node_graph2::NodeId;
+use super::types::*;
+use super::FullGraphState;
+
+pub fn load_library(id: LibraryId, path: &Path) -> anyhow::Result<LoadedLibrary> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read library '{}': {e}", path.display()))?;
+    let (library, _) = FullGraphState::from_saved(&contents)
+        .map_err(|e| anyhow::anyhow!("Failed to load library '{}': {e}", path.display()))?;
+    // saved files always have the top level graph in the state, but just to be sure
+    let library = library.root_state().into_owned();
+    let graph = library.state.graph;
+    let mut functions: Vec<(String, _)> = library.user_state.exposed_nodes.iter()
+        .filter(|(node_id, name)| !name.is_empty() && graph.nodes.get(*node_id)
+            .is_some_and(|node| node.user_data.template == PulseNodeTemplate::Function))
+        .map(|(node_id, name)| (name.clone(), node_id))
+        .collect();
+    functions.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(LoadedLibrary {
+        id,
+        graph,
+        macros: library.user_state.macros,
+        functions,
+        variables: library.user_state.variables,
+        public_outputs: library.user_state.public_outputs,
+    })
+}
+
+impl PulseGraphState {
+    pub fn find_library(&self, id: LibraryId) -> Option<&LoadedLibrary> {
+        self.loaded_libraries.iter().find(|lib| lib.id == id)
+    }
+
+    pub fn find_library_import(&self, id: LibraryId) -> Option<&LibraryImport> {
+        self.libraries.iter().find(|lib| lib.id == id)
+    }
+
+    pub fn library_path(&self, import: &LibraryImport) -> anyhow::Result<PathBuf> {
+        let graph_dir = self.save_file_path.as_ref()
+            .and_then(|path| path.parent())
+            .ok_or_else(|| anyhow::anyhow!("The graph needs to be saved before using libraries"))?;
+        Ok(graph_dir.join(&import.path))
+    }
+
+    // Reads all of the imported libraries from disk again. Returns the errors for the ones that failed.
+    pub fn reload_libraries(&mut self) -> Vec<anyhow::Error> {
+        let mut errors = vec![];
+        let mut loaded = vec![];
+        for import in self.libraries.iter() {
+            match self.library_path(import).and_then(|path| load_library(import.id, &path)) {
+                Ok(library) => loaded.push(library),
+                Err(e) => errors.push(e.context(format!("Library '{}'", import.name))),
+            }
+        }
+        self.loaded_libraries = loaded;
+        errors
+    }
+
+    pub fn add_library(&mut self, path: &Path) -> anyhow::Result<()> {
+        let graph_dir = self.save_file_path.as_ref()
+            .and_then(|path| path.parent())
+            .ok_or_else(|| anyhow::anyhow!("The graph needs to be saved before importing libraries"))?;
+        if self.save_file_path.as_deref() == Some(path) {
+            anyhow::bail!("A graph can't import itself");
+        }
+        // keep the path relative if we can, so the files can be moved around together
+        let relative_path = path.strip_prefix(graph_dir).unwrap_or(path).to_path_buf();
+        let id = LibraryId(self.libraries.iter().map(|lib| lib.id.0 + 1).max().unwrap_or(0));
+        let library = load_library(id, path)?;
+        self.libraries.push(LibraryImport {
+            id,
+            name: path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| format!("Library {}", id.0)),
+            path: relative_path,
+        });
+        self.loaded_libraries.push(library);
+        Ok(())
+    }
+
+    pub fn remove_library(&mut self, id: LibraryId) {
+        self.libraries.retain(|lib| lib.id != id);
+        self.loaded_libraries.retain(|lib| lib.id != id);
+    }
+
+    // node finder entries for everything the imported libraries provide
+    pub fn library_templates(&self) -> Vec<PulseNodeTemplate> {
+        let mut templates = vec![];
+        for library in self.loaded_libraries.iter() {
+            templates.extend(library.macros.iter()
+                .map(|m| PulseNodeTemplate::LibraryMacro { library: library.id, id: m.id }));
+            templates.extend(library.functions.iter()
+                .filter_map(|(_, node_id)| library.graph.nodes.get(*node_id))
+                .map(|node| PulseNodeTemplate::LibraryFunction { library: library.id, function: node.user_data.uid }));
+        }
+        templates
+    }
+}
+
+impl LoadedLibrary {
+    pub fn find_macro(&self, id: MacroId) -> Option<&MacroDefinition> {
+        self.macros.iter().find(|m| m.id == id)
+    }
+
+    // name and node of the function with the given node uid
+    pub fn find_function(&self, uid: NodeUid) -> Option<&(String, NodeId)> {
+        self.functions.iter()
+            .find(|(_, node_id)| self.graph.nodes.get(*node_id).is_some_and(|node| node.user_data.uid == uid))
+    }
+}
+
+impl PartialEq for LoadedLibrary {
+    // the graph itself is not compared, it's only a cache of what's on disk
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.macros == other.macros && self.functions == other.functions
+    }
+}
@@ -0,0 +1,302 @@
+// Macro (collapsed subgraph) editing.
+// The macro graphs are stored in PulseGraphState::macros. To edit one, its state gets swapped with
+// the one shown in the editor, and swapped back when leaving. This way the rest of the editor
+// doesn't need to know which graph is being edited.
+use std::borrow::Cow;
+use std::collections::HashMap;
+use eframe::egui;
+use egui_node_graph2::*;
+use super::clipboard::ClipboardContent;
+use super::types::*;
+use super::FullGraphState;
+
+type InnerInput = (usize, String);
+
+impl FullGraphState {
+    pub fn enter_macro(&mut self, id: MacroId) -> anyhow::Result<()> {
+        if self.user_state.macro_stack.contains(&id) {
+            anyhow::bail!("This macro is already being edited");
+        }
+        let macro_def = self.user_state.macros.iter_mut()
+            .find(|m| m.id == id)
+            .ok_or_else(|| anyhow::anyhow!("Macro with id {} doesn't exist", id.0))?;
+        std::mem::swap(&mut self.state, &mut macro_def.state);
+        std::mem::swap(&mut self.user_state.exposed_nodes, &mut macro_def.exposed_nodes);
+        self.user_state.macro_stack.push(id);
+        self.refresh_macro_labels();
+        Ok(())
+    }
+
+    // returns false if we're already at the top level graph
+    pub fn exit_macro(&mut self) -> bool {
+        let Some(id) = self.user_state.macro_stack.pop() else {
+            return false;
+        };
+        if let Some(macro_def) = self.user_state.macros.iter_mut().find(|m| m.id == id) {
+            std::mem::swap(&mut self.state, &mut macro_def.state);
+            std::mem::swap(&mut self.user_state.exposed_nodes, &mut macro_def.exposed_nodes);
+        }
+        self.refresh_macro_labels();
+        true
+    }
+
+    // macros can be renamed while editing them, so update the node titles in the graph we're showing
+    fn refresh_macro_labels(&mut self) {
+        for node in self.state.graph.nodes.values_mut() {
+            if let PulseNodeTemplate::Macro { id } = node.user_data.template {
+                if let Some(macro_def) = self.user_state.find_macro(id) {
+                    node.label = macro_def.name.clone();
+                }
+            }
+        }
+    }
+
+    // The state with the top level graph in the editor, as it should be saved or compiled.
+    pub fn root_state(&self) -> Cow<'_, FullGraphState> {
+        if self.user_state.macro_stack.is_empty() {
+            return Cow::Borrowed(self);
+        }
+        let mut root = self.clone();
+        while root.exit_macro() {}
+        Cow::Owned(root)
+    }
+
+    pub fn current_macro_mut(&mut self) -> Option<&mut MacroDefinition> {
+        let id = *self.user_state.macro_stack.last()?;
+        self.user_state.macros.iter_mut().find(|m| m.id == id)
+    }
+
+    // Moves the nodes into a new macro, and puts a macro node in their place.
+    // Connections going in and out of the selection become ports of the macro.
+    pub fn collapse_to_macro(&mut self, node_ids: &[NodeId]) -> anyhow::Result<NodeId> {
+        let graph = &self.state.graph;
+        let node_ids: Vec<NodeId> = node_ids.iter().copied()
+            .filter(|id| graph.nodes.get(*id).is_some_and(|node| !matches!(
+                node.user_data.template,
+                PulseNodeTemplate::MacroInputs | PulseNodeTemplate::MacroOutputs
+            )))
+            .collect();
+        if node_ids.is_empty() {
+            anyhow::bail!("No nodes selected to collapse into a macro");
+        }
+        // node references (eg. CallNode -> Function) can't point in or out of a macro, every use of the macro
+        // gets its own copy of the nodes when compiling.
+        let crossing = self.references_crossing(&node_ids);
+        if !crossing.is_empty() {
+            anyhow::bail!(
+                "Can't collapse the selection into a macro, these nodes refer to nodes on the other side of it: {}",
+                crossing.join(", ")
+            );
+        }
+
+        // outer output -> (port name, inner inputs it feeds as (node index, input name))
+        let mut incoming: Vec<(OutputId, String, Vec<InnerInput>)> = vec![];
+        // inner output -> (port name, outer inputs it feeds)
+        let mut outgoing: Vec<(OutputId, String, Vec<InputId>)> = vec![];
+        let mut inputs = vec![];
+        let mut outputs = vec![];
+        for (input_id, output_ids) in graph.iter_connection_groups() {
+            let input = graph.get_input(input_id);
+            let input_idx = node_ids.iter().position(|id| *id == input.node);
+            for output_id in output_ids {
+                let output = graph.get_output(output_id);
+                let output_idx = node_ids.iter().position(|id| *id == output.node);
+                match (output_idx, input_idx) {
+                    // going into the macro
+                    (None, Some(idx)) => {
+                        let input_name = param_name(&graph.nodes[input.node].inputs, input_id);
+                        if let Some(entry) = incoming.iter_mut().find(|entry| entry.0 == output_id) {
+                            entry.2.push((idx, input_name));
+                            continue;
+                        }
+                        let name = unique_port_name(&inputs, param_name(&graph.nodes[output.node].outputs, output_id));
+                        inputs.push(MacroPort {
+                            name: name.clone(),
+                            typ: output.typ.clone(),
+                            value: input.value.clone(),
+                            kind: match input.kind {
+                                InputParamKind::ConstantOnly => InputParamKind::ConnectionOrConstant,
+                                kind => kind,
+                            },
+                        });
+                        incoming.push((output_id, name, vec![(idx, input_name)]));
+                    }
+                    // going out of the macro
+                    (Some(_), None) => {
+                        if let Some(entry) = outgoing.iter_mut().find(|entry| entry.0 == output_id) {
+                            entry.2.push(input_id);
+                            continue;
+                        }
+                        let name = unique_port_name(&outputs, param_name(&graph.nodes[output.node].outputs, output_id));
+                        outputs.push(MacroPort {
+                            name: name.clone(),
+                            typ: output.typ.clone(),
+                            value: input.value.clone(),
+                            kind: InputParamKind::ConnectionOnly,
+                        });
+                        outgoing.push((output_id, name, vec![input_id]));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // build the inner graph, reusing the clipboard code to copy the nodes over
+        let content = ClipboardContent::from_nodes(self, &node_ids);
+        let mut inner = FullGraphState::default();
+        inner.state.pan_zoom.zoom = self.state.pan_zoom.zoom;
+        // uids are shared with the macro graphs
+        inner.user_state.next_node_uid = self.user_state.next_node_uid;
+        let zoom = inner.state.pan_zoom.zoom;
+        let inner_nodes = content.paste_into(&mut inner, egui::pos2(300.0, 100.0) * zoom);
+        let content_width = content.nodes.iter()
+            .map(|node| node.offset[0] + node.size[0] / zoom)
+            .fold(0.0, f32::max);
+
+        let inputs_node = add_port_node(&mut inner, PulseNodeTemplate::MacroInputs, egui::pos2(0.0, 100.0) * zoom);
+        for port in inputs.iter() {
+            inner.state.graph.add_output_param(inputs_node, port.name.clone(), port.typ.clone());
+        }
+        let outputs_node = add_port_node(
+            &mut inner,
+            PulseNodeTemplate::MacroOutputs,
+            egui::pos2(300.0 + content_width + 100.0, 100.0) * zoom,
+        );
+        for port in outputs.iter() {
+            inner.state.graph.add_input_param(
+                outputs_node,
+                port.name.clone(),
+                port.typ.clone(),
+                port.value.clone(),
+                port.kind,
+                true,
+            );
+        }
+        let inner_graph = &mut inner.state.graph;
+        for (_, port_name, targets) in incoming.iter() {
+            let port_output = inner_graph.nodes[inputs_node].get_output(port_name)?;
+            for (idx, input_name) in targets {
+                let input_id = inner_graph.nodes[inner_nodes[*idx]].get_input(input_name)?;
+                let pos = inner_graph.connections(input_id).len();
+                inner_graph.add_connection(port_output, input_id, pos);
+            }
+        }
+        for (output_id, port_name, _) in outgoing.iter() {
+            let output = self.state.graph.get_output(*output_id);
+            let Some(idx) = node_ids.iter().position(|id| *id == output.node) else {
+                continue;
+            };
+            let output_name = param_name(&self.state.graph.nodes[output.node].outputs, *output_id);
+            let inner_output = inner_graph.nodes[inner_nodes[idx]].get_output(&output_name)?;
+            let port_input = inner_graph.nodes[outputs_node].get_input(port_name)?;
+            inner_graph.add_connection(inner_output, port_input, 0);
+        }
+
+        self.user_state.next_node_uid = inner.user_state.next_node_uid;
+        let id = MacroId(self.user_state.macros.iter().map(|m| m.id.0 + 1).max().unwrap_or(0));
+        self.user_state.macros.push(MacroDefinition {
+            id,
+            name: format!("Macro {}", id.0),
+            inputs,
+            outputs,
+            state: inner.state,
+            exposed_nodes: inner.user_state.exposed_nodes,
+        });
+
+        // replace the selection with the macro node
+        let template = PulseNodeTemplate::Macro { id };
+        let label = template.node_graph_label(&mut self.user_state);
+        let user_data = template.user_data(&mut self.user_state);
+        let user_state = &mut self.user_state;
+        let macro_node = self.state.graph.add_node(label, user_data, |graph, node_id| {
+            template.build_node(graph, user_state, node_id)
+        });
+        let position = node_ids.iter()
+            .filter_map(|id| self.state.node_positions.get(*id))
+            .fold(egui::pos2(f32::MAX, f32::MAX), |acc, pos| acc.min(*pos));
+        self.state.node_positions.insert(macro_node, position);
+        self.state.node_sizes.insert(macro_node, egui::vec2(200.0, 200.0));
+        self.state.node_order.push(macro_node);
+
+        let graph = &mut self.state.graph;
+        let mut new_connections: HashMap<InputId, Vec<OutputId>> = HashMap::new();
+        for (output_id, port_name, _) in incoming.iter() {
+            let port_input = graph.nodes[macro_node].get_input(port_name)?;
+            new_connections.entry(port_input).or_default().push(*output_id);
+        }
+        for (_, port_name, targets) in outgoing.iter() {
+            let port_output = graph.nodes[macro_node].get_output(port_name)?;
+            for input_id in targets {
+                new_connections.entry(*input_id).or_default().push(port_output);
+            }
+        }
+        for node_id in node_ids.iter() {
+            graph.remove_node(*node_id);
+            self.state.node_positions.remove(*node_id);
+            self.state.node_sizes.remove(*node_id);
+            self.user_state.exposed_nodes.remove(*node_id);
+        }
+        self.state.node_order.retain(|id| !node_ids.contains(id));
+        for (input_id, output_ids) in new_connections {
+            for output_id in output_ids {
+                let pos = graph.connections(input_id).len();
+                graph.add_connection(output_id, input_id, pos);
+            }
+        }
+        self.state.selected_nodes = vec![macro_node];
+        Ok(macro_node)
+    }
+
+    // Node references between the given nodes and the rest of the graph, as "from -> to" descriptions.
+    fn references_crossing(&self, node_ids: &[NodeId]) -> Vec<String> {
+        let graph = &self.state.graph;
+        let mut crossing = vec![];
+        for (node_id, node) in graph.nodes.iter() {
+            for (_, input_id) in node.inputs.iter() {
+                let PulseGraphValueType::NodeChoice { node: Some(target) } = graph.get_input(*input_id).value else {
+                    continue;
+                };
+                if node_ids.contains(&node_id) != node_ids.contains(&target) {
+                    crossing.push(format!("'{}' -> '{}'", self.node_display_name(node_id), self.node_display_name(target)));
+                }
+            }
+        }
+        crossing
+    }
+
+    fn node_display_name(&self, node_id: NodeId) -> String {
+        match self.user_state.exposed_nodes.get(node_id) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => self.state.graph.nodes.get(node_id).map(|node| node.label.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+fn add_port_node(full_state: &mut FullGraphState, template: PulseNodeTemplate, position: egui::Pos2) -> NodeId {
+    let FullGraphState { state, user_state, .. } = full_state;
+    let node_id = state.graph.add_node(
+        template.node_graph_label(user_state),
+        template.user_data(user_state),
+        |_, _| {},
+    );
+    state.node_positions.insert(node_id, position);
+    state.node_sizes.insert(node_id, egui::vec2(200.0, 200.0));
+    state.node_order.push(node_id);
+    node_id
+}
+
+fn param_name<T: PartialEq>(params: &[(String, T)], id: T) -> String {
+    params.iter()
+        .find(|(_, param_id)| *param_id == id)
+        .map(|(name, _)| name.clone())
+        .unwrap_or_default()
+}
+
+fn unique_port_name(ports: &[MacroPort], name: String) -> String {
+    if !ports.iter().any(|port| port.name == name) {
+        return name;
+    }
+    (1..).map(|i| format!("{name}{i}"))
+        .find(|candidate| !ports.iter().any(|port| &port.name == candidate))
+        .unwrap()
+}
@@ -0,0 +1,853 @@
+// Three-way merge of graph files, for use as a git mergetool (see cli.rs). Nodes are matched by their stable
+// ids (see document.rs), and anything that was changed on one side only, or the same way on both, is merged
+// on its own. What's left are conflicts, which get resolved in the editor by picking a side for each one.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use eframe::egui::{self, Button, RichText};
+use rfd::MessageDialog;
+use super::document::*;
+use super::history::History;
+use super::types::*;
+use super::{FullGraphState, PulseGraphEditor};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+impl Side {
+    fn name(&self) -> &'static str {
+        match self {
+            Side::Ours => "ours",
+            Side::Theirs => "theirs",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ConflictItem {
+    // graph is None for the top level graph
+    Node { graph: Option<MacroId>, uid: NodeUid },
+    Variable(String),
+    PublicOutput(String),
+    Library(LibraryId),
+    // name and ports of a macro, or the whole macro if it was added or deleted. Its nodes are merged separately.
+    Macro(MacroId),
+    // domain and subtype
+    GraphType,
+}
+
+#[derive(Clone, Debug)]
+pub struct MergeConflict {
+    pub item: ConflictItem,
+    pub name: String,
+    pub description: String,
+    pub resolution: Option<Side>,
+}
+
+fn add_conflict(conflicts: &mut Vec<MergeConflict>, item: ConflictItem, name: &str, description: &str) {
+    // one entry per thing, a node can conflict in more than one way
+    if let Some(conflict) = conflicts.iter_mut().find(|conflict| conflict.item == item) {
+        conflict.description = format!("{}, {description}", conflict.description);
+        return;
+    }
+    conflicts.push(MergeConflict {
+        item,
+        name: name.to_string(),
+        description: description.to_string(),
+        resolution: None,
+    });
+}
+
+pub struct MergeResult {
+    // conflicts are left as they are on our side
+    pub merged: GraphDocument,
+    pub conflicts: Vec<MergeConflict>,
+    // their side, with the nodes added on both sides moved to new ids
+    pub theirs: GraphDocument,
+}
+
+// The merge of a value, None if both sides changed it differently.
+fn merge3<T: Clone + PartialEq>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if ours == theirs || theirs == base {
+        Some(ours.clone())
+    } else if ours == base {
+        Some(theirs.clone())
+    } else {
+        None
+    }
+}
+
+// Merges lists of things with a name (variables, outputs...). Our order is kept, with their new ones at the end.
+fn merge_keyed<T: Clone + PartialEq, K: PartialEq + Clone>(
+    base: &[T],
+    ours: &[T],
+    theirs: &[T],
+    key: impl Fn(&T) -> K,
+    mut conflict: impl FnMut(K),
+) -> Vec<T> {
+    let mut keys: Vec<K> = ours.iter().map(&key).collect();
+    keys.extend(theirs.iter().map(&key).filter(|k| !ours.iter().any(|item| key(item) == *k)).collect::<Vec<_>>());
+    let find = |items: &[T], k: &K| items.iter().find(|item| key(item) == *k).cloned();
+    let mut merged = vec![];
+    for k in keys {
+        let ours_item = find(ours, &k);
+        match merge3(&find(base, &k), &ours_item, &find(theirs, &k)) {
+            Some(item) => merged.extend(item),
+            None => {
+                conflict(k);
+                merged.extend(ours_item);
+            }
+        }
+    }
+    merged
+}
+
+// Merges a node that was changed on both sides field by field. Returns the fields that conflict if it can't.
+fn merge_node(base: &DocumentNode, ours: &DocumentNode, theirs: &DocumentNode) -> Result<DocumentNode, Vec<&'static str>> {
+    let mut conflicts = vec![];
+    macro_rules! merge_field {
+        ($field:ident, $name:literal) => {
+            merge3(&base.$field, &ours.$field, &theirs.$field).unwrap_or_else(|| {
+                conflicts.push($name);
+                ours.$field.clone()
+            })
+        };
+    }
+    let names = |node: &DocumentNode| node.inputs.iter().map(|input| input.name.clone()).collect::<Vec<_>>();
+    // with the same ports on all sides every input can be merged on its own
+    let inputs = if names(base) == names(ours) && names(ours) == names(theirs) {
+        base.inputs.iter().zip(ours.inputs.iter()).zip(theirs.inputs.iter())
+            .map(|((base, ours), theirs)| merge3(base, ours, theirs).unwrap_or_else(|| {
+                conflicts.push("inputs");
+                ours.clone()
+            }))
+            .collect()
+    } else {
+        merge_field!(inputs, "inputs")
+    };
+    let node = DocumentNode {
+        template: merge_field!(template, "type"),
+        label: merge_field!(label, "label"),
+        position: merge_field!(position, "position"),
+        size: merge_field!(size, "size"),
+        input_hint_text: merge_field!(input_hint_text, "hint"),
+        custom_output_type: merge_field!(custom_output_type, "output type"),
+        inputs,
+        outputs: merge_field!(outputs, "outputs"),
+    };
+    if conflicts.is_empty() {
+        Ok(node)
+    } else {
+        conflicts.dedup();
+        Err(conflicts)
+    }
+}
+
+// Nodes added on both sides most likely got the same id, theirs are moved to new ids so both are kept.
+// `next` is the next uid that's free on all sides.
+fn renumber_added(base: &DocumentGraph, ours: &DocumentGraph, theirs: &mut DocumentGraph, next: &mut NodeUid) {
+    let mut new_ids: HashMap<NodeUid, NodeUid> = HashMap::new();
+    for (uid, node) in theirs.nodes.iter() {
+        if !base.nodes.contains_key(uid) && ours.nodes.get(uid).is_some_and(|ours| ours != node) {
+            new_ids.insert(*uid, *next);
+            *next += 1;
+        }
+    }
+    if new_ids.is_empty() {
+        return;
+    }
+    let id = |uid: NodeUid| new_ids.get(&uid).copied().unwrap_or(uid);
+    theirs.nodes = std::mem::take(&mut theirs.nodes).into_iter()
+        .map(|(uid, mut node)| {
+            for input in node.inputs.iter_mut() {
+                input.target = input.target.map(id);
+            }
+            (id(uid), node)
+        })
+        .collect();
+    for connection in theirs.connections.iter_mut() {
+        connection.from.0 = id(connection.from.0);
+        connection.to.0 = id(connection.to.0);
+    }
+    theirs.exposed_nodes = std::mem::take(&mut theirs.exposed_nodes).into_iter()
+        .map(|(uid, name)| (id(uid), name))
+        .collect();
+}
+
+// connections to nodes or ports that were deleted on the other side
+fn remove_dangling(graph: &mut DocumentGraph) {
+    let nodes = &graph.nodes;
+    graph.connections.retain(|connection| {
+        nodes.get(&connection.from.0).is_some_and(|node| node.outputs.iter().any(|(name, _)| *name == connection.from.1))
+            && nodes.get(&connection.to.0).is_some_and(|node| node.inputs.iter().any(|input| input.name == connection.to.1))
+    });
+    graph.exposed_nodes.retain(|uid, _| nodes.contains_key(uid));
+}
+
+type IncomingConnections = BTreeMap<(NodeUid, String), Vec<(NodeUid, String)>>;
+
+fn incoming(graph: &DocumentGraph) -> IncomingConnections {
+    let mut incoming = IncomingConnections::new();
+    for connection in graph.connections.iter() {
+        incoming.entry(connection.to.clone()).or_default().push(connection.from.clone());
+    }
+    incoming
+}
+
+fn merge_graph(
+    graph: Option<MacroId>,
+    base: &DocumentGraph,
+    ours: &DocumentGraph,
+    theirs: &DocumentGraph,
+    conflicts: &mut Vec<MergeConflict>,
+) -> DocumentGraph {
+    let mut merged = DocumentGraph::default();
+    let mut conflicted: BTreeSet<NodeUid> = BTreeSet::new();
+    let node_conflict = |conflicts: &mut Vec<MergeConflict>, conflicted: &mut BTreeSet<NodeUid>, uid: NodeUid, description: &str| {
+        let name = [ours, theirs, base].iter()
+            .find_map(|graph| graph.nodes.get(&uid))
+            .map_or_else(|| format!("Node {uid}"), |node| node.label.clone());
+        add_conflict(conflicts, ConflictItem::Node { graph, uid }, &name, description);
+        conflicted.insert(uid);
+    };
+
+    let uids: BTreeSet<NodeUid> = base.nodes.keys().chain(ours.nodes.keys()).chain(theirs.nodes.keys()).copied().collect();
+    for uid in uids {
+        let (b, o, t) = (base.nodes.get(&uid), ours.nodes.get(&uid), theirs.nodes.get(&uid));
+        let node = match merge3(&b, &o, &t) {
+            Some(node) => node.cloned(),
+            None => match (b, o, t) {
+                (Some(b), Some(o), Some(t)) => match merge_node(b, o, t) {
+                    Ok(node) => Some(node),
+                    Err(fields) => {
+                        node_conflict(conflicts, &mut conflicted, uid, &format!("{} changed on both sides", fields.join(", ")));
+                        Some(o.clone())
+                    }
+                },
+                (None, ..) => {
+                    node_conflict(conflicts, &mut conflicted, uid, "added differently on both sides");
+                    o.cloned()
+                }
+                _ => {
+                    node_conflict(conflicts, &mut conflicted, uid, "deleted on one side and changed on the other");
+                    o.cloned()
+                }
+            },
+        };
+        if let Some(node) = node {
+            merged.nodes.insert(uid, node);
+        }
+        let (b, o, t) = (base.exposed_nodes.get(&uid), ours.exposed_nodes.get(&uid), theirs.exposed_nodes.get(&uid));
+        let name = merge3(&b, &o, &t).unwrap_or_else(|| {
+            node_conflict(conflicts, &mut conflicted, uid, "name changed on both sides");
+            o
+        });
+        if let Some(name) = name {
+            merged.exposed_nodes.insert(uid, name.clone());
+        }
+    }
+
+    let (base_incoming, ours_incoming, theirs_incoming) = (incoming(base), incoming(ours), incoming(theirs));
+    let ports: BTreeSet<&(NodeUid, String)> = base_incoming.keys().chain(ours_incoming.keys()).chain(theirs_incoming.keys()).collect();
+    for port in ports {
+        let empty = vec![];
+        let b = base_incoming.get(port).unwrap_or(&empty);
+        let o = ours_incoming.get(port).unwrap_or(&empty);
+        let t = theirs_incoming.get(port).unwrap_or(&empty);
+        let sources = match merge3(b, o, t) {
+            // conflicting nodes keep our connections until a side is picked
+            _ if conflicted.contains(&port.0) => o.clone(),
+            Some(sources) => sources,
+            None => {
+                let takes_many = merged.nodes.get(&port.0)
+                    .and_then(|node| node.inputs.iter().find(|input| input.name == port.1))
+                    .is_some_and(|input| input.max_connections != NonZeroU32::new(1));
+                if takes_many {
+                    // keep what either side added, and drop what either side removed
+                    let mut sources: Vec<_> = o.iter().filter(|source| t.contains(source) || !b.contains(source)).cloned().collect();
+                    sources.extend(t.iter().filter(|source| !b.contains(source) && !o.contains(source)).cloned());
+                    sources
+                } else {
+                    node_conflict(conflicts, &mut conflicted, port.0, &format!("connection to '{}' changed on both sides", port.1));
+                    o.clone()
+                }
+            }
+        };
+        merged.connections.extend(sources.into_iter().map(|from| DocumentConnection { from, to: port.clone() }));
+    }
+
+    merged.sort_connections();
+    merged.group_frames = merge3(&base.group_frames, &ours.group_frames, &theirs.group_frames).unwrap_or_else(|| {
+        // frames have nothing to match them by, so both sides' new ones are kept and nothing is removed
+        let mut frames = ours.group_frames.clone();
+        frames.extend(theirs.group_frames.iter().filter(|frame| !base.group_frames.contains(frame) && !ours.group_frames.contains(frame)).cloned());
+        frames
+    });
+    remove_dangling(&mut merged);
+    merged
+}
+
+pub fn merge_documents(base: &GraphDocument, ours: &GraphDocument, mut theirs: GraphDocument) -> MergeResult {
+    let mut next_node_uid = [base, ours, &theirs].iter()
+        .map(|document| document.next_node_uid.max(document.max_node_uid() + 1))
+        .max()
+        .unwrap_or(1);
+    renumber_added(&base.graph, &ours.graph, &mut theirs.graph, &mut next_node_uid);
+    for theirs_macro in theirs.macros.iter_mut() {
+        let (Some(base_macro), Some(ours_macro)) = (base.find_macro(theirs_macro.id), ours.find_macro(theirs_macro.id)) else {
+            continue;
+        };
+        renumber_added(&base_macro.graph, &ours_macro.graph, &mut theirs_macro.graph, &mut next_node_uid);
+    }
+    theirs.next_node_uid = next_node_uid;
+
+    let mut conflicts = vec![];
+    let graph_type = |document: &GraphDocument| (document.graph_domain.clone(), document.graph_subtype.clone());
+    let (graph_domain, graph_subtype) = merge3(&graph_type(base), &graph_type(ours), &graph_type(&theirs)).unwrap_or_else(|| {
+        add_conflict(&mut conflicts, ConflictItem::GraphType, "Graph type", "changed on both sides");
+        graph_type(ours)
+    });
+    let variables = merge_keyed(&base.variables, &ours.variables, &theirs.variables, |var| var.name.clone(), |name| {
+        add_conflict(&mut conflicts, ConflictItem::Variable(name.clone()), &name, "variable changed on both sides");
+    });
+    let public_outputs = merge_keyed(&base.public_outputs, &ours.public_outputs, &theirs.public_outputs, |output| output.name.clone(), |name| {
+        add_conflict(&mut conflicts, ConflictItem::PublicOutput(name.clone()), &name, "output changed on both sides");
+    });
+    let libraries = merge_keyed(&base.libraries, &ours.libraries, &theirs.libraries, |lib| lib.id, |id| {
+        let name = ours.libraries.iter().find(|lib| lib.id == id).map_or("Library", |lib| lib.name.as_str());
+        add_conflict(&mut conflicts, ConflictItem::Library(id), name, "import changed on both sides");
+    });
+    let graph = merge_graph(None, &base.graph, &ours.graph, &theirs.graph, &mut conflicts);
+
+    let mut macro_ids: Vec<MacroId> = ours.macros.iter().map(|def| def.id).collect();
+    macro_ids.extend(theirs.macros.iter().map(|def| def.id).filter(|id| ours.find_macro(*id).is_none()));
+    let mut macros = vec![];
+    for id in macro_ids {
+        let (b, o, t) = (base.find_macro(id), ours.find_macro(id), theirs.find_macro(id));
+        let merged = match merge3(&b, &o, &t) {
+            Some(def) => def.cloned(),
+            None => match (b, o, t) {
+                (Some(b), Some(o), Some(t)) => {
+                    let header = |def: &DocumentMacro| (def.name.clone(), def.inputs.clone(), def.outputs.clone());
+                    let (name, inputs, outputs) = merge3(&header(b), &header(o), &header(t)).unwrap_or_else(|| {
+                        add_conflict(&mut conflicts, ConflictItem::Macro(id), &o.name, "name or ports changed on both sides");
+                        header(o)
+                    });
+                    let graph = merge_graph(Some(id), &b.graph, &o.graph, &t.graph, &mut conflicts);
+                    Some(DocumentMacro { id, name, inputs, outputs, graph })
+                }
+                _ => {
+                    let name = o.or(t).map_or("Macro", |def| def.name.as_str());
+                    add_conflict(&mut conflicts, ConflictItem::Macro(id), name, "added or deleted on one side and changed on the other");
+                    o.cloned()
+                }
+            },
+        };
+        macros.extend(merged);
+    }
+
+    let merged = GraphDocument {
+        format_version: ours.format_version,
+        graph_domain,
+        graph_subtype,
+        variables,
+        public_outputs,
+        libraries,
+        graph,
+        macros,
+        next_node_uid,
+    };
+    MergeResult { merged, conflicts, theirs }
+}
+
+fn replace_keyed<T: Clone>(target: &mut Vec<T>, side: &[T], is_item: impl Fn(&T) -> bool) {
+    let value = side.iter().find(|item| is_item(item)).cloned();
+    match (target.iter().position(&is_item), value) {
+        (Some(idx), Some(value)) => target[idx] = value,
+        (Some(idx), None) => {
+            target.remove(idx);
+        }
+        (None, Some(value)) => target.push(value),
+        (None, None) => {}
+    }
+}
+
+// Makes the conflicting thing in `target` the way it is in `side`.
+pub fn apply_side(target: &mut GraphDocument, side: &GraphDocument, item: &ConflictItem) {
+    match item {
+        ConflictItem::Node { graph, uid } => {
+            let (Some(target_graph), Some(side_graph)) = (target.graph_mut(*graph), side.graph(*graph)) else {
+                return;
+            };
+            match side_graph.nodes.get(uid) {
+                Some(node) => target_graph.nodes.insert(*uid, node.clone()),
+                None => target_graph.nodes.remove(uid),
+            };
+            match side_graph.exposed_nodes.get(uid) {
+                Some(name) => target_graph.exposed_nodes.insert(*uid, name.clone()),
+                None => target_graph.exposed_nodes.remove(uid),
+            };
+            target_graph.connections.retain(|connection| connection.to.0 != *uid);
+            target_graph.connections.extend(side_graph.connections.iter().filter(|connection| connection.to.0 == *uid).cloned());
+            // the merge dropped the wires going out of a node that was deleted on our side, they come back
+            // unless the input they went to took another connection since
+            let outgoing: Vec<DocumentConnection> = side_graph.connections.iter()
+                .filter(|connection| connection.from.0 == *uid && connection.to.0 != *uid)
+                .filter(|connection| !target_graph.connections.contains(connection))
+                .filter(|connection| {
+                    let single = target_graph.nodes.get(&connection.to.0)
+                        .and_then(|node| node.inputs.iter().find(|input| input.name == connection.to.1))
+                        .is_some_and(|input| input.max_connections == NonZeroU32::new(1));
+                    !single || !target_graph.connections.iter().any(|other| other.to == connection.to)
+                })
+                .cloned()
+                .collect();
+            target_graph.connections.extend(outgoing);
+            target_graph.sort_connections();
+            remove_dangling(target_graph);
+        }
+        ConflictItem::Variable(name) => replace_keyed(&mut target.variables, &side.variables, |var| var.name == *name),
+        ConflictItem::PublicOutput(name) => {
+            replace_keyed(&mut target.public_outputs, &side.public_outputs, |output| output.name == *name);
+        }
+        ConflictItem::Library(id) => replace_keyed(&mut target.libraries, &side.libraries, |lib| lib.id == *id),
+        ConflictItem::Macro(id) => {
+            let target_macro = target.macros.iter_mut().find(|def| def.id == *id);
+            match (target_macro, side.find_macro(*id)) {
+                (Some(target_macro), Some(side_macro)) => {
+                    target_macro.name = side_macro.name.clone();
+                    target_macro.inputs = side_macro.inputs.clone();
+                    target_macro.outputs = side_macro.outputs.clone();
+                }
+                (None, Some(side_macro)) => target.macros.push(side_macro.clone()),
+                (Some(_), None) => target.macros.retain(|def| def.id != *id),
+                (None, None) => {}
+            }
+        }
+        ConflictItem::GraphType => {
+            target.graph_domain = side.graph_domain.clone();
+            target.graph_subtype = side.graph_subtype.clone();
+        }
+    }
+}
+
+fn load_document(path: &Path) -> anyhow::Result<GraphDocument> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {e}", path.display()))?;
+    let (full_state, _) = FullGraphState::from_saved(&contents)
+        .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+    Ok(GraphDocument::from_full_state(&full_state))
+}
+
+// A merge as it's being resolved in the editor.
+#[derive(Clone)]
+pub struct MergeSession {
+    merged_path: PathBuf,
+    merged: GraphDocument,
+    ours: GraphDocument,
+    theirs: GraphDocument,
+    conflicts: Vec<MergeConflict>,
+    // set once the result was saved, for the exit code of the mergetool
+    resolved: Arc<AtomicBool>,
+}
+
+impl MergeSession {
+    pub fn from_files(base: &Path, ours: &Path, theirs: &Path, merged_path: PathBuf) -> anyhow::Result<Self> {
+        let (base, ours, theirs) = (load_document(base)?, load_document(ours)?, load_document(theirs)?);
+        let result = merge_documents(&base, &ours, theirs);
+        Ok(Self {
+            merged_path,
+            merged: result.merged,
+            ours,
+            theirs: result.theirs,
+            conflicts: result.conflicts,
+            resolved: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub fn conflicts(&self) -> &[MergeConflict] {
+        &self.conflicts
+    }
+
+    pub fn merged_path(&self) -> &Path {
+        &self.merged_path
+    }
+
+    pub fn resolved_flag(&self) -> Arc<AtomicBool> {
+        self.resolved.clone()
+    }
+
+    // Writes the result of a merge without conflicts.
+    pub fn write_merged(&self) -> anyhow::Result<()> {
+        // going through the editor state checks that the result makes sense, and puts it in canonical order
+        let full_state = self.merged.clone().into_full_state()?;
+        std::fs::write(&self.merged_path, GraphDocument::from_full_state(&full_state).to_ron()?)?;
+        Ok(())
+    }
+}
+
+impl PulseGraphEditor {
+    // Opens the result of a merge with conflicts in a new tab, with the conflicts listed in a panel next to it.
+    pub fn with_merge(mut self, session: MergeSession, ctx: &egui::Context) -> Self {
+        let mut full_state = match session.merged.clone().into_full_state() {
+            Ok(full_state) => full_state,
+            Err(e) => {
+                MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Merge failed")
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .set_description(format!("The merged graph is not valid: {e:#}"))
+                    .show();
+                return self;
+            }
+        };
+        // libraries are relative to the graph file
+        full_state.user_state.save_file_path = Some(session.merged_path.clone());
+        full_state.user_state.bindings = self.user_state().bindings.clone();
+        full_state.verify_compat();
+        for e in full_state.user_state.reload_libraries() {
+            println!("[Load] Warning: {e:#}");
+        }
+        if !self.is_active_tab_blank() {
+            self.new_tab(ctx);
+        }
+        self.full_state = full_state;
+        self.history = History::default();
+        self.merge = Some(session);
+        self.update_titlebar(ctx);
+        self
+    }
+
+    // switches to the tab with the merge result, returns false if it was closed
+    fn show_merge_tab(&mut self, ctx: &egui::Context) -> bool {
+        let Some(merged_path) = self.merge.as_ref().map(|session| session.merged_path.clone()) else {
+            return false;
+        };
+        let tab = (0..self.tabs.len()).find(|idx| {
+            let full_state = if *idx == self.active_tab { &self.full_state } else { &self.tabs[*idx].full_state };
+            full_state.user_state.save_file_path.as_ref() == Some(&merged_path)
+        });
+        match tab {
+            Some(idx) => {
+                self.switch_tab(idx, ctx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn resolve_conflict(&mut self, idx: usize, side: Side, ctx: &egui::Context) -> anyhow::Result<()> {
+        if !self.show_merge_tab(ctx) {
+            anyhow::bail!("The tab with the merged graph was closed");
+        }
+        let Some(session) = self.merge.as_ref() else {
+            return Ok(());
+        };
+        let conflict = &session.conflicts[idx];
+        let root = self.full_state.root_state().into_owned();
+        let mut document = GraphDocument::from_full_state(&root);
+        let side_document = match side {
+            Side::Ours => &session.ours,
+            Side::Theirs => &session.theirs,
+        };
+        apply_side(&mut document, side_document, &conflict.item);
+        let mut full_state = document.into_full_state()?;
+        // keep what isn't part of the file
+        full_state.state.pan_zoom.pan = root.state.pan_zoom.pan;
+        full_state.user_state.save_file_path = root.user_state.save_file_path.clone();
+        full_state.user_state.bindings = root.user_state.bindings.clone();
+        full_state.user_state.loaded_libraries = root.user_state.loaded_libraries.clone();
+        full_state.verify_compat();
+        let label = format!("Use {} for {}", side.name(), conflict.name);
+        self.full_state = full_state;
+        if let Some(session) = self.merge.as_mut() {
+            session.conflicts[idx].resolution = Some(side);
+        }
+        self.commit_history(Some(&label));
+        Ok(())
+    }
+
+    fn finish_merge(&mut self, ctx: &egui::Context) -> anyhow::Result<()> {
+        if !self.show_merge_tab(ctx) {
+            anyhow::bail!("The tab with the merged graph was closed");
+        }
+        let Some(merged_path) = self.merge.as_ref().map(|session| session.merged_path.clone()) else {
+            return Ok(());
+        };
+        self.perform_save(Some(&merged_path))?;
+        if let Some(session) = self.merge.take() {
+            session.resolved.store(true, Ordering::Relaxed);
+        }
+        // the merge is done when the mergetool exits
+        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        Ok(())
+    }
+
+    pub(super) fn merge_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(session) = self.merge.as_ref() else {
+            return;
+        };
+        let mut resolve = None;
+        let mut jump_to = None;
+        let mut finish = false;
+        let mut abort = false;
+        ui.heading("Merge conflicts");
+        let resolved = session.conflicts.iter().filter(|conflict| conflict.resolution.is_some()).count();
+        ui.label(format!("{resolved} of {} resolved", session.conflicts.len()));
+        // only nodes of the graph that's shown can be jumped to
+        let shown_graph = self.user_state().macro_stack.last().copied();
+        egui::ScrollArea::vertical().max_height(ui.available_height() - 60.0).show(ui, |ui| {
+            for (idx, conflict) in session.conflicts.iter().enumerate() {
+                ui.separator();
+                ui.horizontal(|ui| {
+                    let color = if conflict.resolution.is_some() { ui.visuals().weak_text_color() } else { ui.visuals().warn_fg_color };
+                    match conflict.item {
+                        ConflictItem::Node { graph, uid } if graph == shown_graph => {
+                            if ui.link(RichText::new(&conflict.name).color(color)).clicked() {
+                                jump_to = Some(uid);
+                            }
+                        }
+                        _ => {
+                            ui.label(RichText::new(&conflict.name).color(color));
+                        }
+                    }
+                    ui.label(&conflict.description);
+                });
+                ui.horizontal(|ui| {
+                    for side in [Side::Ours, Side::Theirs] {
+                        let button = Button::selectable(conflict.resolution == Some(side), format!("Use {}", side.name()));
+                        if ui.add(button).clicked() {
+                            resolve = Some((idx, side));
+                        }
+                    }
+                });
+            }
+        });
+        ui.separator();
+        ui.horizontal(|ui| {
+            let all_resolved = resolved == session.conflicts.len();
+            if ui.add_enabled(all_resolved, Button::new("Finish merge"))
+                .on_hover_text(format!("Save the result to {} and close the editor", session.merged_path.display()))
+                .on_disabled_hover_text("Pick a side for every conflict first")
+                .clicked() {
+                finish = true;
+            }
+            if ui.button("Abort").on_hover_text("Close the editor without saving, the merge is left unresolved").clicked() {
+                abort = true;
+            }
+        });
+
+        let ctx = ui.ctx().clone();
+        let result = if let Some((idx, side)) = resolve {
+            self.resolve_conflict(idx, side, &ctx)
+        } else if finish {
+            self.finish_merge(&ctx)
+        } else {
+            Ok(())
+        };
+        if let Err(e) = result {
+            MessageDialog::new()
+                .set_level(rfd::MessageLevel::Error)
+                .set_title("Merge failed")
+                .set_buttons(rfd::MessageButtons::Ok)
+                .set_description(format!("{e:#}"))
+                .show();
+        }
+        if abort {
+            self.merge = None;
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+        if let Some(uid) = jump_to {
+            if let Some(node_id) = self.state().graph.nodes.iter().find(|(_, node)| node.user_data.uid == uid).map(|(node_id, _)| node_id) {
+                self.jump_to_node(node_id);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use eframe::egui;
+    use super::*;
+
+    fn add_log(full_state: &mut FullGraphState, x: f32) -> NodeUid {
+        let node_id = full_state.add_node_at(PulseNodeTemplate::DebugLog, egui::pos2(x, 0.0), egui::vec2(200.0, 200.0));
+        full_state.state.graph.nodes[node_id].user_data.uid
+    }
+
+    // a method and three logs, with the method connected to the first log
+    fn base_state() -> (FullGraphState, [NodeUid; 4]) {
+        let mut full_state = FullGraphState::default();
+        let method_id = full_state.add_node_at(PulseNodeTemplate::CellPublicMethod, egui::Pos2::ZERO, egui::vec2(200.0, 200.0));
+        let method = full_state.state.graph.nodes[method_id].user_data.uid;
+        let logs = [add_log(&mut full_state, 300.0), add_log(&mut full_state, 600.0), add_log(&mut full_state, 900.0)];
+        (full_state, [method, logs[0], logs[1], logs[2]])
+    }
+
+    fn base_document() -> (GraphDocument, [NodeUid; 4]) {
+        let (full_state, uids) = base_state();
+        let mut document = GraphDocument::from_full_state(&full_state);
+        connect(&mut document.graph, uids[0], uids[1]);
+        (document, uids)
+    }
+
+    fn connect(graph: &mut DocumentGraph, from: NodeUid, to: NodeUid) {
+        graph.connections.push(DocumentConnection {
+            from: (from, "outAction".to_string()),
+            to: (to, "ActionIn".to_string()),
+        });
+        graph.sort_connections();
+    }
+
+    fn sources(graph: &DocumentGraph, to: NodeUid) -> Vec<NodeUid> {
+        graph.connections.iter()
+            .filter(|connection| connection.to == (to, "ActionIn".to_string()))
+            .map(|connection| connection.from.0)
+            .collect()
+    }
+
+    fn message(graph: &DocumentGraph, uid: NodeUid) -> &PulseGraphValueType {
+        &graph.nodes[&uid].inputs.iter().find(|input| input.name == "pMessage").unwrap().value
+    }
+
+    fn set_message(graph: &mut DocumentGraph, uid: NodeUid, value: &str) {
+        let node = graph.nodes.get_mut(&uid).unwrap();
+        let input = node.inputs.iter_mut().find(|input| input.name == "pMessage").unwrap();
+        input.value = PulseGraphValueType::String { value: value.into() };
+    }
+
+    #[test]
+    fn test_merge_edits_on_different_sides() {
+        let (base, [method, log_a, log_b, _]) = base_document();
+        let mut ours = base.clone();
+        ours.graph.nodes.get_mut(&log_a).unwrap().position = (300.0, 500.0);
+        let mut theirs = base.clone();
+        set_message(&mut theirs.graph, log_b, "hello");
+        connect(&mut theirs.graph, log_a, log_b);
+
+        let result = merge_documents(&base, &ours, theirs);
+        assert!(result.conflicts.is_empty());
+        let graph = &result.merged.graph;
+        assert_eq!(graph.nodes[&log_a].position, (300.0, 500.0));
+        assert_eq!(*message(graph, log_b), PulseGraphValueType::String { value: "hello".into() });
+        assert_eq!(sources(graph, log_a), vec![method]);
+        assert_eq!(sources(graph, log_b), vec![log_a]);
+    }
+
+    #[test]
+    fn test_merge_nodes_added_on_both_sides() {
+        let (full_state, [method, ..]) = base_state();
+        let base = GraphDocument::from_full_state(&full_state);
+        let mut ours_state = full_state.clone();
+        let ours_added = add_log(&mut ours_state, 1200.0);
+        let mut theirs_state = full_state;
+        let theirs_added = add_log(&mut theirs_state, 1500.0);
+        // both sides handed out the same uid
+        assert_eq!(ours_added, theirs_added);
+        let ours = GraphDocument::from_full_state(&ours_state);
+        let mut theirs = GraphDocument::from_full_state(&theirs_state);
+        connect(&mut theirs.graph, method, theirs_added);
+
+        let result = merge_documents(&base, &ours, theirs);
+        assert!(result.conflicts.is_empty());
+        let graph = &result.merged.graph;
+        assert_eq!(graph.nodes.len(), base.graph.nodes.len() + 2);
+        assert_eq!(graph.nodes[&ours_added].position, (1200.0, 0.0));
+        let (renumbered, _) = graph.nodes.iter().find(|(_, node)| node.position == (1500.0, 0.0)).unwrap();
+        assert_ne!(*renumbered, ours_added);
+        // their connection follows the node to its new uid
+        assert_eq!(sources(graph, *renumbered), vec![method]);
+        assert!(sources(graph, ours_added).is_empty());
+        assert!(result.theirs.graph.nodes.contains_key(renumbered));
+        assert!(result.merged.next_node_uid > *renumbered);
+    }
+
+    #[test]
+    fn test_merge_node_deleted_on_one_side() {
+        let (mut base, [method, log_a, log_b, _]) = base_document();
+        connect(&mut base.graph, log_a, log_b);
+        let mut ours = base.clone();
+        ours.graph.nodes.remove(&log_a);
+        ours.graph.connections.clear();
+        let mut theirs = base.clone();
+        set_message(&mut theirs.graph, log_a, "changed");
+
+        let mut result = merge_documents(&base, &ours, theirs);
+        assert_eq!(result.conflicts.len(), 1);
+        let conflict = &result.conflicts[0];
+        assert_eq!(conflict.item, ConflictItem::Node { graph: None, uid: log_a });
+        assert_eq!(conflict.description, "deleted on one side and changed on the other");
+        // left the way it is on our side until a side is picked
+        assert!(!result.merged.graph.nodes.contains_key(&log_a));
+
+        apply_side(&mut result.merged, &result.theirs, &conflict.item);
+        let graph = &result.merged.graph;
+        assert_eq!(*message(graph, log_a), PulseGraphValueType::String { value: "changed".into() });
+        assert_eq!(sources(graph, log_a), vec![method]);
+        // and what it was connected to
+        assert_eq!(sources(graph, log_b), vec![log_a]);
+    }
+
+    #[test]
+    fn test_merge_wide_input_connections() {
+        let (mut base, [method, log_a, log_b, log_c]) = base_document();
+        connect(&mut base.graph, method, log_c);
+        let wide_input = |document: &mut GraphDocument, max_connections| {
+            let node = document.graph.nodes.get_mut(&log_c).unwrap();
+            node.inputs.iter_mut().find(|input| input.name == "ActionIn").unwrap().max_connections = max_connections;
+        };
+        wide_input(&mut base, None);
+        // ours adds a connection, theirs removes the old one and adds another
+        let mut ours = base.clone();
+        connect(&mut ours.graph, log_a, log_c);
+        let mut theirs = base.clone();
+        theirs.graph.connections.retain(|connection| connection.to.0 != log_c);
+        connect(&mut theirs.graph, log_b, log_c);
+
+        let result = merge_documents(&base, &ours, theirs.clone());
+        assert!(result.conflicts.is_empty());
+        let mut merged_sources = sources(&result.merged.graph, log_c);
+        merged_sources.sort();
+        assert_eq!(merged_sources, vec![log_a, log_b]);
+
+        // an input that takes a single connection can't keep both
+        for document in [&mut base, &mut ours, &mut theirs] {
+            wide_input(document, NonZeroU32::new(1));
+        }
+        let result = merge_documents(&base, &ours, theirs);
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(result.conflicts[0].item, ConflictItem::Node { graph: None, uid: log_c });
+        assert_eq!(sources(&result.merged.graph, log_c), sources(&ours.graph, log_c));
+    }
+
+    #[test]
+    fn test_apply_side_round_trip() {
+        let (base, [_, log_a, log_b, _]) = base_document();
+        let mut ours = base.clone();
+        set_message(&mut ours.graph, log_a, "ours");
+        ours.graph_subtype = "ours".into();
+        let mut theirs = base.clone();
+        set_message(&mut theirs.graph, log_a, "theirs");
+        set_message(&mut theirs.graph, log_b, "theirs");
+        theirs.graph_subtype = "theirs".into();
+
+        let result = merge_documents(&base, &ours, theirs);
+        assert_eq!(result.conflicts.len(), 2);
+        let mut merged = result.merged.clone();
+        for conflict in result.conflicts.iter() {
+            apply_side(&mut merged, &result.theirs, &conflict.item);
+        }
+        assert!(merged == result.theirs);
+        for conflict in result.conflicts.iter() {
+            apply_side(&mut merged, &ours, &conflict.item);
+        }
+        assert!(merged == result.merged);
+        assert_eq!(*message(&merged.graph, log_b), PulseGraphValueType::String { value: "theirs".into() });
+    }
+}
@@ -1,33 +1,225 @@
-use ron::{Value, value::{Map, Number, F32}};
-
-// This is currently unused due to issues with RON deserializing into Value type without losing version information
-#[allow(dead_code)]
-fn migrate_v2(editor: &mut Value) -> anyhow::Result<()> {
-    // new entry node_sized needs to be populated with node_ids in the graph.
-    if let Value::Map(state) = editor {
-        let nodes = &state[&Value::String("node_order".to_string())];
-        if let Value::Map(nodes) = nodes {
-            let mut node_sizes = Value::Map(Map::new());
-            let node_sizes_map = match &mut node_sizes {
-                Value::Map(map) => map,
-                _ => unreachable!("Can't be not a map"),
-            };
-            for (version, _) in nodes.iter() {
-                let mut sizes_map = Map::new();
-                sizes_map.insert(
-                    Value::String("x".into()),
-                    Value::Number(Number::F32(F32::new(200.0)))
-                );
-                sizes_map.insert(
-                    Value::String("y".into()),
-                    Value::Number(Number::F32(F32::new(200.0)))
+// Upgrades of graphs saved by older versions of the editor. Saved graphs carry the version of the file
+// format they were written with, and every change to the format that needs the old files fixed up gets a step
+// here that upgrades them from one version to the next.
+// Graphs saved before the version was written are version 0 and go through every step, so the steps check
+// what's in the graph instead of assuming it's missing.
+use serde::Deserialize;
+use eframe::egui;
+use egui_node_graph2::*;
+use crate::pulsetypes::*;
+use super::types::*;
+use super::FullGraphState;
+
+// version written into saved graphs, the same as the number of steps below
+pub const FORMAT_VERSION: u32 = 6;
+
+struct Migration {
+    // shown to the user when the step changed something
+    description: &'static str,
+    // upgrades a graph from the version of its index in MIGRATIONS to the next one.
+    // Returns true if anything was changed.
+    apply: fn(&mut FullGraphState) -> bool,
+}
+
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize] = [
+    // 0 -> 1
+    Migration { description: "Added the missing node sizes", apply: add_node_sizes },
+    // 1 -> 2
+    Migration { description: "Added the sound event type to Start Sound Event nodes", apply: add_sound_event_type },
+    // 2 -> 3
+    Migration { description: "Added the entity handle input to EntFire nodes", apply: add_entfire_handle },
+    // 3 -> 4
+    Migration { description: "Updated Call Node nodes for the async fire mode", apply: update_call_nodes },
+    // 4 -> 5, only the layout of the file changed (see document.rs), the graph stays the same
+    Migration { description: "Switched to the document file layout", apply: |_| false },
+    // 5 -> 6
+    Migration { description: "Gave the nodes ids that are never reused", apply: add_node_uids },
+];
+
+// what was done to a graph when loading it
+pub struct Upgrade {
+    pub from: u32,
+    pub changes: Vec<&'static str>,
+}
+
+#[derive(Deserialize)]
+struct VersionHeader {
+    #[serde(default)]
+    format_version: u32,
+}
+
+fn check_version(version: u32) -> anyhow::Result<()> {
+    if version > FORMAT_VERSION {
+        anyhow::bail!(
+            "This graph was saved by a newer version of the editor (file format version {version}, this version only \
+            supports up to {FORMAT_VERSION}). Update the editor to open it."
+        );
+    }
+    Ok(())
+}
+
+// Checks the version of a saved graph before parsing the rest of it. Files from newer versions would most
+// likely fail to parse anyway, this gives a better error when they do.
+pub fn check_format_version(contents: &str) -> anyhow::Result<u32> {
+    // graphs that don't parse at all get their error from the full parse
+    let version = ron::from_str::<VersionHeader>(contents).map_or(0, |header| header.format_version);
+    check_version(version)?;
+    Ok(version)
+}
+
+impl FullGraphState {
+    // Brings the graph up to the current file format. Returns what was upgraded, if it was from an older version.
+    pub fn migrate(&mut self) -> anyhow::Result<Option<Upgrade>> {
+        let from = self.format_version;
+        check_version(from)?;
+        if from == FORMAT_VERSION {
+            return Ok(None);
+        }
+        let changes = MIGRATIONS[from as usize..].iter()
+            .filter(|migration| (migration.apply)(self))
+            .map(|migration| migration.description)
+            .collect();
+        self.format_version = FORMAT_VERSION;
+        Ok(Some(Upgrade { from, changes }))
+    }
+}
+
+fn nodes_where(full_state: &FullGraphState, filter: impl Fn(&Node<PulseNodeData>) -> bool) -> Vec<NodeId> {
+    full_state.state.graph.nodes.iter()
+        .filter(|(_, node)| filter(node))
+        .map(|(node_id, _)| node_id)
+        .collect()
+}
+
+// v0.1.1 introduces a SecondaryMap node_sizes in GraphEditorState
+fn add_node_sizes(full_state: &mut FullGraphState) -> bool {
+    let state = &full_state.state;
+    let missing = nodes_where(full_state, |_| true).into_iter()
+        .filter(|node_id| !state.node_sizes.contains_key(*node_id))
+        .collect::<Vec<_>>();
+    for node_id in missing.iter() {
+        full_state.state.node_sizes.insert(*node_id, egui::vec2(200.0, 200.0));
+    }
+    !missing.is_empty()
+}
+
+// v0.3.1 added the sound event source input
+fn add_sound_event_type(full_state: &mut FullGraphState) -> bool {
+    let node_ids = nodes_where(full_state, |node| {
+        node.user_data.template == PulseNodeTemplate::SoundEventStart && node.get_input("soundEventType").is_err()
+    });
+    let graph = &mut full_state.state.graph;
+    for node_id in node_ids.iter().copied() {
+        graph.add_input_param(
+            node_id,
+            "soundEventType".to_string(),
+            PulseDataType::GeneralEnum,
+            PulseGraphValueType::GeneralEnumChoice {
+                value: GeneralEnumChoice::SoundEventStartType(SoundEventStartType::default())
+            },
+            InputParamKind::ConstantOnly,
+            true,
+        );
+        // TODO: would be good to have some publically accessible simplifications for adding common inputs
+        graph.add_input_param(
+            node_id,
+            "ActionIn".to_string(),
+            PulseDataType::Action,
+            PulseGraphValueType::Action,
+            InputParamKind::ConnectionOnly,
+            true,
+        );
+        graph.add_output_param(node_id, "outAction".to_string(), PulseDataType::Action);
+        // all of this below is just to move the input action to the top, since the library doesn't really make that easy.
+        let node = &mut graph.nodes[node_id];
+        let mut input_id = None;
+        node.inputs.retain(|input| {
+            input_id = Some(input.1);
+            input.0 != "ActionIn"
+        });
+        if let Some(input_id) = input_id {
+            node.inputs.insert(0, ("ActionIn".to_string(), input_id));
+        }
+    }
+    !node_ids.is_empty()
+}
+
+// v0.3.1 added the entity handle input to EntFire
+fn add_entfire_handle(full_state: &mut FullGraphState) -> bool {
+    let node_ids = nodes_where(full_state, |node| {
+        node.user_data.template == PulseNodeTemplate::EntFire && node.get_input("entityHandle").is_err()
+    });
+    for node_id in node_ids.iter().copied() {
+        full_state.state.graph.add_input_param(
+            node_id,
+            "entityHandle".to_string(),
+            PulseDataType::EHandle,
+            PulseGraphValueType::EHandle,
+            InputParamKind::ConnectionOnly,
+            true,
+        );
+    }
+    !node_ids.is_empty()
+}
+
+// v0.3.1 added the Async fire mode to Call Node for functions, and calls of entity output listeners lost
+// their outAction
+fn update_call_nodes(full_state: &mut FullGraphState) -> bool {
+    let graph = &full_state.state.graph;
+    let call_target = |node_id: NodeId| {
+        let input_id = graph.nodes[node_id].get_input("nodeId").ok()?;
+        let target = graph.get_input(input_id).value().clone().try_node_id().ok()?;
+        graph.nodes.get(target).map(|target| target.user_data.template)
+    };
+    let call_nodes: Vec<(NodeId, Option<PulseNodeTemplate>)> = nodes_where(full_state, |node| {
+        node.user_data.template == PulseNodeTemplate::CallNode && node.get_input("Async").is_err()
+    }).into_iter().map(|node_id| (node_id, call_target(node_id))).collect();
+    let mut changed = false;
+    for (node_id, target) in call_nodes {
+        match target {
+            Some(PulseNodeTemplate::Function) => {
+                full_state.state.graph.add_input_param(
+                    node_id,
+                    "Async".to_string(),
+                    PulseDataType::Bool,
+                    PulseGraphValueType::Bool { value: false },
+                    InputParamKind::ConstantOnly,
+                    true,
                 );
-                let opt = Value::Option(Some(Box::from(Value::Map(sizes_map))));
-                node_sizes_map.insert(Value::String("value".into()), opt);
-                node_sizes_map.insert(Value::String("version".into()), version.clone());
+                changed = true;
+            }
+            Some(PulseNodeTemplate::ListenForEntityOutput) => {
+                if let Ok(output_id) = full_state.state.graph.nodes[node_id].get_output("outAction") {
+                    full_state.state.graph.remove_output_param(output_id);
+                    changed = true;
+                }
             }
-            state.insert(ron::Value::String("node_sizes".to_string()), node_sizes);
+            _ => {}
         }
     }
-    Ok(())
-}
\ No newline at end of file
+    changed
+}
+
+// v0.3.4 keeps a uid in every node. Documents had the slot of the node as its id and already have them set,
+// older graphs get them in slot order.
+fn add_node_uids(full_state: &mut FullGraphState) -> bool {
+    let FullGraphState { state, user_state, .. } = full_state;
+    let mut graphs: Vec<&mut PulseGraph> = std::iter::once(&mut state.graph)
+        .chain(user_state.macros.iter_mut().map(|def| &mut def.state.graph))
+        .collect();
+    let max_uid = graphs.iter()
+        .flat_map(|graph| graph.nodes.values().map(|node| node.user_data.uid))
+        .max()
+        .unwrap_or(0);
+    let mut next = user_state.next_node_uid.max(max_uid + 1);
+    let mut changed = false;
+    for graph in graphs.iter_mut() {
+        for node in graph.nodes.values_mut().filter(|node| node.user_data.uid == 0) {
+            node.user_data.uid = next;
+            next += 1;
+            changed = true;
+        }
+    }
+    user_state.next_node_uid = next;
+    changed
+}
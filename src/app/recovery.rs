@@ -0,0 +1,313 @@
+// Crash recovery. Every now and then the graphs with unsaved changes get written to a directory of their
+// own for this run of the editor, and once more if the editor panics. A clean exit removes the directory,
+// so any directories found on start are from runs that didn't end well, and are offered for recovery.
+// Other editors that are still open keep a lock on a file in their directory, those are left alone.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use eframe::egui::{self, Button, RichText};
+use super::history::History;
+use super::{FullGraphState, ModalWindowType, PulseGraphEditor, APP_NAME};
+
+// seconds between autosaves
+const AUTOSAVE_INTERVAL: f64 = 60.0;
+// locked by the run the directory belongs to for as long as it's going
+const LOCK_FILE: &str = "session.lock";
+
+#[derive(Default, Clone)]
+pub struct Recovery {
+    // directory of this run, created on the first autosave
+    session_dir: Option<PathBuf>,
+    // the lock on LOCK_FILE in session_dir, taken when the directory is created
+    lock: Option<Arc<fs::File>>,
+    last_autosave: f64,
+    // left over by earlier runs
+    pub sessions: Vec<RecoverySession>,
+}
+
+#[derive(Clone)]
+pub struct RecoverySession {
+    dir: PathBuf,
+    modified: SystemTime,
+    graphs: Vec<FullGraphState>,
+}
+
+// true if the run that made the directory is still going
+fn is_in_use(dir: &Path) -> bool {
+    let Ok(file) = fs::File::open(dir.join(LOCK_FILE)) else {
+        return false;
+    };
+    matches!(file.try_lock(), Err(fs::TryLockError::WouldBlock))
+}
+
+fn recovery_root() -> PathBuf {
+    #[cfg(feature = "persistence")]
+    if let Some(dir) = eframe::storage_dir(APP_NAME) {
+        return dir.join("recovery");
+    }
+    std::env::temp_dir().join(format!("{} recovery", APP_NAME))
+}
+
+impl Recovery {
+    fn session_dir(&mut self) -> &PathBuf {
+        self.session_dir.get_or_insert_with(|| {
+            let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            recovery_root().join(format!("{started}-{}", std::process::id()))
+        })
+    }
+
+    // Creates the directory of this run if it isn't there yet, and locks it.
+    fn create_session_dir(&mut self) -> anyhow::Result<PathBuf> {
+        let dir = self.session_dir().clone();
+        if self.lock.is_none() {
+            fs::create_dir_all(&dir)?;
+            let file = fs::File::create(dir.join(LOCK_FILE))?;
+            file.try_lock()?;
+            self.lock = Some(Arc::new(file));
+        }
+        Ok(dir)
+    }
+
+    // Unlocks and removes the directory of this run.
+    fn remove_dir(&mut self) -> std::io::Result<()> {
+        // windows can't remove files that are still open
+        self.lock = None;
+        match &self.session_dir {
+            Some(dir) if dir.exists() => fs::remove_dir_all(dir),
+            _ => Ok(()),
+        }
+    }
+
+    // Looks for the directories left over by runs that ended without cleaning up. Directories that don't
+    // have anything to recover are removed.
+    pub fn find_sessions(&mut self) {
+        let Ok(entries) = fs::read_dir(recovery_root()) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let dir = entry.path();
+            if !dir.is_dir() || Some(&dir) == self.session_dir.as_ref() || is_in_use(&dir) {
+                continue;
+            }
+            let mut files: Vec<PathBuf> = fs::read_dir(&dir).into_iter()
+                .flatten()
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+                .collect();
+            files.sort();
+            let mut modified = UNIX_EPOCH;
+            let mut graphs = vec![];
+            for file in files {
+                let contents = match fs::read_to_string(&file) {
+                    Ok(contents) => contents,
+                    Err(e) => {
+                        println!("[Recovery] Warning: Failed to read {}: {e}", file.display());
+                        continue;
+                    }
+                };
+                match ron::from_str::<FullGraphState>(&contents) {
+                    Ok(full_state) => graphs.push(full_state),
+                    Err(e) => println!("[Recovery] Warning: Failed to parse {}: {e}", file.display()),
+                }
+                if let Ok(file_modified) = fs::metadata(&file).and_then(|meta| meta.modified()) {
+                    modified = modified.max(file_modified);
+                }
+            }
+            if graphs.is_empty() {
+                // without a lock file, another run may have just created it
+                if dir.join(LOCK_FILE).exists() {
+                    let _ = fs::remove_dir_all(&dir);
+                }
+                continue;
+            }
+            self.sessions.push(RecoverySession { dir, modified, graphs });
+        }
+        // newest first
+        self.sessions.sort_by_key(|session| std::cmp::Reverse(session.modified));
+    }
+
+    // Removes the directory of this run, called on a clean exit.
+    pub fn remove_session_dir(&mut self) {
+        if let Err(e) = self.remove_dir() {
+            let dir = self.session_dir.as_deref().unwrap_or(Path::new(""));
+            println!("[Recovery] Warning: Failed to remove {}: {e}", dir.display());
+        }
+    }
+}
+
+fn graph_title(full_state: &FullGraphState) -> String {
+    full_state.user_state.save_file_path.as_ref()
+        .and_then(|path| path.file_name())
+        .and_then(|name| name.to_str())
+        .unwrap_or("<UNSAVED>")
+        .to_string()
+}
+
+fn time_ago(time: SystemTime) -> String {
+    let secs = SystemTime::now().duration_since(time).unwrap_or(Duration::ZERO).as_secs();
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} minutes ago", secs / 60),
+        3600..86400 => format!("{} hours ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+impl PulseGraphEditor {
+    pub(super) fn autosave(&mut self, ctx: &egui::Context, time: f64) {
+        let due_in = self.recovery.last_autosave + AUTOSAVE_INTERVAL - time;
+        if due_in > 0.0 {
+            // egui doesn't repaint on its own while idle
+            ctx.request_repaint_after(Duration::from_secs_f64(due_in));
+            return;
+        }
+        self.recovery.last_autosave = time;
+        if let Err(e) = self.write_recovery_files() {
+            println!("[Recovery] Warning: Autosave failed: {e:#}");
+        }
+    }
+
+    // Called while unwinding from a panic in the editor.
+    pub(super) fn emergency_save(&mut self) {
+        match self.write_recovery_files() {
+            Ok(dir) => println!("[Recovery] Unsaved graphs were written to {}", dir.display()),
+            Err(e) => println!("[Recovery] Emergency save failed: {e:#}"),
+        }
+    }
+
+    // Writes every graph with unsaved changes to the directory of this run, and removes the files of the
+    // ones that were saved or closed since.
+    fn write_recovery_files(&mut self) -> anyhow::Result<PathBuf> {
+        let dir = self.recovery.session_dir().clone();
+        let mut written = vec![];
+        for idx in 0..self.tabs.len() {
+            if !self.is_tab_dirty(idx) {
+                continue;
+            }
+            let full_state = if idx == self.active_tab { &self.full_state } else { &self.tabs[idx].full_state };
+            let contents = ron::ser::to_string_pretty(full_state, ron::ser::PrettyConfig::default())?;
+            self.recovery.create_session_dir()?;
+            let path = dir.join(format!("graph{idx}.ron"));
+            // write it next to the old one first, so a crash halfway through doesn't leave a broken file
+            let temp_path = path.with_extension("ron.tmp");
+            fs::write(&temp_path, contents)?;
+            fs::rename(&temp_path, &path)?;
+            written.push(path);
+        }
+        if written.is_empty() {
+            self.recovery.remove_dir()?;
+            return Ok(dir);
+        }
+        written.push(dir.join(LOCK_FILE));
+        for entry in fs::read_dir(&dir)?.flatten() {
+            if !written.contains(&entry.path()) {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(dir)
+    }
+
+    pub(super) fn open_recovery_prompt(&mut self) {
+        if !self.recovery.sessions.is_empty() {
+            self.current_modal_dialog.is_open = true;
+            self.current_modal_dialog.window_type = ModalWindowType::RecoverSessions;
+        }
+    }
+
+    fn restore_session(&mut self, idx: usize, ctx: &egui::Context) {
+        let session = self.recovery.sessions.remove(idx);
+        for mut full_state in session.graphs {
+            if let Err(e) = full_state.migrate() {
+                println!("[Recovery] Warning: {e:#}");
+                continue;
+            }
+            full_state.verify_compat();
+            for e in full_state.user_state.reload_libraries() {
+                println!("[Load] Warning: {e:#}");
+            }
+            full_state.user_state.bindings = self.user_state().bindings.clone();
+            if !self.is_active_tab_blank() {
+                self.new_tab(ctx);
+            }
+            // a new history isn't marked as saved, so the graph shows up as having unsaved changes
+            self.full_state = full_state;
+            self.history = History::default();
+        }
+        // the graphs are part of this run now, and get autosaved with it
+        self.recovery.last_autosave = f64::NEG_INFINITY;
+        if let Err(e) = fs::remove_dir_all(&session.dir) {
+            println!("[Recovery] Warning: Failed to remove {}: {e}", session.dir.display());
+        }
+        self.update_titlebar(ctx);
+    }
+
+    fn discard_session(&mut self, idx: usize) {
+        let session = self.recovery.sessions.remove(idx);
+        if let Err(e) = fs::remove_dir_all(&session.dir) {
+            println!("[Recovery] Warning: Failed to remove {}: {e}", session.dir.display());
+        }
+    }
+
+    // Contents of the recovery prompt. Returns true once there's nothing left to recover.
+    pub(super) fn recovery_ui(&mut self, ui: &mut egui::Ui) -> bool {
+        ui.set_width(450.0);
+        ui.label(RichText::new("Recover unsaved work").size(24.0));
+        ui.label(RichText::new("The editor didn't close properly last time. These graphs had unsaved changes:").size(16.0));
+        let mut restore = None;
+        let mut discard = None;
+        egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+            for (idx, session) in self.recovery.sessions.iter().enumerate() {
+                ui.separator();
+                ui.label(RichText::new(format!("Autosaved {}", time_ago(session.modified))).strong());
+                for full_state in session.graphs.iter() {
+                    ui.label(format!("  {}", graph_title(full_state)));
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Restore").on_hover_text("Open these graphs in new tabs").clicked() {
+                        restore = Some(idx);
+                    }
+                    if ui.button("Discard").on_hover_text("Delete the recovered graphs").clicked() {
+                        discard = Some(idx);
+                    }
+                });
+            }
+        });
+        if let Some(idx) = restore {
+            self.restore_session(idx, ui.ctx());
+        } else if let Some(idx) = discard {
+            self.discard_session(idx);
+        }
+        ui.separator();
+        egui::Sides::new().show(
+            ui,
+            |_ui| {},
+            |ui| {
+                ui.add_sized([120., 30.], Button::new(RichText::new("Later").size(18.0)))
+                    .on_hover_text("Ask again next time the editor starts")
+                    .clicked()
+            },
+        ).1 || self.recovery.sessions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locked_session_is_in_use() {
+        let dir = std::env::temp_dir().join(format!("{} recovery test {}", APP_NAME, std::process::id()));
+        let mut recovery = Recovery { session_dir: Some(dir.clone()), ..Default::default() };
+        assert!(!is_in_use(&dir));
+        recovery.create_session_dir().unwrap();
+        assert!(is_in_use(&dir));
+        // what a crashed run leaves behind
+        recovery.lock = None;
+        assert!(dir.join(LOCK_FILE).exists());
+        assert!(!is_in_use(&dir));
+        recovery.remove_session_dir();
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,203 @@
+// Variables and public outputs are referenced by name from the nodes (GetVar, SetVar, FireOutput...).
+// Keeps those references in sync when renaming, and finds them for the usages list and before deleting.
+use eframe::egui;
+use egui_node_graph2::*;
+use super::types::*;
+use super::{FullGraphState, ModalWindowType, PulseGraphEditor};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "persistence", derive(serde::Serialize, serde::Deserialize))]
+pub enum GraphSymbol {
+    Variable,
+    PublicOutput,
+}
+
+impl GraphSymbol {
+    pub fn ui_name(&self) -> &'static str {
+        match self {
+            GraphSymbol::Variable => "Variable",
+            GraphSymbol::PublicOutput => "Output",
+        }
+    }
+
+    fn references(&self, value: &PulseGraphValueType, name: &str) -> bool {
+        match (self, value) {
+            (GraphSymbol::Variable, PulseGraphValueType::InternalVariableName { value, .. })
+            | (GraphSymbol::PublicOutput, PulseGraphValueType::InternalOutputName { value, .. }) => value == name,
+            _ => false,
+        }
+    }
+}
+
+pub struct SymbolUsage {
+    // None if the node is in the graph shown in the editor, otherwise the name of the graph it's in
+    pub location: Option<String>,
+    pub node_id: NodeId,
+    pub title: String,
+}
+
+impl FullGraphState {
+    // Every graph of the file: the one in the editor first, then the macros (and the parents of the
+    // macro being edited, which are swapped into the macro slots), with a name to show for them.
+    fn graphs(&self) -> Vec<(Option<String>, &PulseGraph)> {
+        let stack = &self.user_state.macro_stack;
+        let mut graphs = vec![(None, &self.state.graph)];
+        for macro_def in self.user_state.macros.iter() {
+            let name = match stack.iter().position(|id| *id == macro_def.id) {
+                Some(0) => "Main graph".to_string(),
+                Some(pos) => self.user_state.find_macro(stack[pos - 1])
+                    .map_or_else(|| "Macro".to_string(), |parent| parent.name.clone()),
+                None => macro_def.name.clone(),
+            };
+            graphs.push((Some(name), &macro_def.state.graph));
+        }
+        graphs
+    }
+
+    fn graphs_mut(&mut self) -> Vec<&mut PulseGraph> {
+        let mut graphs = vec![&mut self.state.graph];
+        graphs.extend(self.user_state.macros.iter_mut().map(|macro_def| &mut macro_def.state.graph));
+        graphs
+    }
+
+    pub fn find_usages(&self, symbol: GraphSymbol, name: &str) -> Vec<SymbolUsage> {
+        if name.is_empty() {
+            return vec![];
+        }
+        let mut usages = vec![];
+        for (location, graph) in self.graphs() {
+            for (node_id, node) in graph.nodes.iter() {
+                let used = node.inputs.iter()
+                    .any(|(_, input_id)| symbol.references(&graph.get_input(*input_id).value, name));
+                if used {
+                    usages.push(SymbolUsage {
+                        location: location.clone(),
+                        node_id,
+                        title: node.label.clone(),
+                    });
+                }
+            }
+        }
+        usages
+    }
+
+    // Points all the nodes referencing `old_name` to `new_name`.
+    pub fn rename_symbol(&mut self, symbol: GraphSymbol, old_name: &str, new_name: &str) {
+        if old_name.is_empty() || old_name == new_name {
+            return;
+        }
+        for graph in self.graphs_mut() {
+            for input in graph.inputs.values_mut() {
+                if !symbol.references(&input.value, old_name) {
+                    continue;
+                }
+                match &mut input.value {
+                    PulseGraphValueType::InternalVariableName { prevvalue, value }
+                    | PulseGraphValueType::InternalOutputName { prevvalue, value } => {
+                        *prevvalue = std::mem::replace(value, new_name.to_string());
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+impl PulseGraphEditor {
+    // Name field of a variable or output. The old name is remembered while it's being edited, so the
+    // nodes can be updated once the editing is done. Returns (old, new) when it was renamed. Names that
+    // `is_taken` are refused, the nodes using them would end up pointing at the other one.
+    pub(super) fn symbol_name_edit(
+        ui: &mut egui::Ui,
+        name: &mut String,
+        hint: &str,
+        is_taken: impl Fn(&str) -> bool,
+    ) -> Option<(String, String)> {
+        let name_before = name.clone();
+        let resp = ui.add(egui::TextEdit::singleline(name)
+            .font(egui::TextStyle::Heading)
+            .hint_text(hint)
+        );
+        let refused_id = resp.id.with("refused");
+        if resp.gained_focus() {
+            ui.data_mut(|d| {
+                d.insert_temp(resp.id, name_before);
+                d.remove::<String>(refused_id);
+            });
+        }
+        let mut renamed = None;
+        if resp.lost_focus() {
+            let old_name: Option<String> = ui.data_mut(|d| d.remove_temp(resp.id));
+            if let Some(old_name) = old_name {
+                if old_name != *name && is_taken(name) {
+                    let refused = std::mem::replace(name, old_name);
+                    ui.data_mut(|d| d.insert_temp(refused_id, refused));
+                } else if old_name != *name {
+                    renamed = Some((old_name, name.clone()));
+                }
+            }
+        }
+        let warning = match ui.data(|d| d.get_temp::<String>(refused_id)) {
+            Some(refused) => Some(format!("'{refused}' is already used, the name wasn't changed")),
+            None if resp.has_focus() && is_taken(name) => Some("This name is already used".to_string()),
+            None => None,
+        };
+        if let Some(warning) = warning {
+            ui.colored_label(ui.visuals().warn_fg_color, warning);
+        }
+        renamed
+    }
+
+    // Asks first if the variable or output is still in use.
+    pub(super) fn request_delete_symbol(&mut self, symbol: GraphSymbol, idx: usize) {
+        let name = match symbol {
+            GraphSymbol::Variable => self.user_state().variables.get(idx).map(|var| &var.name),
+            GraphSymbol::PublicOutput => self.user_state().public_outputs.get(idx).map(|output| &output.name),
+        };
+        let Some(name) = name else {
+            return;
+        };
+        if self.full_state.find_usages(symbol, name).is_empty() {
+            self.delete_symbol(symbol, idx);
+        } else {
+            self.current_modal_dialog.is_open = true;
+            self.current_modal_dialog.window_type = ModalWindowType::ConfirmDeleteSymbol(symbol, idx);
+        }
+    }
+
+    pub(super) fn delete_symbol(&mut self, symbol: GraphSymbol, idx: usize) {
+        match symbol {
+            GraphSymbol::Variable if idx < self.user_state().variables.len() => {
+                self.user_state_mut().variables.remove(idx);
+            }
+            GraphSymbol::PublicOutput if idx < self.user_state().public_outputs.len() => {
+                self.user_state_mut().public_outputs.remove(idx);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Collapsible list of the nodes using a variable or output. Nodes in the graph shown in the editor can be
+// clicked to jump to them.
+pub(super) fn usages_ui(ui: &mut egui::Ui, id_salt: impl std::hash::Hash, usages: &[SymbolUsage], jump_to: &mut Option<NodeId>) {
+    egui::CollapsingHeader::new(format!("Usages ({})", usages.len()))
+        .id_salt(id_salt)
+        .show(ui, |ui| {
+            if usages.is_empty() {
+                ui.label("Not used by any node");
+            }
+            for usage in usages.iter() {
+                match &usage.location {
+                    None => {
+                        if ui.link(&usage.title).on_hover_text("Jump to node").clicked() {
+                            *jump_to = Some(usage.node_id);
+                        }
+                    }
+                    Some(location) => {
+                        ui.label(format!("{} (in {location})", usage.title));
+                    }
+                }
+            }
+        });
+}
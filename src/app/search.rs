@@ -0,0 +1,147 @@
+// Find in graph. Looks through node titles, constant values, variable and output names and the
+// names of the bindings used by the nodes.
+use eframe::egui;
+use egui_node_graph2::*;
+use super::types::*;
+use super::{FullGraphState, PulseGraphEditor};
+
+pub struct SearchHit {
+    pub node_id: NodeId,
+    // the node title and the texts that matched
+    pub title: String,
+    pub matches: Vec<String>,
+}
+
+#[derive(Default, Clone)]
+pub struct SearchPanel {
+    pub query: String,
+    // index into the current results, of the last hit we jumped to
+    pub current: Option<usize>,
+    pub request_focus: bool,
+}
+
+impl FullGraphState {
+    // Case insensitive search, returns the nodes in drawing order.
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return vec![];
+        }
+        self.state.node_order.iter()
+            .filter_map(|node_id| {
+                let node = self.state.graph.nodes.get(*node_id)?;
+                let matches: Vec<String> = self.search_entries(*node_id, node).into_iter()
+                    .filter(|entry| entry.to_lowercase().contains(&query))
+                    .collect();
+                (!matches.is_empty()).then(|| SearchHit {
+                    node_id: *node_id,
+                    title: node.label.clone(),
+                    matches,
+                })
+            })
+            .collect()
+    }
+
+    // everything about a node that can be searched for
+    fn search_entries(&self, node_id: NodeId, node: &Node<PulseNodeData>) -> Vec<String> {
+        let bindings = &self.user_state.bindings;
+        let mut entries = vec![format!("Title: {}", node.label)];
+        if let Some(name) = self.user_state.exposed_nodes.get(node_id) {
+            entries.push(format!("Name: {name}"));
+        }
+        if let PulseNodeTemplate::LibraryBindingAssigned { binding } = node.user_data.template {
+            if let Some(binding) = bindings.find_function_by_id(binding) {
+                entries.push(format!("Function: {}", binding.libname));
+            }
+        }
+        for (name, input_id) in node.inputs.iter() {
+            let entry = match &self.state.graph.get_input(*input_id).value {
+                PulseGraphValueType::String { value }
+                | PulseGraphValueType::EntityName { value }
+                | PulseGraphValueType::SoundEventName { value }
+                | PulseGraphValueType::Resource { value, .. } => format!("{name}: {value}"),
+                PulseGraphValueType::InternalVariableName { value, .. } => format!("Variable: {value}"),
+                PulseGraphValueType::InternalOutputName { value, .. } => format!("Output: {value}"),
+                PulseGraphValueType::LibraryBindingChoice { value } => match bindings.find_function_by_id(*value) {
+                    Some(binding) => format!("Function: {}", binding.libname),
+                    None => continue,
+                },
+                PulseGraphValueType::EventBindingChoice { value } => match bindings.find_event_by_id(*value) {
+                    Some(binding) => format!("Event: {}", binding.libname),
+                    None => continue,
+                },
+                PulseGraphValueType::HookBindingChoice { value } => match bindings.find_hook_by_id(*value) {
+                    Some(binding) => format!("Hook: {}", binding.libname),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            entries.push(entry);
+        }
+        entries
+    }
+}
+
+impl PulseGraphEditor {
+    pub(super) fn open_search(&mut self) {
+        let search = self.search.get_or_insert_with(SearchPanel::default);
+        search.request_focus = true;
+    }
+
+    pub(super) fn jump_to_node(&mut self, node_id: NodeId) {
+        self.state_mut().selected_nodes = vec![node_id];
+        self.state_mut().center_on_node(node_id);
+    }
+
+    pub(super) fn search_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        let mut close = false;
+        let mut jump_to = None;
+        let results = self.full_state.search(&search.query);
+        ui.horizontal(|ui| {
+            ui.heading("Find in graph");
+            if ui.button("X").on_hover_text("Close (Esc)").clicked() {
+                close = true;
+            }
+        });
+        let resp = ui.add(egui::TextEdit::singleline(&mut search.query).hint_text("Search..."));
+        if std::mem::take(&mut search.request_focus) {
+            resp.request_focus();
+        }
+        if resp.changed() {
+            search.current = None;
+        }
+        // Enter goes to the next hit
+        if resp.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) && !results.is_empty() {
+            let next = search.current.map_or(0, |current| (current + 1) % results.len());
+            search.current = Some(next);
+            jump_to = Some(results[next].node_id);
+            resp.request_focus();
+        }
+        if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+            close = true;
+        }
+        ui.label(format!("{} results", results.len()));
+        ui.separator();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (idx, hit) in results.iter().enumerate() {
+                let resp = ui.selectable_label(search.current == Some(idx), egui::RichText::new(&hit.title).strong());
+                for text in hit.matches.iter().filter(|text| !text.starts_with("Title: ")) {
+                    ui.label(egui::RichText::new(text).small());
+                }
+                if resp.clicked() {
+                    search.current = Some(idx);
+                    jump_to = Some(hit.node_id);
+                }
+            }
+        });
+        if close {
+            self.search = None;
+        }
+        if let Some(node_id) = jump_to {
+            self.jump_to_node(node_id);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+// Multiple open graphs. The active one lives in PulseGraphEditor::full_state/history like before,
+// its entry in `tabs` is only a placeholder until we switch away from it.
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use super::{FullGraphState, PulseGraphEditor, ModalWindowType};
+use super::history::History;
+
+#[derive(Default, Clone)]
+pub struct GraphTab {
+    pub full_state: FullGraphState,
+    pub history: History,
+}
+
+// what gets remembered in the eframe storage between sessions
+#[derive(Default, Serialize, Deserialize)]
+pub struct PersistedTabs {
+    pub tabs: Vec<FullGraphState>,
+    pub active_tab: usize,
+    // which tabs had unsaved changes, missing when written by older versions
+    #[serde(default)]
+    pub dirty: Vec<bool>,
+}
+
+impl PulseGraphEditor {
+    pub(super) fn switch_tab(&mut self, idx: usize, ctx: &egui::Context) {
+        if idx == self.active_tab || idx >= self.tabs.len() {
+            return;
+        }
+        self.stash_active_tab();
+        let tab = std::mem::take(&mut self.tabs[idx]);
+        self.full_state = tab.full_state;
+        self.history = tab.history;
+        self.active_tab = idx;
+        self.update_titlebar(ctx);
+    }
+
+    // moves the active graph back into its tab entry
+    fn stash_active_tab(&mut self) {
+        let bindings = self.user_state().bindings.clone();
+        let mut tab = GraphTab {
+            full_state: std::mem::take(&mut self.full_state),
+            history: std::mem::take(&mut self.history),
+        };
+        // bindings aren't part of the saved state, keep them around for the next active tab
+        self.user_state_mut().bindings = bindings;
+        std::mem::swap(&mut self.tabs[self.active_tab], &mut tab);
+    }
+
+    pub(super) fn new_tab(&mut self, ctx: &egui::Context) {
+        if self.tabs.is_empty() {
+            // placeholder for the active graph
+            self.tabs.push(GraphTab::default());
+        }
+        let mut full_state = FullGraphState::default();
+        full_state.user_state.bindings = self.user_state().bindings.clone();
+        self.tabs.push(GraphTab {
+            full_state,
+            history: History::saved(),
+        });
+        self.switch_tab(self.tabs.len() - 1, ctx);
+    }
+
+    pub(super) fn close_tab(&mut self, idx: usize, ctx: &egui::Context) {
+        if idx >= self.tabs.len() {
+            return;
+        }
+        if self.tabs.len() == 1 {
+            // always keep one graph open
+            self.new_tab(ctx);
+        }
+        if idx == self.active_tab {
+            let next = if idx + 1 < self.tabs.len() { idx + 1 } else { idx - 1 };
+            self.switch_tab(next, ctx);
+        }
+        self.tabs.remove(idx);
+        if self.active_tab > idx {
+            self.active_tab -= 1;
+        }
+    }
+
+    pub(super) fn is_tab_dirty(&self, idx: usize) -> bool {
+        // diffs can't be saved, closing them doesn't lose anything
+        if self.tab_state(idx).user_state.diff.is_some() {
+            false
+        } else if idx == self.active_tab {
+            self.history.is_dirty()
+        } else {
+            self.tabs[idx].history.is_dirty()
+        }
+    }
+
+    pub(super) fn mark_saved(&mut self) {
+        self.history.mark_saved(&self.full_state);
+    }
+
+    fn tab_state(&self, idx: usize) -> &FullGraphState {
+        if idx == self.active_tab {
+            &self.full_state
+        } else {
+            &self.tabs[idx].full_state
+        }
+    }
+
+    pub(super) fn tab_title(&self, idx: usize) -> String {
+        let full_state = self.tab_state(idx);
+        if let Some(diff) = &full_state.user_state.diff {
+            return diff.title.clone();
+        }
+        full_state.user_state.save_file_path.as_ref()
+            .and_then(|path| path.file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or("<UNSAVED>")
+            .to_string()
+    }
+
+    // true if the active tab is a new graph without any changes, which can be replaced when opening a file
+    pub(super) fn is_active_tab_blank(&self) -> bool {
+        self.user_state().save_file_path.is_none() && self.state().graph.nodes.is_empty()
+    }
+
+    pub(super) fn tabs_ui(&mut self, ui: &mut egui::Ui) {
+        let mut clicked_tab = None;
+        let mut tab_to_close = None;
+        ui.horizontal_wrapped(|ui| {
+            for idx in 0..self.tabs.len() {
+                let dirty = self.is_tab_dirty(idx);
+                let title = if dirty {
+                    format!("{}*", self.tab_title(idx))
+                } else {
+                    self.tab_title(idx)
+                };
+                if ui.selectable_label(idx == self.active_tab, title).clicked() {
+                    clicked_tab = Some(idx);
+                }
+                if ui.small_button("x").on_hover_text("Close").clicked() {
+                    tab_to_close = Some((idx, dirty));
+                }
+                ui.separator();
+            }
+            if ui.button("+").on_hover_text("New graph").clicked() {
+                clicked_tab = Some(usize::MAX);
+            }
+        });
+        match clicked_tab {
+            Some(usize::MAX) => self.new_tab(ui.ctx()),
+            Some(idx) => self.switch_tab(idx, ui.ctx()),
+            None => {}
+        }
+        if let Some((idx, dirty)) = tab_to_close {
+            if dirty {
+                self.current_modal_dialog.is_open = true;
+                self.current_modal_dialog.window_type = ModalWindowType::ConfirmCloseTab(idx);
+            } else {
+                self.close_tab(idx, ui.ctx());
+            }
+        }
+    }
+
+    pub(super) fn persisted_tabs(&self) -> PersistedTabs {
+        // diffs are made from files that may be gone by the next start, so they aren't kept
+        let kept: Vec<usize> = (0..self.tabs.len())
+            .filter(|idx| self.tab_state(*idx).user_state.diff.is_none())
+            .collect();
+        PersistedTabs {
+            tabs: kept.iter().map(|idx| self.tab_state(*idx).clone()).collect(),
+            active_tab: kept.iter().position(|idx| *idx >= self.active_tab).unwrap_or(0),
+            dirty: kept.iter().map(|idx| self.is_tab_dirty(*idx)).collect(),
+        }
+    }
+}
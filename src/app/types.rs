@@ -1,11 +1,16 @@
 use std::marker::PhantomData;
 use std::{path::PathBuf, borrow::Cow};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
 use serde::{Deserialize, Serialize};
 use slotmap::SecondaryMap;
 use egui_node_graph2::*;
 use crate::typing::*;
 use crate::pulsetypes::*;
 use crate::bindings::{GraphBindings, FunctionBinding, EventBinding};
+use super::validation::NodeIssues;
+use super::diff::DiffView;
+pub use super::document::NodeUid;
 
 /// The NodeData holds a custom data struct inside each node. It's useful to
 /// store additional information that doesn't live in parameters. For this
@@ -25,6 +30,9 @@ pub struct PulseNodeData {
     pub custom_output_type: Option<PulseValueType>,
     #[serde(default)]
     pub added_inputs: Vec<InputId>,
+    // stable id of the node, see document.rs. Copies of a node get a new one.
+    #[serde(default)]
+    pub uid: NodeUid,
 }
 
 /// `DataType`s are what defines the possible range of connections when
@@ -215,6 +223,19 @@ pub enum PulseNodeTemplate {
     RandomInt,
     RandomFloat,
     EntOutputHandler,
+    CombineFlags,
+    TestFlags,
+    // passes its input straight through, only used to tidy up the connections
+    Reroute,
+    Macro { id: MacroId },
+    // port nodes that only exist inside of a macro graph
+    MacroInputs,
+    MacroOutputs,
+    // definitions coming from imported library files
+    LibraryMacro { library: LibraryId, id: MacroId },
+    // uid of the Function node in the library graph, so it still finds the function if the library changes.
+    // The function name is stored in the node too, to report it when the function is gone.
+    LibraryFunction { library: LibraryId, function: NodeUid },
 }
 
 /// The response type is used to encode side-effects produced when drawing a
@@ -236,6 +257,76 @@ pub enum PulseGraphResponse {
     ChangeFunctionBinding(NodeId, FunctionBinding),
     ChangeRemoteNodeId(NodeId, NodeId),
     UpdatePolymorphicTypes(NodeId),
+    OpenMacro(MacroId),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MacroId(pub u32);
+
+// Input or output port of a macro node, mirrored by the MacroInputs/MacroOutputs nodes inside of the macro.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct MacroPort {
+    pub name: String,
+    pub typ: PulseDataType,
+    pub value: PulseGraphValueType,
+    pub kind: InputParamKind,
+}
+
+// A collapsed group of nodes. The graph is inlined by the compiler wherever the macro is used.
+#[derive(Clone)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct MacroDefinition {
+    pub id: MacroId,
+    pub name: String,
+    pub inputs: Vec<MacroPort>,
+    pub outputs: Vec<MacroPort>,
+    // while the macro is being edited this holds the state of the parent graph instead (they get swapped)
+    pub state: MyEditorState,
+    pub exposed_nodes: SecondaryMap<NodeId, String>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct LibraryId(pub u32);
+
+// Identifies an open graph for the lifetime of the process, NodeIds are only meaningful inside of it.
+// The process id is part of it because the clipboard can be shared between editor instances.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GraphInstanceId {
+    process: u32,
+    counter: u64,
+}
+
+impl GraphInstanceId {
+    pub fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self {
+            process: std::process::id(),
+            counter: NEXT.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+// Another graph file whose macros and functions can be used in this graph.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct LibraryImport {
+    pub id: LibraryId,
+    pub name: String,
+    // relative to the directory of the graph file
+    pub path: PathBuf,
+}
+
+// Contents of an imported library, read from disk when loading the graph and before compiling.
+#[derive(Clone)]
+pub struct LoadedLibrary {
+    pub id: LibraryId,
+    pub graph: PulseGraph,
+    pub macros: Vec<MacroDefinition>,
+    // named Function nodes of the library graph
+    pub functions: Vec<(String, NodeId)>,
+    pub variables: Vec<PulseVariable>,
+    pub public_outputs: Vec<OutputDefinition>,
 }
 
 /// The graph 'global' state. This state struct is passed around to the node and
@@ -257,6 +348,30 @@ pub struct PulseGraphState {
     pub graph_domain: String,
     #[cfg_attr(feature = "persistence", serde(default))]
     pub graph_subtype: String,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub macros: Vec<MacroDefinition>,
+    // macros that are currently being edited, last one is the one shown in the editor
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub macro_stack: Vec<MacroId>,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub libraries: Vec<LibraryImport>,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub loaded_libraries: Vec<LoadedLibrary>,
+    // problems found by the last validation pass over the graph in the editor
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub node_issues: NodeIssues,
+    // nodes that never run, see validation::unreachable_nodes
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub unreachable_nodes: HashSet<NodeId>,
+    // set on tabs that show the changes between two versions of a graph, see diff.rs
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub diff: Option<Box<DiffView>>,
+    // a loaded graph gets a new id, its NodeIds could've changed since it was saved
+    #[cfg_attr(feature = "persistence", serde(skip, default = "GraphInstanceId::new"))]
+    pub instance_id: GraphInstanceId,
+    // uid of the next new node, shared by the macro graphs. Uids are never reused.
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub next_node_uid: NodeUid,
 }
 
 impl Default for PulseGraphState {
@@ -270,33 +385,31 @@ impl Default for PulseGraphState {
             bindings: GraphBindings::default(),
             graph_domain: "ServerEntity".to_string(),
             graph_subtype: "PVAL_EHANDLE:point_pulse".to_string(),
+            macros: Vec::new(),
+            macro_stack: Vec::new(),
+            libraries: Vec::new(),
+            loaded_libraries: Vec::new(),
+            node_issues: NodeIssues::new(),
+            unreachable_nodes: HashSet::new(),
+            diff: None,
+            instance_id: GraphInstanceId::new(),
+            next_node_uid: 1,
         }
     }
 }
 
 pub struct AllMyNodeTemplates {
     pub game_function_count: usize,
+    pub macro_ids: Vec<MacroId>,
+    pub library_templates: Vec<PulseNodeTemplate>,
 }
 
 #[cfg(feature = "nongame_asset_build")]
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct EditorConfig {
-    pub python_interpreter: String,
-    pub assetassembler_path: PathBuf,
     pub red2_template_path: PathBuf,
 }
 
-#[derive(Default, Clone, Copy, PartialEq, Eq)]
-#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
-#[cfg_attr(feature = "persistence", serde(tag = "version"))]
-pub enum FileVersion {
-    #[default]
-    #[cfg_attr(feature = "persistence", serde(rename = "v1"))]
-    V1,
-    #[cfg_attr(feature = "persistence", serde(rename = "v2"))]
-    V2,
-}
-
 pub type PulseGraph = Graph<PulseNodeData, PulseDataType, PulseGraphValueType>;
 pub type MyEditorState = GraphEditorState<
     PulseNodeData,
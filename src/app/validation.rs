@@ -0,0 +1,248 @@
+// Quick checks for problems that would otherwise only show up when compiling. Runs on the graph shown in
+// the editor, the results are drawn as badges on the nodes and listed in the problems panel.
+use std::collections::{HashMap, HashSet};
+use eframe::egui::{self, Color32};
+use slotmap::SecondaryMap;
+use egui_node_graph2::*;
+use crate::compiler::typecheck::typecheck_graph;
+use super::types::*;
+use super::{FullGraphState, PulseGraphEditor};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum IssueSeverity {
+    Warning,
+    Error,
+}
+
+impl IssueSeverity {
+    pub fn color(&self) -> Color32 {
+        match self {
+            IssueSeverity::Warning => Color32::from_rgb(240, 190, 40),
+            IssueSeverity::Error => Color32::from_rgb(230, 60, 60),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct NodeIssue {
+    pub severity: IssueSeverity,
+    pub message: String,
+}
+
+impl NodeIssue {
+    fn error(message: impl Into<String>) -> Self {
+        Self { severity: IssueSeverity::Error, message: message.into() }
+    }
+    fn warning(message: impl Into<String>) -> Self {
+        Self { severity: IssueSeverity::Warning, message: message.into() }
+    }
+}
+
+pub type NodeIssues = SecondaryMap<NodeId, Vec<NodeIssue>>;
+
+// worst problem of a node, if it has any
+pub fn node_severity(issues: &NodeIssues, node_id: NodeId) -> Option<IssueSeverity> {
+    issues.get(node_id)?.iter().map(|issue| issue.severity).max()
+}
+
+impl FullGraphState {
+    // Runs when the history recorded a change, not every frame.
+    pub fn validate(&mut self) {
+        self.user_state.node_issues = validate_graph(&self.state.graph, &self.user_state);
+        self.user_state.unreachable_nodes = unreachable_nodes(&self.state.graph);
+    }
+}
+
+pub fn validate_graph(graph: &PulseGraph, user_state: &PulseGraphState) -> NodeIssues {
+    let mut issues = NodeIssues::new();
+    for (node_id, node) in graph.nodes.iter() {
+        let mut node_issues = vec![];
+        if matches!(node.user_data.template, PulseNodeTemplate::MacroOutputs | PulseNodeTemplate::Comment) {
+            continue;
+        }
+        if let PulseNodeTemplate::LibraryFunction { library, function } = node.user_data.template {
+            match user_state.find_library(library) {
+                None => node_issues.push(NodeIssue::error("The library of this function isn't loaded")),
+                Some(loaded) if loaded.find_function(function).is_none() => {
+                    node_issues.push(NodeIssue::error("The function was removed from the library"));
+                }
+                _ => {}
+            }
+        }
+        for (name, input_id) in node.inputs.iter() {
+            let input = graph.get_input(*input_id);
+            if !graph.connections(*input_id).is_empty() {
+                continue;
+            }
+            match &input.value {
+                PulseGraphValueType::Action => {
+                    node_issues.push(NodeIssue::warning(format!(
+                        "'{name}' is not connected, this node will never run"
+                    )));
+                }
+                PulseGraphValueType::EntityName { value } if value.trim().is_empty() => {
+                    node_issues.push(NodeIssue::warning(format!("'{name}' has no entity name set")));
+                }
+                PulseGraphValueType::InternalVariableName { value, .. } => {
+                    if value.is_empty() {
+                        node_issues.push(NodeIssue::error("No variable chosen"));
+                    } else if !user_state.variables.iter().any(|var| var.name == *value) {
+                        node_issues.push(NodeIssue::error(format!("Variable '{value}' doesn't exist")));
+                    }
+                }
+                PulseGraphValueType::InternalOutputName { value, .. } => {
+                    if value.is_empty() {
+                        node_issues.push(NodeIssue::error("No output chosen"));
+                    } else if !user_state.public_outputs.iter().any(|output| output.name == *value) {
+                        node_issues.push(NodeIssue::error(format!("Output '{value}' doesn't exist")));
+                    }
+                }
+                PulseGraphValueType::NodeChoice { node } => match node {
+                    None => node_issues.push(NodeIssue::error("No node chosen")),
+                    Some(target) if !graph.nodes.contains_key(*target) => {
+                        node_issues.push(NodeIssue::error("The chosen node was deleted"));
+                    }
+                    Some(target) if !user_state.exposed_nodes.contains_key(*target) => {
+                        node_issues.push(NodeIssue::error("The chosen node no longer has a name"));
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        if !node_issues.is_empty() {
+            issues.insert(node_id, node_issues);
+        }
+    }
+    for error in typecheck_graph(graph, user_state) {
+        let issue = NodeIssue::error(format!("'{}' {}", error.port, error.message));
+        match issues.get_mut(error.node_id) {
+            Some(node_issues) => node_issues.push(issue),
+            None => {
+                issues.insert(error.node_id, vec![issue]);
+            }
+        }
+    }
+    issues
+}
+
+// Nodes the compiler never gets to: not in the action flow of any inflow node (or a node called with
+// CallNode), and not providing a value to something that is.
+pub fn unreachable_nodes(graph: &PulseGraph) -> HashSet<NodeId> {
+    let is_root = |template: PulseNodeTemplate| matches!(
+        template,
+        PulseNodeTemplate::CellPublicMethod | PulseNodeTemplate::EventHandler
+        | PulseNodeTemplate::GraphHook | PulseNodeTemplate::EntOutputHandler
+        // entry point of a macro graph
+        | PulseNodeTemplate::MacroInputs
+    );
+    let mut stack: Vec<NodeId> = graph.nodes.iter()
+        .filter(|(_, node)| is_root(node.user_data.template))
+        .map(|(node_id, _)| node_id)
+        .collect();
+    stack.extend(graph.inputs.values().filter_map(|input| match input.value {
+        PulseGraphValueType::NodeChoice { node: Some(target) } if graph.nodes.contains_key(target) => Some(target),
+        _ => None,
+    }));
+
+    // follow the action flow
+    let mut action_targets: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+    for (input_id, output_ids) in graph.iter_connection_groups() {
+        let input = graph.get_input(input_id);
+        for output_id in output_ids {
+            let output = graph.get_output(output_id);
+            if output.typ == PulseDataType::Action || input.typ == PulseDataType::Action {
+                action_targets.entry(output.node).or_default().push(input.node);
+            }
+        }
+    }
+    let mut reached: HashSet<NodeId> = HashSet::new();
+    while let Some(node_id) = stack.pop() {
+        if reached.insert(node_id) {
+            stack.extend(action_targets.get(&node_id).into_iter().flatten());
+        }
+    }
+    // then everything that provides values to the nodes that run
+    let mut stack: Vec<NodeId> = reached.iter().copied().collect();
+    while let Some(node_id) = stack.pop() {
+        for (_, input_id) in graph[node_id].inputs.iter() {
+            for output_id in graph.connections(*input_id) {
+                let source = graph.get_output(output_id).node;
+                if reached.insert(source) {
+                    stack.push(source);
+                }
+            }
+        }
+    }
+
+    graph.nodes.iter()
+        .filter(|(node_id, node)| !reached.contains(node_id) && node.user_data.template != PulseNodeTemplate::Comment)
+        .map(|(node_id, _)| node_id)
+        .collect()
+}
+
+impl PulseGraphEditor {
+    pub(super) fn problem_count(&self) -> usize {
+        self.user_state().node_issues.values().map(|issues| issues.len()).sum::<usize>()
+            + self.user_state().unreachable_nodes.len()
+    }
+
+    pub(super) fn select_unreachable_nodes(&mut self) {
+        let mut unreachable: Vec<NodeId> = self.user_state().unreachable_nodes.iter().copied().collect();
+        // keep the drawing order, so the selection doesn't jump around between frames
+        unreachable.sort_by_key(|node_id| self.state().node_order.iter().position(|id| id == node_id));
+        self.state_mut().selected_nodes = unreachable;
+    }
+
+    pub(super) fn problems_ui(&mut self, ui: &mut egui::Ui) {
+        let mut jump_to = None;
+        let mut select_unreachable = false;
+        ui.horizontal(|ui| {
+            ui.heading("Problems");
+            if ui.button("X").on_hover_text("Close").clicked() {
+                self.show_problems = false;
+            }
+        });
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            let graph = &self.state().graph;
+            let mut issues: Vec<(NodeId, &NodeIssue)> = self.user_state().node_issues.iter()
+                .flat_map(|(node_id, issues)| issues.iter().map(move |issue| (node_id, issue)))
+                .collect();
+            // errors first
+            issues.sort_by_key(|(_, issue)| std::cmp::Reverse(issue.severity));
+            for (node_id, issue) in issues {
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new("⚠").color(issue.severity.color()));
+                    if ui.link(&graph[node_id].label).clicked() {
+                        jump_to = Some(node_id);
+                    }
+                    ui.label(&issue.message);
+                });
+            }
+            let unreachable = &self.user_state().unreachable_nodes;
+            if !unreachable.is_empty() {
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} unreachable nodes, they are never run and get left out when compiling", unreachable.len()));
+                    if ui.button("Select all unreachable").clicked() {
+                        select_unreachable = true;
+                    }
+                });
+                for node_id in self.state().node_order.iter().filter(|id| unreachable.contains(id)) {
+                    if ui.link(&graph[*node_id].label).clicked() {
+                        jump_to = Some(*node_id);
+                    }
+                }
+            }
+            if self.problem_count() == 0 {
+                ui.label("No problems found");
+            }
+        });
+        if select_unreachable {
+            self.select_unreachable_nodes();
+        }
+        if let Some(node_id) = jump_to {
+            self.jump_to_node(node_id);
+        }
+    }
+}
@@ -103,7 +103,7 @@ impl GraphBindings {
     }
 }
 
-// stub used for Undo functionality, there's no need to clone these.
+// stub, copies of the graph state (tabs, last saved state) don't need their own bindings.
 impl Clone for GraphBindings {
     fn clone(&self) -> Self {
         GraphBindings {
@@ -0,0 +1,243 @@
+// Command line mode, for using the editor's checks without the UI.
+//   pulseedit check <graph.ron> [--bindings <bindings.json>]
+//   pulseedit interface <graph.ron> [--bindings <bindings.json>]
+//   pulseedit fgd <graph.ron> [--bindings <bindings.json>] [--add <file.fgd>]
+//   pulseedit merge <base> <local> <remote> <merged> [--no-ui]
+//   pulseedit diff <old> [<new>] [--rev <rev>] [--ui]
+use std::path::{Path, PathBuf};
+use crate::app::FullGraphState;
+use crate::app::fgd::{add_to_fgd_file, merge_fgd};
+use crate::app::merge::MergeSession;
+use crate::app::diff::{git_version, DiffSession};
+use crate::bindings::load_bindings;
+use crate::compiler::check_graph_types;
+
+const USAGE: &str = "Usage: pulseedit check <graph.ron> [--bindings <bindings.json>]
+       pulseedit interface <graph.ron> [--bindings <bindings.json>]
+       pulseedit fgd <graph.ron> [--bindings <bindings.json>] [--add <file.fgd>]
+       pulseedit merge <base> <local> <remote> <merged> [--no-ui]
+       pulseedit diff <old> [<new>] [--rev <rev>] [--ui]";
+
+pub enum CliResult {
+    Exit(i32),
+    // the merge has conflicts, the editor should start with them
+    ResolveMerge(Box<MergeSession>),
+    // the editor should start with the changes between two graphs shown
+    ShowDiff(Box<DiffSession>),
+}
+
+// Runs the command given on the command line. Returns None if the editor should start normally instead.
+pub fn run(args: &[String]) -> Option<CliResult> {
+    let (command, rest) = args.split_first()?;
+    match command.as_str() {
+        "check" => Some(CliResult::Exit(match parse_check_args(rest) {
+            Ok((graph_path, bindings_path)) => check(&graph_path, &bindings_path),
+            Err(e) => {
+                eprintln!("{e}\n{USAGE}");
+                2
+            }
+        })),
+        "interface" => Some(CliResult::Exit(match parse_check_args(rest) {
+            Ok((graph_path, bindings_path)) => interface(&graph_path, &bindings_path),
+            Err(e) => {
+                eprintln!("{e}\n{USAGE}");
+                2
+            }
+        })),
+        "fgd" => Some(CliResult::Exit(match fgd(rest) {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("{e:#}\n{USAGE}");
+                2
+            }
+        })),
+        "merge" => Some(merge(rest)),
+        "diff" => Some(match diff(rest) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("{e:#}\n{USAGE}");
+                CliResult::Exit(2)
+            }
+        }),
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Some(CliResult::Exit(0))
+        }
+        _ => None,
+    }
+}
+
+// Meant to be used as a git mergetool, exits with 0 only if the merged file was written.
+fn merge(args: &[String]) -> CliResult {
+    let no_ui = args.iter().any(|arg| arg == "--no-ui");
+    let paths: Vec<&String> = args.iter().filter(|arg| *arg != "--no-ui").collect();
+    let [base, local, remote, merged] = paths[..] else {
+        eprintln!("merge needs the base, local, remote and merged files\n{USAGE}");
+        return CliResult::Exit(2);
+    };
+    let session = match MergeSession::from_files(base.as_ref(), local.as_ref(), remote.as_ref(), merged.into()) {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("Merge failed: {e:#}");
+            return CliResult::Exit(2);
+        }
+    };
+    if session.conflicts().is_empty() {
+        return CliResult::Exit(match session.write_merged() {
+            Ok(()) => {
+                println!("{}: merged without conflicts", merged);
+                0
+            }
+            Err(e) => {
+                eprintln!("Failed to write {merged}: {e:#}");
+                2
+            }
+        });
+    }
+    for conflict in session.conflicts() {
+        println!("{}: conflict in {}: {}", merged, conflict.name, conflict.description);
+    }
+    if no_ui {
+        return CliResult::Exit(1);
+    }
+    CliResult::ResolveMerge(Box::new(session))
+}
+
+// Prints the changes from one graph to another, exits with 1 if there are any. With only one file, it's
+// compared with how it is in git (HEAD unless --rev is given).
+fn diff(args: &[String]) -> anyhow::Result<CliResult> {
+    let mut files = vec![];
+    let mut rev = None;
+    let mut ui = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rev" => rev = Some(args.next().ok_or_else(|| anyhow::anyhow!("--rev needs a revision"))?.clone()),
+            "--ui" => ui = true,
+            _ if files.len() < 2 => files.push(PathBuf::from(arg)),
+            _ => anyhow::bail!("Unexpected argument '{arg}'"),
+        }
+    }
+    let read = |path: &Path| std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {e}", path.display()));
+    let file_name = |path: &Path| path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let (old, new, title, graph_path) = match &files[..] {
+        [path] => {
+            let rev = rev.unwrap_or_else(|| "HEAD".to_string());
+            (git_version(path, &rev)?, read(path)?, format!("{} ({rev} ↔ current)", file_name(path)), path)
+        }
+        [old, new] if rev.is_none() => (read(old)?, read(new)?, format!("{} ↔ {}", file_name(old), file_name(new)), new),
+        [_, _] => anyhow::bail!("--rev only works with a single file"),
+        _ => anyhow::bail!("No graph file given"),
+    };
+    let session = DiffSession::new(&old, &new, title, Some(graph_path.clone()))?;
+    if ui {
+        return Ok(CliResult::ShowDiff(Box::new(session)));
+    }
+    let diff = session.diff();
+    if diff.is_empty() {
+        println!("No changes");
+        return Ok(CliResult::Exit(0));
+    }
+    print!("{}", diff.summary());
+    Ok(CliResult::Exit(1))
+}
+
+fn parse_check_args(args: &[String]) -> anyhow::Result<(PathBuf, PathBuf)> {
+    let mut graph_path = None;
+    let mut bindings_path = PathBuf::from("bindings.json");
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bindings" => {
+                bindings_path = args.next()
+                    .ok_or_else(|| anyhow::anyhow!("--bindings needs a path"))?
+                    .into();
+            }
+            _ if graph_path.is_none() => graph_path = Some(PathBuf::from(arg)),
+            _ => anyhow::bail!("Unexpected argument '{arg}'"),
+        }
+    }
+    let graph_path = graph_path.ok_or_else(|| anyhow::anyhow!("No graph file given"))?;
+    Ok((graph_path, bindings_path))
+}
+
+// Loads a graph the same way the editor does, with the bindings its binding nodes refer to.
+fn load_graph(graph_path: &PathBuf, bindings_path: &Path) -> anyhow::Result<FullGraphState> {
+    let mut full_state = FullGraphState::default();
+    // bindings first, loading the graph fixes up the binding nodes with them
+    full_state.user_state.bindings = load_bindings(bindings_path)
+        .map_err(|e| anyhow::anyhow!("Failed to load bindings from {}: {e:#}", bindings_path.display()))?;
+    let upgrade = full_state.load_state(graph_path)
+        .map_err(|e| anyhow::anyhow!("Failed to load {}: {e:#}", graph_path.display()))?;
+    if let Some(upgrade) = upgrade {
+        for change in upgrade.changes {
+            eprintln!("{}: upgraded from file format version {}: {change}", graph_path.display(), upgrade.from);
+        }
+    }
+    Ok(full_state)
+}
+
+fn check(graph_path: &PathBuf, bindings_path: &Path) -> i32 {
+    let full_state = match load_graph(graph_path, bindings_path) {
+        Ok(full_state) => full_state,
+        Err(e) => {
+            eprintln!("{e:#}");
+            return 2;
+        }
+    };
+    let root = full_state.root_state();
+    match check_graph_types(&root.state.graph, &root.user_state) {
+        Ok(errors) if errors.is_empty() => {
+            println!("{}: no problems found", graph_path.display());
+            0
+        }
+        Ok(errors) => {
+            for error in errors.iter() {
+                println!("{}: {error}", graph_path.display());
+            }
+            1
+        }
+        Err(e) => {
+            eprintln!("{}: {e:#}", graph_path.display());
+            1
+        }
+    }
+}
+
+// Prints the Markdown interface document of a graph, see app/interface.rs.
+fn interface(graph_path: &PathBuf, bindings_path: &Path) -> i32 {
+    match load_graph(graph_path, bindings_path).and_then(|full_state| full_state.interface_markdown()) {
+        Ok(markdown) => {
+            print!("{markdown}");
+            0
+        }
+        Err(e) => {
+            eprintln!("{e:#}");
+            2
+        }
+    }
+}
+
+// Prints the FGD entry of a graph, or adds it to an FGD file, see app/fgd.rs.
+fn fgd(args: &[String]) -> anyhow::Result<()> {
+    let mut fgd_path = None;
+    let mut rest = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--add" => fgd_path = Some(PathBuf::from(args.next().ok_or_else(|| anyhow::anyhow!("--add needs a path"))?)),
+            _ => rest.push(arg.clone()),
+        }
+    }
+    let (graph_path, bindings_path) = parse_check_args(&rest)?;
+    let entry = load_graph(&graph_path, &bindings_path)?.fgd_entry()?;
+    match fgd_path {
+        Some(fgd_path) => {
+            add_to_fgd_file(&fgd_path, &entry)?;
+            println!("{}: added {}", fgd_path.display(), entry.key);
+        }
+        None => print!("{}", merge_fgd("", &entry)?),
+    }
+    Ok(())
+}
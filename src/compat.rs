@@ -1 +0,0 @@
-pub mod format_v1;
\ No newline at end of file
@@ -1,6 +1,11 @@
 mod instruction_templates;
+mod libraries;
+mod macros;
 mod nodes;
 pub mod serialization;
+pub mod typecheck;
+#[cfg(any(feature = "nongame_asset_build", test))]
+mod resource;
 
 use std::{fs, borrow::Cow};
 use anyhow::anyhow;
@@ -16,7 +21,7 @@ use crate::utils::*;
 use serialization::*;
 
 #[cfg(feature = "nongame_asset_build")]
-use std::{path::PathBuf, path, process::Command};
+use std::{path::PathBuf, path};
 #[cfg(feature = "nongame_asset_build")]
 use crate::app::types::EditorConfig;
 
@@ -103,6 +108,21 @@ macro_rules! reg_map_setup_inputs {
     }
 }
 
+// The graph as the rest of the compiler sees it, with library functions and macros inlined.
+pub fn linked_graph(graph: &PulseGraph, graph_state: &PulseGraphState) -> anyhow::Result<(PulseGraph, PulseGraphState)> {
+    let mut graph = graph.clone();
+    let mut graph_state = graph_state.clone();
+    libraries::link_library_functions(&mut graph, &mut graph_state)?;
+    macros::expand_macros(&mut graph, &mut graph_state)?;
+    Ok((graph, graph_state))
+}
+
+// Type checks the graph like compiling it would, with the library functions and macros inlined.
+pub fn check_graph_types(graph: &PulseGraph, graph_state: &PulseGraphState) -> anyhow::Result<Vec<typecheck::TypeError>> {
+    let (graph, graph_state) = linked_graph(graph, graph_state)?;
+    Ok(typecheck::typecheck_graph(&graph, &graph_state))
+}
+
 // process all inflow nodes and logic chain.
 // returns false if no inflow node was processed
 fn traverse_inflow_nodes(
@@ -317,6 +337,7 @@ fn traverse_function_entry(
     if existing_entrypoint.is_none() {
         let chunk_id = graph_def.create_chunk();
         let chunk = graph_def.chunks.get_mut(chunk_id as usize).unwrap();
+        #[allow(clippy::needless_late_init)]
         let ret_value;
         // node specific thingies.
         match node.user_data.template {
@@ -383,7 +404,9 @@ fn traverse_function_entry(
         Ok(ret_value)
     } else {
         // we already traversed this entrypoint, so we can just return the chunk id
-        Ok(existing_entrypoint.unwrap().1)
+        #[allow(clippy::unnecessary_unwrap)]
+        let existing_entrypoint = existing_entrypoint.unwrap();
+        Ok(existing_entrypoint.1)
     }
 }
 
@@ -484,17 +507,17 @@ fn traverse_ent_output_cell(
     Ok(())
 }
 
-pub fn compile_graph(
-    graph: &PulseGraph,
-    graph_state: &PulseGraphState,
-    #[cfg(feature = "nongame_asset_build")]
-    config: &EditorConfig,
-) -> anyhow::Result<()> {
+// Builds the graph definition that gets written out, without touching the disk.
+fn build_graph_definition(graph: &PulseGraph, graph_state: &PulseGraphState) -> anyhow::Result<kv3::Value> {
+    // library definitions and macros get inlined first, the rest of the compiler never sees them
+    let (graph, graph_state) = linked_graph(graph, graph_state)?;
+    let (graph, graph_state) = (&graph, &graph_state);
+    let type_errors = typecheck::typecheck_graph(graph, graph_state);
+    if !type_errors.is_empty() {
+        let errors: Vec<String> = type_errors.iter().map(|e| e.to_string()).collect();
+        anyhow::bail!("Graph compile failed, type errors found:\n{}", errors.join("\n"));
+    }
     let mut graph_def = PulseGraphDef::default();
-    let file_dir = graph_state
-        .save_file_path
-        .as_ref()
-        .ok_or(anyhow!("File needs to be saved before compiling"))?;
     graph_def.variables = graph_state.variables.clone();
     graph_def.public_outputs = graph_state.public_outputs.clone();
     graph_def.map_name = String::from("maps/main.vmap");
@@ -513,7 +536,20 @@ pub fn compile_graph(
             anyhow::bail!("Graph compile failed: {}", e);
         }
     }
-    let data = kv3::to_string(&graph_def.serialize());
+    Ok(graph_def.serialize())
+}
+
+pub fn compile_graph(
+    graph: &PulseGraph,
+    graph_state: &PulseGraphState,
+    #[cfg(feature = "nongame_asset_build")]
+    config: &EditorConfig,
+) -> anyhow::Result<()> {
+    let file_dir = graph_state
+        .save_file_path
+        .as_ref()
+        .ok_or(anyhow!("File needs to be saved before compiling"))?;
+    let graph_value = build_graph_definition(graph, graph_state)?;
     let _ = fs::create_dir_all(file_dir).map_err(|e| {
         anyhow!(
             "Graph compile failed: Failed to create output directory: {}",
@@ -524,7 +560,7 @@ pub fn compile_graph(
     #[cfg(not(feature = "nongame_asset_build"))] {
         let mut file_path = file_dir.clone();
         file_path.set_extension("vpulse");
-        fs::write(file_path, data)
+        fs::write(file_path, kv3::to_string(&graph_value))
             .map_err(|e| anyhow!("Graph compile failed: Failed to write to file: {}", e))?
     }
 
@@ -532,21 +568,18 @@ pub fn compile_graph(
         let file_name = file_dir.file_name().ok_or_else(|| {
             anyhow::anyhow!("The provided file source path doesn't contain a filename, please re-save the file: '{}'", file_dir.display())
         })?;
-        // create a temporary file in the system temp directory with random suffix to avoid confilicts (very unlikely anyways)
-        use rand::{Rng, distributions::Alphanumeric};
-        let temp_dir_file = std::env::temp_dir().join(
-            format!("{}_{}.vpulse", file_name.display(), 
-            rand::thread_rng()
-                .sample_iter(&Alphanumeric)
-                .take(6)
-                .map(char::from)
-                .collect::<String>()
-        ));
-        fs::write(&temp_dir_file, data)
+        let red2_path = config.red2_template_path.as_path();
+        let red2_template = fs::read_to_string(red2_path).map_err(|e| {
+            anyhow!("Graph compile failed: RED2 template location was specified incorrectly in the config: {}: {}", red2_path.display(), e)
+        })?;
+        let resource = resource::compile_resource(&red2_template, file_dir, &graph_value)
+            .map_err(|e| anyhow!("Graph compile failed: {:#}", e))?;
+        // get rid of file name for the output path
+        let mut out_file = get_output_path(file_dir.parent().unwrap())?.join(file_name);
+        out_file.set_extension("vpulse_c");
+        println!("Determined full output path: {}", out_file.display());
+        fs::write(out_file, resource)
             .map_err(|e| anyhow!("Graph compile failed: Failed to write to file: {}", e))?;
-        run_asset_builder(config, &temp_dir_file, file_dir)
-            .map_err(|e| anyhow!("Graph compile failed: Failed to run asset builder: {}", e))?;
-        let _ = fs::remove_file(&temp_dir_file); // ok to ignore
     }
     Ok(())
 }
@@ -589,49 +622,6 @@ fn get_output_path(original_path: &path::Path) -> anyhow::Result<PathBuf> {
     Ok(original_path.into())
 }
 
-#[cfg(feature = "nongame_asset_build")]
-fn run_asset_builder(config: &EditorConfig, path_src: &path::Path, path_editor_file: &path::Path) -> anyhow::Result<()> {
-    println!("Running asset assembler for file: {}", path_src.display());
-    let assetbuilder_path = config.assetassembler_path.as_path();
-    let red2_path = config.red2_template_path.as_path();
-    if !assetbuilder_path.exists() || !assetbuilder_path.is_file() {
-        return Err(anyhow::anyhow!(
-            "Asset assembler location was specified incorrectly in the config: {}",
-            assetbuilder_path.display()
-        ));
-    }
-    if !red2_path.exists() || !red2_path.is_file() {
-        return Err(anyhow::anyhow!(
-            "RED2 template location was specified incorrectly in the config: {}",
-            red2_path.display()
-        ));
-    }
-    if !path_editor_file.exists() || !path_editor_file.is_file() {
-        return Err(anyhow::anyhow!("File needs to be saved before compilation"));
-    }
-    let file_name = path_editor_file.file_name().ok_or_else(|| {
-        anyhow::anyhow!("Can't get file name from the path: {}", path_src.display())
-    })?;
-    // get rid of file name for the output path
-    let mut out_file = get_output_path(path_editor_file.parent().unwrap())?.join(file_name);
-    out_file.set_extension("vpulse_c");
-    println!("Determined full output path: {}", out_file.display());
-    let mut process = Command::new(config.python_interpreter.as_str())
-        .arg(assetbuilder_path)
-        .arg("-p")
-        .arg("vpulse")
-        .arg("-f")
-        .arg(red2_path)
-        .arg(path_src)
-        .arg("-o")
-        .arg(out_file.as_os_str())
-        .spawn()?;
-    // it's fine to freeze the UI here, because recompiling while loading could lead to issues.
-    // this process should usually be fast enough to not be very noticable.
-    process.wait()?;
-    Ok(())
-}
-
 fn try_find_output_mapping(graph_def: &PulseGraphDef, output_id: &Option<OutputId>) -> i32 {
     match output_id {
         Some(output_id) => {
@@ -672,7 +662,7 @@ fn get_input_register_or_create_constant_from_id(
     value_type: PulseValueType,
     always_reevaluate: bool,
 ) -> anyhow::Result<Option<i32>> {
-    let connection_to_input: Option<OutputId> = graph.connection(input_id);
+    let connection_to_input: Option<OutputId> = get_connected_output(graph, input_id);
     let target_register: i32;
     // if we find a connection, then traverse to that node, whatever happens we should get a register id back.
     match connection_to_input {
@@ -942,7 +932,7 @@ fn traverse_nodes_and_populate<'a>(
             // to determine if we need to add it to the EntFire call
             let param_value_exists = 'checkParmValue: {
                 let input_id = current_node.get_input("value")?;
-                let connection_to_input: Option<OutputId> = graph.connection(input_id);
+                let connection_to_input: Option<OutputId> = get_connected_output(graph, input_id);
                 if connection_to_input.is_some() {
                     break 'checkParmValue true;
                 }
@@ -963,7 +953,7 @@ fn traverse_nodes_and_populate<'a>(
             let cell = CPulseCell_Step_EntFire::new(input_value.clone().into());
 
             let port_ehandle = current_node.get_input("entityHandle")?;
-            if graph.connection(port_ehandle).is_some() {
+            if get_connected_output(graph, port_ehandle).is_some() {
                 let register_map = reg_map_setup_inputs!("hTarget", reg_entity, "pParam", reg_param);
                 add_cell_and_invoking(
                     graph_def,
@@ -1004,8 +994,8 @@ fn traverse_nodes_and_populate<'a>(
                 .get_input("B")
                 .map_err(|e| anyhow!(e).context("ConcatString node"))?;
             let input_ids = [id_a, id_b];
-            let connection_to_a = graph.connection(id_a);
-            let connection_to_b = graph.connection(id_b);
+            let connection_to_a = get_connected_output(graph, id_a);
+            let connection_to_b = get_connected_output(graph, id_b);
             let connections_to_resolve: [Option<OutputId>; 2] = [connection_to_a, connection_to_b];
             let mut input_registers: [i32; 2] = [-1, -1];
 
@@ -1048,7 +1038,9 @@ fn traverse_nodes_and_populate<'a>(
                             chunk.add_instruction(instruction);
                             graph_def.add_register_mapping_input(input_ids[i], input_registers[i]);
                         } else {
-                            input_registers[i] = register.unwrap();
+                            #[allow(clippy::unnecessary_unwrap)]
+                            let register = register.unwrap();
+                            input_registers[i] = register;
                         }
                     }
                 }
@@ -1109,7 +1101,8 @@ fn traverse_nodes_and_populate<'a>(
             let value_id = current_node
                 .get_input("value")
                 .map_err(|e| anyhow!(e).context("IntToString node"))?;
-            let connection_to_value = graph.connection(value_id);
+            let connection_to_value = get_connected_output(graph, value_id);
+            #[allow(clippy::needless_late_init)]
             let register_input: i32;
             match connection_to_value {
                 Some(out) => {
@@ -1276,7 +1269,7 @@ fn traverse_nodes_and_populate<'a>(
             let hentity_input_id = current_node
                 .get_input("hEntity")
                 .map_err(|e| anyhow!(e).context("DebugWorldText node"))?;
-            let connection_to_hentity = graph.connection(hentity_input_id);
+            let connection_to_hentity = get_connected_output(graph, hentity_input_id);
             if connection_to_hentity.is_none() {
                 println!("No connection found for hEntity input in DebugWorldText node. Node will not be processed, next action won't execute.");
                 return Ok(-1);
@@ -2657,6 +2650,89 @@ fn traverse_nodes_and_populate<'a>(
             );
             return Ok(reg_out);
         }
+        PulseNodeTemplate::CombineFlags => {
+            let reg_out = try_find_output_mapping(graph_def, output_id);
+            if reg_out > -1 {
+                return Ok(reg_out);
+            }
+            let typ = get_constant_graph_input_value!(graph, current_node, "type", try_pulse_type);
+            let (_, flags) = get_constant_graph_input_value!(graph, current_node, "flags", try_enum);
+            let clear = get_constant_graph_input_value!(graph, current_node, "clear", try_to_bool);
+            let reg_value = get_register!("value", typ.clone())
+                .ok_or(anyhow!("CombineFlags node: Failed to get input register for 'value'."))?;
+            // there are no bitwise instructions, so each flag is set or cleared with integer math:
+            // bit_set = (value / bit) % 2, value = value (+ bit) - bit * bit_set
+            let mut reg_int = graph_def.add_chunk_register(target_chunk as usize, "PVAL_INT".to_string(), None)
+                .ok_or_else(|| anyhow!("Failed to add register for 'CombineFlags' node."))?;
+            graph_def.add_chunk_instruction(target_chunk as usize, instruction_templates::convert_value(reg_int, reg_value));
+            for flag in flags.get_flags() {
+                let bit = flag.get_flag_bits()
+                    .ok_or_else(|| anyhow!("CombineFlags node: the value of {} isn't known.", flag.to_str()))?;
+                if bit == 0 {
+                    continue;
+                }
+                let reg_bit = add_int_constant(graph_def, target_chunk, bit)?;
+                let reg_is_set = add_flag_bit_test(graph_def, target_chunk, reg_int, reg_bit)?;
+                let reg_set_part = add_int_op(graph_def, target_chunk, "MUL_INT", reg_is_set, reg_bit)?;
+                if !clear {
+                    reg_int = add_int_op(graph_def, target_chunk, "ADD_INT", reg_int, reg_bit)?;
+                }
+                reg_int = add_int_op(graph_def, target_chunk, "SUB_INT", reg_int, reg_set_part)?;
+            }
+            let reg_out = graph_def.add_chunk_register(target_chunk as usize, typ.to_string(), None)
+                .ok_or_else(|| anyhow!("Failed to add register for 'CombineFlags' node."))?;
+            graph_def.add_chunk_instruction(target_chunk as usize, instruction_templates::convert_value(reg_out, reg_int));
+            if let Some(out) = output_id {
+                graph_def.add_register_mapping(*out, reg_out);
+            }
+            return Ok(reg_out);
+        }
+        PulseNodeTemplate::TestFlags => {
+            let reg_out = try_find_output_mapping(graph_def, output_id);
+            if reg_out > -1 {
+                return Ok(reg_out);
+            }
+            let typ = get_constant_graph_input_value!(graph, current_node, "type", try_pulse_type);
+            let (_, flags) = get_constant_graph_input_value!(graph, current_node, "flags", try_enum);
+            let reg_value = get_register!("value", typ)
+                .ok_or(anyhow!("TestFlags node: Failed to get input register for 'value'."))?;
+            let reg_int = graph_def.add_chunk_register(target_chunk as usize, "PVAL_INT".to_string(), None)
+                .ok_or_else(|| anyhow!("Failed to add register for 'TestFlags' node."))?;
+            graph_def.add_chunk_instruction(target_chunk as usize, instruction_templates::convert_value(reg_int, reg_value));
+            // count how many of the requested flags are set, all of them have to be.
+            let mut reg_count = add_int_constant(graph_def, target_chunk, 0)?;
+            let mut expected_count = 0;
+            for flag in flags.get_flags() {
+                let bit = flag.get_flag_bits()
+                    .ok_or_else(|| anyhow!("TestFlags node: the value of {} isn't known.", flag.to_str()))?;
+                if bit == 0 {
+                    continue;
+                }
+                let reg_bit = add_int_constant(graph_def, target_chunk, bit)?;
+                let reg_is_set = add_flag_bit_test(graph_def, target_chunk, reg_int, reg_bit)?;
+                reg_count = add_int_op(graph_def, target_chunk, "ADD_INT", reg_count, reg_is_set)?;
+                expected_count += 1;
+            }
+            let reg_expected = add_int_constant(graph_def, target_chunk, expected_count)?;
+            let reg_out = graph_def.add_chunk_register(target_chunk as usize, "PVAL_BOOL".to_string(), None)
+                .ok_or_else(|| anyhow!("Failed to add register for 'TestFlags' node."))?;
+            graph_def.add_chunk_instruction(
+                target_chunk as usize,
+                instruction_templates::binary_op("EQ_INT", reg_count, reg_expected, reg_out)
+            );
+            if let Some(out) = output_id {
+                graph_def.add_register_mapping(*out, reg_out);
+            }
+            return Ok(reg_out);
+        }
+        // connections are resolved through reroutes, so the compiler never lands on one
+        PulseNodeTemplate::Reroute => {
+            anyhow::bail!("Reroute node was reached while compiling, it should have been skipped");
+        }
+        // these only exist inside of macros, and are gone after the macros are expanded
+        PulseNodeTemplate::MacroInputs | PulseNodeTemplate::MacroOutputs => {
+            anyhow::bail!("Macro port nodes can't be used outside of a macro");
+        }
         _ => todo!(
             "Implement node template: {:?}",
             current_node.user_data.template
@@ -2665,6 +2741,30 @@ fn traverse_nodes_and_populate<'a>(
     Ok(-1)
 }
 
+// loads an integer constant into a new register
+fn add_int_constant(graph_def: &mut PulseGraphDef, chunk_id: i32, value: i32) -> anyhow::Result<i32> {
+    let const_id = graph_def.add_constant(PulseConstant::Integer(value));
+    let reg = graph_def.add_chunk_register(chunk_id as usize, "PVAL_INT".to_string(), None)
+        .ok_or_else(|| anyhow!("Failed to add register for integer constant."))?;
+    graph_def.add_chunk_instruction(chunk_id as usize, instruction_templates::get_const(const_id, reg));
+    Ok(reg)
+}
+
+// runs an integer instruction on two registers, returning the new result register
+fn add_int_op(graph_def: &mut PulseGraphDef, chunk_id: i32, code: &str, reg_a: i32, reg_b: i32) -> anyhow::Result<i32> {
+    let reg_out = graph_def.add_chunk_register(chunk_id as usize, "PVAL_INT".to_string(), None)
+        .ok_or_else(|| anyhow!("Failed to add register for {code}."))?;
+    graph_def.add_chunk_instruction(chunk_id as usize, instruction_templates::binary_op(code, reg_a, reg_b, reg_out));
+    Ok(reg_out)
+}
+
+// returns a register with 1 if the bit is set in the value, 0 otherwise
+fn add_flag_bit_test(graph_def: &mut PulseGraphDef, chunk_id: i32, reg_value: i32, reg_bit: i32) -> anyhow::Result<i32> {
+    let reg_two = add_int_constant(graph_def, chunk_id, 2)?;
+    let reg_shifted = add_int_op(graph_def, chunk_id, "DIV_INT", reg_value, reg_bit)?;
+    add_int_op(graph_def, chunk_id, "MOD_INT", reg_shifted, reg_two)
+}
+
 fn make_library_call(
     graph_def: &mut PulseGraphDef, 
     graph_state: &PulseGraphState, 
@@ -2695,3 +2795,41 @@ fn make_library_call(
     graph_def.add_invoke_binding(invoke_binding);
     Ok(reg_map)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::FullGraphState;
+
+    fn add_node(full_state: &mut FullGraphState, template: PulseNodeTemplate) -> NodeId {
+        full_state.add_node_at(template, eframe::egui::Pos2::ZERO, eframe::egui::vec2(200.0, 200.0))
+    }
+
+    fn set_input(graph: &mut PulseGraph, node_id: NodeId, name: &str, value: PulseGraphValueType) {
+        let input = graph.nodes[node_id].get_input(name).unwrap();
+        graph.get_input_mut(input).value = value;
+    }
+
+    #[test]
+    fn test_test_flags_on_plain_enum() {
+        let mut full_state = FullGraphState::default();
+        let method = add_node(&mut full_state, PulseNodeTemplate::CellPublicMethod);
+        let compare = add_node(&mut full_state, PulseNodeTemplate::CompareIf);
+        let test = add_node(&mut full_state, PulseNodeTemplate::TestFlags);
+        let graph = &mut full_state.state.graph;
+        set_input(graph, method, "name", PulseGraphValueType::String { value: "Run".into() });
+        let enum_type = SchemaEnumType::TraceContents;
+        let value = SchemaEnumValue::TraceContents(PulseTraceContents::Solid);
+        set_input(graph, test, "type", PulseGraphValueType::Typ { value: PulseValueType::PVAL_SCHEMA_ENUM(enum_type) });
+        set_input(graph, test, "value", PulseGraphValueType::SchemaEnum { enum_type, value: value.clone() });
+        set_input(graph, test, "flags", PulseGraphValueType::SchemaEnum { enum_type, value });
+        let method_out = graph.nodes[method].get_output("outAction").unwrap();
+        graph.add_connection(method_out, graph.nodes[compare].get_input("ActionIn").unwrap(), 0);
+        let test_out = graph.nodes[test].get_output("out").unwrap();
+        graph.add_connection(test_out, graph.nodes[compare].get_input("condition").unwrap(), 0);
+
+        // TraceContents values aren't bits, testing them used to compile to a check that's always true
+        let error = build_graph_definition(&full_state.state.graph, &full_state.user_state).unwrap_err();
+        assert!(format!("{error:#}").contains("isn't known"), "{error:#}");
+    }
+}
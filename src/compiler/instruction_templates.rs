@@ -170,6 +170,17 @@ pub fn or_bool(register_a: i32, register_b: i32, register_out: i32) -> Instructi
     instr
 }
 
+// generic instruction taking two registers, eg. ADD_INT, MOD_INT
+pub fn binary_op(code: &str, register_a: i32, register_b: i32, register_out: i32) -> Instruction {
+    Instruction {
+        code: String::from(code),
+        reg0: register_out,
+        reg1: register_a,
+        reg2: register_b,
+        ..Default::default()
+    }
+}
+
 pub fn not_bool(register_in: i32, register_out: i32) -> Instruction {
     Instruction {
         code: String::from("NOT"),
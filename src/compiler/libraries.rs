@@ -0,0 +1,139 @@
+// Pulls the functions used from imported libraries into the graph being compiled.
+use std::collections::{HashMap, hash_map::Entry};
+use anyhow::anyhow;
+use egui_node_graph2::*;
+use crate::app::types::*;
+use super::macros::{copy_nodes, rebind_library_nodes};
+
+// Replaces LibraryFunction nodes with CallNodes pointing to a copy of the library function.
+// Each used library is copied in once, without its inflow nodes so they don't run as part of this graph.
+pub fn link_library_functions(graph: &mut PulseGraph, graph_state: &mut PulseGraphState) -> anyhow::Result<()> {
+    let call_nodes: Vec<(NodeId, LibraryId, NodeUid)> = graph.nodes.iter()
+        .filter_map(|(node_id, node)| match node.user_data.template {
+            PulseNodeTemplate::LibraryFunction { library, function } => Some((node_id, library, function)),
+            _ => None,
+        })
+        .collect();
+    // library -> uid of the function in the library -> copied Function node
+    let mut linked: HashMap<LibraryId, HashMap<NodeUid, NodeId>> = HashMap::new();
+    for (call_node, library_id, function) in call_nodes {
+        if let Entry::Vacant(entry) = linked.entry(library_id) {
+            entry.insert(link_library(graph, graph_state, library_id)?);
+        }
+        let function_node = match linked[&library_id].get(&function) {
+            Some(function_node) => *function_node,
+            None => {
+                // the name the function had when the node was made
+                let name_input = graph.nodes[call_node].get_input("functionName")?;
+                let function_name = graph.get_input(name_input).value.clone().try_to_string()?;
+                let library_name = graph_state.find_library_import(library_id).map_or("", |lib| lib.name.as_str());
+                anyhow::bail!("Function '{function_name}' was not found in the imported library '{library_name}'");
+            }
+        };
+
+        graph.nodes[call_node].user_data.template = PulseNodeTemplate::CallNode;
+        graph.add_input_param(
+            call_node,
+            "nodeId".into(),
+            PulseDataType::NoideChoice,
+            PulseGraphValueType::NodeChoice { node: Some(function_node) },
+            InputParamKind::ConstantOnly,
+            true,
+        );
+    }
+    Ok(())
+}
+
+fn link_library(
+    graph: &mut PulseGraph,
+    graph_state: &mut PulseGraphState,
+    library_id: LibraryId,
+) -> anyhow::Result<HashMap<NodeUid, NodeId>> {
+    let library = graph_state.find_library(library_id)
+        .ok_or_else(|| anyhow!("A library function is used from a library that isn't loaded"))?
+        .clone();
+    let copied = copy_nodes(graph, &library.graph, |node| !matches!(
+        node.user_data.template,
+        PulseNodeTemplate::EventHandler
+            | PulseNodeTemplate::CellPublicMethod
+            | PulseNodeTemplate::GraphHook
+            | PulseNodeTemplate::EntOutputHandler
+    ));
+    rebind_library_nodes(graph, &copied, library_id)?;
+    for (source, targets) in library.graph.iter_connection_groups() {
+        let Some(input) = copied.inputs.get(&source) else {
+            continue;
+        };
+        for output in targets.iter().filter_map(|output| copied.outputs.get(output)) {
+            let pos = graph.connections(*input).len();
+            graph.add_connection(*output, *input, pos);
+        }
+    }
+    // the variables and outputs used by the library need to exist in the compiled graph too
+    for var in library.variables {
+        if !graph_state.variables.iter().any(|v| v.name == var.name) {
+            graph_state.variables.push(var);
+        }
+    }
+    for output in library.public_outputs {
+        if !graph_state.public_outputs.iter().any(|o| o.name == output.name) {
+            graph_state.public_outputs.push(output);
+        }
+    }
+    Ok(library.functions.iter()
+        .filter_map(|(_, node_id)| {
+            let uid = library.graph.nodes.get(*node_id)?.user_data.uid;
+            copied.nodes.get(node_id).map(|new_node| (uid, *new_node))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use eframe::egui;
+    use super::*;
+    use crate::app::FullGraphState;
+
+    fn add_node(full_state: &mut FullGraphState, template: PulseNodeTemplate) -> NodeId {
+        full_state.add_node_at(template, egui::Pos2::ZERO, egui::vec2(200.0, 200.0))
+    }
+
+    #[test]
+    fn test_library_functions_are_found_by_uid() {
+        let mut library_state = FullGraphState::default();
+        let first = add_node(&mut library_state, PulseNodeTemplate::Function);
+        let second = add_node(&mut library_state, PulseNodeTemplate::Function);
+        let second_uid = library_state.state.graph.nodes[second].user_data.uid;
+        let library_id = LibraryId(0);
+        let mut library = LoadedLibrary {
+            id: library_id,
+            graph: library_state.state.graph.clone(),
+            macros: vec![],
+            functions: vec![("First".into(), first), ("Second".into(), second)],
+            variables: vec![],
+            public_outputs: vec![],
+        };
+
+        let mut full_state = FullGraphState::default();
+        full_state.user_state.loaded_libraries.push(library.clone());
+        let call = add_node(&mut full_state, PulseNodeTemplate::LibraryFunction { library: library_id, function: second_uid });
+        assert_eq!(full_state.state.graph.nodes[call].label, "Call Second");
+
+        // the library changed the order of its functions since the node was made
+        library.functions.reverse();
+        full_state.user_state.loaded_libraries = vec![library.clone()];
+        let (mut graph, mut graph_state) = (full_state.state.graph.clone(), full_state.user_state.clone());
+        link_library_functions(&mut graph, &mut graph_state).unwrap();
+        let node_id = graph.nodes[call].get_input("nodeId").unwrap();
+        let target = graph.get_input(node_id).value.clone().try_node_id().unwrap();
+        assert_eq!(graph.nodes[target].user_data.uid, second_uid);
+
+        // and then removed it
+        library.graph.remove_node(second);
+        library.functions.retain(|(_, node_id)| *node_id != second);
+        full_state.user_state.loaded_libraries = vec![library];
+        let (mut graph, mut graph_state) = (full_state.state.graph.clone(), full_state.user_state.clone());
+        let err = link_library_functions(&mut graph, &mut graph_state).unwrap_err();
+        assert!(err.to_string().contains("'Second' was not found"), "{err}");
+    }
+}
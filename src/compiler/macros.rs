@@ -0,0 +1,303 @@
+// Inlines macro nodes into the graph before compiling, so the result is the same as if
+// the nodes were never collapsed.
+use std::collections::HashMap;
+use anyhow::anyhow;
+use egui_node_graph2::*;
+use crate::app::types::*;
+
+// upper bound on the number of expanded macros, to catch macros that contain themselves
+const MAX_MACRO_EXPANSIONS: usize = 4096;
+
+// Replaces all of the macro nodes in the graph by their contents.
+pub fn expand_macros(graph: &mut PulseGraph, graph_state: &mut PulseGraphState) -> anyhow::Result<()> {
+    let mut expansions = 0;
+    while let Some(macro_node) = graph.nodes.iter().find(|(_, node)| is_macro_node(node)).map(|(id, _)| id) {
+        expansions += 1;
+        if expansions > MAX_MACRO_EXPANSIONS {
+            anyhow::bail!("Too many macros to expand, does a macro contain itself?");
+        }
+        expand_macro_node(graph, graph_state, macro_node)?;
+    }
+    Ok(())
+}
+
+fn is_macro_node(node: &Node<PulseNodeData>) -> bool {
+    matches!(node.user_data.template, PulseNodeTemplate::Macro { .. } | PulseNodeTemplate::LibraryMacro { .. })
+}
+
+// ids of the nodes and params copied from another graph
+#[derive(Default)]
+pub struct CopiedNodes {
+    pub nodes: HashMap<NodeId, NodeId>,
+    pub inputs: HashMap<InputId, InputId>,
+    pub outputs: HashMap<OutputId, OutputId>,
+}
+
+// Copies the nodes matching the filter from another graph, without their connections.
+// Node references (eg. CallNode) can only point to the copied nodes, others are cleared.
+pub fn copy_nodes(
+    graph: &mut PulseGraph,
+    source: &PulseGraph,
+    filter: impl Fn(&Node<PulseNodeData>) -> bool,
+) -> CopiedNodes {
+    let mut copied = CopiedNodes::default();
+    for (source_id, source_node) in source.nodes.iter() {
+        if !filter(source_node) {
+            continue;
+        }
+        let new_node = graph.add_node(source_node.label.clone(), source_node.user_data.clone(), |graph, node_id| {
+            for (name, input_id) in source_node.inputs.iter() {
+                let param = source.get_input(*input_id);
+                let new_input = graph.add_input_param(
+                    node_id,
+                    name.clone(),
+                    param.typ.clone(),
+                    param.value.clone(),
+                    param.kind,
+                    param.shown_inline,
+                );
+                copied.inputs.insert(*input_id, new_input);
+            }
+            for (name, output_id) in source_node.outputs.iter() {
+                let new_output = graph.add_output_param(node_id, name.clone(), source.get_output(*output_id).typ.clone());
+                copied.outputs.insert(*output_id, new_output);
+            }
+        });
+        let user_data = &mut graph.nodes[new_node].user_data;
+        user_data.added_inputs = user_data.added_inputs.iter()
+            .filter_map(|input_id| copied.inputs.get(input_id).copied())
+            .collect();
+        copied.nodes.insert(source_id, new_node);
+    }
+    for input_id in copied.inputs.values() {
+        if let PulseGraphValueType::NodeChoice { node } = &mut graph.get_input_mut(*input_id).value {
+            *node = node.and_then(|target| copied.nodes.get(&target).copied());
+        }
+    }
+    copied
+}
+
+// Nodes copied from a library refer to the macros of that library, not the ones of the graph.
+pub fn rebind_library_nodes(graph: &mut PulseGraph, copied: &CopiedNodes, library: LibraryId) -> anyhow::Result<()> {
+    for node_id in copied.nodes.values() {
+        let template = &mut graph.nodes[*node_id].user_data.template;
+        match *template {
+            PulseNodeTemplate::Macro { id } => *template = PulseNodeTemplate::LibraryMacro { library, id },
+            PulseNodeTemplate::LibraryMacro { .. } | PulseNodeTemplate::LibraryFunction { .. } => {
+                anyhow::bail!("Libraries that use other libraries are not supported");
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn expand_macro_node(
+    graph: &mut PulseGraph,
+    graph_state: &mut PulseGraphState,
+    macro_node: NodeId,
+) -> anyhow::Result<()> {
+    let label = &graph.nodes[macro_node].label;
+    let (macro_def, library) = match graph.nodes[macro_node].user_data.template {
+        PulseNodeTemplate::Macro { id } => (graph_state.find_macro(id), None),
+        PulseNodeTemplate::LibraryMacro { library, id } => {
+            let loaded = graph_state.find_library(library)
+                .ok_or_else(|| anyhow!("Macro node '{label}' is from a library that isn't loaded"))?;
+            (loaded.find_macro(id), Some(library))
+        }
+        _ => return Ok(()),
+    };
+    let macro_def = macro_def
+        .ok_or_else(|| anyhow!("Macro node '{label}' refers to a macro that doesn't exist"))?;
+    let inner = &macro_def.state.graph.clone();
+    let exposed_nodes = macro_def.exposed_nodes.clone();
+
+    // copy the nodes over, the port nodes are replaced by the connections of the macro node
+    let copied = copy_nodes(graph, inner, |node| !matches!(
+        node.user_data.template,
+        PulseNodeTemplate::MacroInputs | PulseNodeTemplate::MacroOutputs
+    ));
+    if let Some(library) = library {
+        rebind_library_nodes(graph, &copied, library)?;
+    }
+    for (inner_id, new_node) in copied.nodes.iter() {
+        if let Some(name) = exposed_nodes.get(*inner_id) {
+            graph_state.exposed_nodes.insert(*new_node, name.clone());
+        }
+    }
+    let (input_map, output_map) = (&copied.inputs, &copied.outputs);
+
+    // resolves an output inside of the macro to the outputs in the outer graph
+    let macro_node_data = &graph.nodes[macro_node];
+    let resolve_output = |output_id: OutputId| -> Vec<OutputId> {
+        let output = inner.get_output(output_id);
+        if matches!(inner.nodes[output.node].user_data.template, PulseNodeTemplate::MacroInputs) {
+            let port_name = port_name(&inner.nodes[output.node].outputs, output_id);
+            macro_node_data.get_input(&port_name)
+                .map(|input_id| graph.connections(input_id))
+                .unwrap_or_default()
+        } else {
+            output_map.get(&output_id).copied().into_iter().collect()
+        }
+    };
+    let mut new_connections: Vec<(OutputId, InputId)> = vec![];
+    let mut new_values: Vec<(InputId, PulseGraphValueType)> = vec![];
+    for (input_id, output_ids) in inner.iter_connection_groups() {
+        let input = inner.get_input(input_id);
+        let targets: Vec<InputId> = if matches!(inner.nodes[input.node].user_data.template, PulseNodeTemplate::MacroOutputs) {
+            let port_name = port_name(&inner.nodes[input.node].inputs, input_id);
+            let Ok(macro_output) = macro_node_data.get_output(&port_name) else {
+                continue;
+            };
+            graph.iter_connection_groups()
+                .filter(|(_, outputs)| outputs.contains(&macro_output))
+                .map(|(input, _)| input)
+                .collect()
+        } else {
+            input_map.get(&input_id).copied().into_iter().collect()
+        };
+        for output_id in output_ids {
+            let sources = resolve_output(output_id);
+            // unconnected macro input, use the constant value set on the macro node instead
+            let output = inner.get_output(output_id);
+            if sources.is_empty() && matches!(inner.nodes[output.node].user_data.template, PulseNodeTemplate::MacroInputs) {
+                let port_name = port_name(&inner.nodes[output.node].outputs, output_id);
+                if let Ok(macro_input) = macro_node_data.get_input(&port_name) {
+                    for target in targets.iter() {
+                        new_values.push((*target, graph.get_input(macro_input).value.clone()));
+                    }
+                }
+            }
+            for source in sources {
+                for target in targets.iter() {
+                    new_connections.push((source, *target));
+                }
+            }
+        }
+    }
+
+    graph.remove_node(macro_node);
+    for (input_id, value) in new_values {
+        graph.get_input_mut(input_id).value = value;
+    }
+    for (output_id, input_id) in new_connections {
+        let pos = graph.connections(input_id).len();
+        graph.add_connection(output_id, input_id, pos);
+    }
+    Ok(())
+}
+
+fn port_name<T: PartialEq>(params: &[(String, T)], id: T) -> String {
+    params.iter()
+        .find(|(_, param_id)| *param_id == id)
+        .map(|(name, _)| name.clone())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::FullGraphState;
+
+    fn add_node(full_state: &mut FullGraphState, template: PulseNodeTemplate) -> NodeId {
+        let FullGraphState { state, user_state, .. } = full_state;
+        let label = template.node_graph_label(user_state);
+        let user_data = template.user_data(user_state);
+        let node_id = state.graph.add_node(label, user_data, |graph, node_id| {
+            template.build_node(graph, user_state, node_id)
+        });
+        state.node_positions.insert(node_id, eframe::egui::pos2(0.0, 0.0));
+        node_id
+    }
+
+    fn connect(graph: &mut PulseGraph, from: NodeId, to: NodeId) {
+        let output = graph.nodes[from].get_output("outAction").unwrap();
+        let input = graph.nodes[to].get_input("ActionIn").unwrap();
+        graph.add_connection(output, input, 0);
+    }
+
+    #[test]
+    fn test_collapse_and_expand_macro() {
+        let mut full_state = FullGraphState::default();
+        let method = add_node(&mut full_state, PulseNodeTemplate::CellPublicMethod);
+        let log_a = add_node(&mut full_state, PulseNodeTemplate::DebugLog);
+        let log_b = add_node(&mut full_state, PulseNodeTemplate::DebugLog);
+        let graph = &mut full_state.state.graph;
+        let message = graph.nodes[log_a].get_input("pMessage").unwrap();
+        graph.get_input_mut(message).value = PulseGraphValueType::String { value: "hello".into() };
+        connect(graph, method, log_a);
+        connect(graph, log_a, log_b);
+
+        full_state.collapse_to_macro(&[log_a]).unwrap();
+        assert_eq!(full_state.state.graph.nodes.len(), 3);
+        assert!(!full_state.state.graph.nodes.contains_key(log_a));
+
+        let FullGraphState { state, mut user_state, .. } = full_state;
+        let mut graph = state.graph;
+        expand_macros(&mut graph, &mut user_state).unwrap();
+        assert_eq!(graph.nodes.len(), 3);
+        let (inlined, inlined_node) = graph.nodes.iter()
+            .find(|(id, _)| *id != method && *id != log_b)
+            .unwrap();
+        assert_eq!(inlined_node.user_data.template, PulseNodeTemplate::DebugLog);
+        let message = inlined_node.get_input("pMessage").unwrap();
+        assert_eq!(graph.get_input(message).value, PulseGraphValueType::String { value: "hello".into() });
+        let inlined_in = inlined_node.get_input("ActionIn").unwrap();
+        assert_eq!(graph.connections(inlined_in), vec![graph.nodes[method].get_output("outAction").unwrap()]);
+        let log_b_in = graph.nodes[log_b].get_input("ActionIn").unwrap();
+        assert_eq!(graph.connections(log_b_in), vec![graph.nodes[inlined].get_output("outAction").unwrap()]);
+    }
+
+    // CallNode -> Function graph, with a log after the function
+    fn call_function_graph() -> (FullGraphState, [NodeId; 4]) {
+        let mut full_state = FullGraphState::default();
+        let method = add_node(&mut full_state, PulseNodeTemplate::CellPublicMethod);
+        let call = add_node(&mut full_state, PulseNodeTemplate::CallNode);
+        let function = add_node(&mut full_state, PulseNodeTemplate::Function);
+        let log = add_node(&mut full_state, PulseNodeTemplate::DebugLog);
+        full_state.user_state.exposed_nodes.insert(function, "DoLog".into());
+        let graph = &mut full_state.state.graph;
+        let name = graph.nodes[method].get_input("name").unwrap();
+        graph.get_input_mut(name).value = PulseGraphValueType::String { value: "Run".into() };
+        let node_id = graph.nodes[call].get_input("nodeId").unwrap();
+        graph.get_input_mut(node_id).value = PulseGraphValueType::NodeChoice { node: Some(function) };
+        graph.add_input_param(
+            call,
+            "Async".into(),
+            PulseDataType::Bool,
+            PulseGraphValueType::Bool { value: false },
+            InputParamKind::ConstantOnly,
+            true,
+        );
+        connect(graph, method, call);
+        connect(graph, function, log);
+        (full_state, [method, call, function, log])
+    }
+
+    fn compile_to_string(full_state: &FullGraphState) -> String {
+        let value = crate::compiler::build_graph_definition(&full_state.state.graph, &full_state.user_state).unwrap();
+        kv3::to_string(&value)
+    }
+
+    #[test]
+    fn test_collapse_function_call_keeps_bytecode() {
+        let (mut full_state, [_, call, function, log]) = call_function_graph();
+        let expected = compile_to_string(&full_state);
+        assert!(expected.contains("PULSE_CALL_SYNC"));
+
+        full_state.collapse_to_macro(&[call, function, log]).unwrap();
+        assert_eq!(full_state.state.graph.nodes.len(), 2);
+        assert_eq!(compile_to_string(&full_state), expected);
+    }
+
+    #[test]
+    fn test_collapse_refuses_references_across_the_selection() {
+        let (mut full_state, [_, call, function, log]) = call_function_graph();
+        let err = full_state.collapse_to_macro(&[call]).unwrap_err();
+        assert!(err.to_string().contains("'Call node' -> 'DoLog'"), "{err}");
+        let err = full_state.collapse_to_macro(&[function, log]).unwrap_err();
+        assert!(err.to_string().contains("'Call node' -> 'DoLog'"), "{err}");
+        assert_eq!(full_state.state.graph.nodes.len(), 4);
+        assert!(full_state.user_state.macros.is_empty());
+    }
+}
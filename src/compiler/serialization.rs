@@ -447,7 +447,7 @@ impl PulseConstant {
             PulseConstant::Color_RGB(value) 
                 => Value::Array(vec![Value::Number(value[0].into()), Value::Number(value[1].into()), Value::Number(value[2].into())]),
             PulseConstant::Bool(value) => Value::Bool(*value),
-            PulseConstant::SchemaEnum(_, value) => Value::String(value.to_str().to_string()),
+            PulseConstant::SchemaEnum(_, value) => Value::String(value.to_str().into_owned()),
             PulseConstant::Resource(_, value) => Value::Flag("resource".into(), Value::String(value.clone()).into()),
             PulseConstant::Array(_, value) => {
                 let values = value.iter().map(|v| v.serialize_value()).collect();
@@ -537,8 +537,12 @@ impl KV3Serialize for PulseVariable {
             PulseValueType::PVAL_BOOL => Value::Bool(false), // default value for bool is false
             PulseValueType::PVAL_SNDEVT_NAME(val) 
                 => Value::Flag("soundevent".into(), Value::String(val.clone().unwrap_or_default()).into()),
-            PulseValueType::PVAL_SCHEMA_ENUM(en) 
-                => Value::String(en.to_str().to_string()),
+            // schema enum defaults are kept in the buffer as their engine representation.
+            PulseValueType::PVAL_SCHEMA_ENUM(en) => {
+                let val = SchemaEnumValue::from_str_typed(en, &self.default_value_buffer)
+                    .unwrap_or_else(|| SchemaEnumValue::default_from_type(en));
+                Value::String(val.to_str().into_owned())
+            }
 
             PulseValueType::PVAL_TRANSFORM(_)
             | PulseValueType::PVAL_TRANSFORM_WORLDSPACE(_)
@@ -0,0 +1,241 @@
+// Type checking of the graph. The editor only makes sure the PulseDataTypes of connected ports match, but
+// the actual types are PulseValueTypes (int or float, which entity class, what's in an array...). This
+// figures out the value type of every output where it can, and checks it against what the connected input
+// expects. Anything that can't be figured out is left alone, the goal is to not have false positives.
+use std::fmt;
+use egui_node_graph2::*;
+use crate::app::types::*;
+use crate::bindings::PolimorphicTypeInfo;
+use crate::typing::PulseValueType;
+use crate::utils::get_connected_output;
+
+// reroute chains longer than this are treated as unknown
+const MAX_REROUTE_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub node_id: NodeId,
+    pub node: String,
+    pub port: String,
+    pub message: String,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ('{}'): {}", self.node, self.port, self.message)
+    }
+}
+
+// Checks every connection and constant value of the graph.
+pub fn typecheck_graph(graph: &PulseGraph, graph_state: &PulseGraphState) -> Vec<TypeError> {
+    let checker = TypeChecker { graph, graph_state };
+    let mut errors = vec![];
+    for (node_id, node) in graph.nodes.iter() {
+        for (port, input_id) in node.inputs.iter() {
+            let error = |message: String| TypeError {
+                node_id,
+                node: node.label.clone(),
+                port: port.clone(),
+                message,
+            };
+            let input = graph.get_input(*input_id);
+            for output_id in graph.connections(*input_id) {
+                let output = graph.get_output(output_id);
+                if output.typ != input.typ && !output.typ.allow_any_type() && !input.typ.allow_any_type() {
+                    errors.push(error(format!("expects {}, but is connected to {}", input.typ.name(), output.typ.name())));
+                    continue;
+                }
+                let (Some(expected), Some(actual)) = (checker.input_type(node, port, *input_id), checker.output_type(output_id, 0)) else {
+                    continue;
+                };
+                if !types_compatible(&expected, &actual) {
+                    errors.push(error(format!("expects {expected}, but is connected to {actual}")));
+                }
+            }
+            if !graph.connections(*input_id).is_empty() || input.kind == InputParamKind::ConnectionOnly {
+                continue;
+            }
+            if let Some(expected) = checker.input_type(node, port, *input_id) {
+                if let Err(message) = check_constant(&expected, &input.value) {
+                    errors.push(error(message));
+                }
+            }
+        }
+    }
+    errors
+}
+
+struct TypeChecker<'a> {
+    graph: &'a PulseGraph,
+    graph_state: &'a PulseGraphState,
+}
+
+impl TypeChecker<'_> {
+    fn variable_type(&self, node: &Node<PulseNodeData>) -> Option<PulseValueType> {
+        let input_id = node.get_input("variableName").ok()?;
+        let name = self.graph.get_input(input_id).value.clone().try_variable_name().ok()?;
+        self.graph_state.variables.iter()
+            .find(|var| var.name == name)
+            .map(|var| var.typ_and_default_value.clone())
+    }
+
+    // Type the node expects on an input, None if we don't know (or it takes anything).
+    fn input_type(&self, node: &Node<PulseNodeData>, port: &str, input_id: InputId) -> Option<PulseValueType> {
+        let bindings = &self.graph_state.bindings;
+        match node.user_data.template {
+            PulseNodeTemplate::LibraryBindingAssigned { binding } => {
+                if let Some(param) = bindings.find_function_by_id(binding).and_then(|b| b.find_inparam_by_name(port)) {
+                    // polymorphic parameters take whatever is connected
+                    return param.polymorphic_arg.is_none().then(|| param.pulsetype.clone());
+                }
+            }
+            PulseNodeTemplate::SetVar if port == "value" => return self.variable_type(node),
+            PulseNodeTemplate::FireOutput if port == "param" => {
+                let name_id = node.get_input("outputName").ok()?;
+                let name = self.graph.get_input(name_id).value.clone().try_output_name().ok()?;
+                return self.graph_state.public_outputs.iter()
+                    .find(|output| output.name == name)
+                    .map(|output| output.typ.clone());
+            }
+            _ => {}
+        }
+        let input = self.graph.get_input(input_id);
+        match &input.value {
+            PulseGraphValueType::Resource { resource_type, .. } => Some(PulseValueType::PVAL_RESOURCE(resource_type.clone(), None)),
+            PulseGraphValueType::TypeSafeInteger { integer_type } => Some(PulseValueType::PVAL_TYPESAFE_INT(Some(integer_type.clone()), None)),
+            PulseGraphValueType::SchemaEnum { enum_type, .. } => Some(PulseValueType::PVAL_SCHEMA_ENUM(*enum_type)),
+            _ => data_type_default(&input.typ),
+        }
+    }
+
+    // Type of the values coming out of an output, None if we don't know.
+    fn output_type(&self, output_id: OutputId, depth: usize) -> Option<PulseValueType> {
+        let output = self.graph.get_output(output_id);
+        let node = &self.graph[output.node];
+        let port = node.outputs.iter().find(|(_, id)| *id == output_id).map(|(name, _)| name.as_str())?;
+        let bindings = &self.graph_state.bindings;
+        let custom_type = node.user_data.custom_output_type.clone()
+            .filter(|_| port == "out" || port == "retval");
+        match node.user_data.template {
+            PulseNodeTemplate::Reroute => {
+                if depth >= MAX_REROUTE_DEPTH {
+                    return None;
+                }
+                let source = get_connected_output(self.graph, node.get_input("in").ok()?)?;
+                return self.output_type(source, depth + 1);
+            }
+            PulseNodeTemplate::GetVar if port == "value" => return self.variable_type(node),
+            PulseNodeTemplate::LibraryBindingAssigned { binding } => {
+                let binding = bindings.find_function_by_id(binding)?;
+                if port == "retval" && binding.polymorphic_return.is_some() {
+                    return match &binding.polymorphic_return {
+                        // the subtype is only a hint, the engine upcasts it anyway
+                        Some(PolimorphicTypeInfo::ToSubtype(_)) => None,
+                        _ => custom_type,
+                    };
+                }
+                if let Some(param) = binding.find_outparam_by_name(port) {
+                    return Some(param.pulsetype.clone());
+                }
+            }
+            PulseNodeTemplate::EventHandler => {
+                let choice = node.get_input("event").ok()?;
+                if let PulseGraphValueType::EventBindingChoice { value } = self.graph.get_input(choice).value {
+                    let param = bindings.find_event_by_id(value)?
+                        .inparams.as_ref()?
+                        .iter()
+                        .find(|param| param.name == port);
+                    if let Some(param) = param {
+                        return Some(param.pulsetype.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+        custom_type.or_else(|| data_type_default(&output.typ))
+    }
+}
+
+// Value type that a port of this data type always has. Scalars could be ints or floats, so they are unknown.
+fn data_type_default(typ: &PulseDataType) -> Option<PulseValueType> {
+    Some(match typ {
+        PulseDataType::Vec2 => PulseValueType::PVAL_VEC2(None),
+        PulseDataType::Vec3 => PulseValueType::PVAL_VEC3(None),
+        PulseDataType::Vec3Local => PulseValueType::PVAL_VEC3_LOCAL(None),
+        PulseDataType::Vec4 => PulseValueType::PVAL_VEC4(None),
+        PulseDataType::QAngle => PulseValueType::PVAL_QANGLE(None),
+        PulseDataType::Color => PulseValueType::PVAL_COLOR_RGB(None),
+        PulseDataType::String => PulseValueType::PVAL_STRING(None),
+        PulseDataType::Bool => PulseValueType::PVAL_BOOL,
+        PulseDataType::EHandle => PulseValueType::PVAL_EHANDLE(None),
+        PulseDataType::SndEventHandle => PulseValueType::PVAL_SNDEVT_GUID(None),
+        PulseDataType::SoundEventName => PulseValueType::PVAL_SNDEVT_NAME(None),
+        PulseDataType::EntityName => PulseValueType::DOMAIN_ENTITY_NAME,
+        PulseDataType::Transform => PulseValueType::PVAL_TRANSFORM(None),
+        PulseDataType::TransformWorldspace => PulseValueType::PVAL_TRANSFORM_WORLDSPACE(None),
+        PulseDataType::Resource => PulseValueType::PVAL_RESOURCE(None, None),
+        PulseDataType::Array => PulseValueType::PVAL_ARRAY(Box::new(PulseValueType::PVAL_ANY)),
+        PulseDataType::GameTime => PulseValueType::PVAL_GAMETIME(None),
+        PulseDataType::TypeSafeInteger => PulseValueType::PVAL_TYPESAFE_INT(None, None),
+        PulseDataType::Any => PulseValueType::PVAL_ANY,
+        _ => return None,
+    })
+}
+
+// Can a value of type `actual` be passed where `expected` is wanted? Subtypes that aren't known on either
+// side (EHandle without a class, resource without a type...) are accepted.
+pub fn types_compatible(expected: &PulseValueType, actual: &PulseValueType) -> bool {
+    use PulseValueType::*;
+    match (expected, actual) {
+        (PVAL_ANY, _) | (_, PVAL_ANY) => true,
+        (PVAL_BOOL | PVAL_BOOL_VALUE(_), PVAL_BOOL | PVAL_BOOL_VALUE(_)) => true,
+        (PVAL_EHANDLE(expected), PVAL_EHANDLE(actual)) => subtypes_match(expected, actual),
+        (PVAL_RESOURCE(expected, _), PVAL_RESOURCE(actual, _)) => subtypes_match(expected, actual),
+        (PVAL_TYPESAFE_INT(expected, _), PVAL_TYPESAFE_INT(actual, _)) => subtypes_match(expected, actual),
+        (PVAL_SCHEMA_ENUM(expected), PVAL_SCHEMA_ENUM(actual)) => expected == actual,
+        (PVAL_ARRAY(expected), PVAL_ARRAY(actual)) => types_compatible(expected, actual),
+        _ => std::mem::discriminant(expected) == std::mem::discriminant(actual),
+    }
+}
+
+fn subtypes_match(expected: &Option<String>, actual: &Option<String>) -> bool {
+    match (expected.as_deref(), actual.as_deref()) {
+        (Some(expected), Some(actual)) if !expected.is_empty() && !actual.is_empty() => expected == actual,
+        _ => true,
+    }
+}
+
+// Checks a constant typed into an input against the type the input expects.
+fn check_constant(expected: &PulseValueType, value: &PulseGraphValueType) -> Result<(), String> {
+    use PulseValueType::*;
+    let actual = match value {
+        PulseGraphValueType::Scalar { value } => {
+            return match expected {
+                PVAL_INT(_) | PVAL_TYPESAFE_INT(_, _) if value.fract() != 0.0 => {
+                    Err(format!("expects a whole number, but is set to {value}"))
+                }
+                PVAL_INT(_) | PVAL_TYPESAFE_INT(_, _) | PVAL_FLOAT(_) | PVAL_GAMETIME(_) | PVAL_ANY => Ok(()),
+                _ => Err(format!("expects {expected}, but is set to a number")),
+            };
+        }
+        PulseGraphValueType::String { .. } => PVAL_STRING(None),
+        PulseGraphValueType::Bool { .. } => PVAL_BOOL,
+        PulseGraphValueType::Vec2 { .. } => PVAL_VEC2(None),
+        PulseGraphValueType::Vec3 { .. } => PVAL_VEC3(None),
+        PulseGraphValueType::Vec3Local { .. } => PVAL_VEC3_LOCAL(None),
+        PulseGraphValueType::Vec4 { .. } => PVAL_VEC4(None),
+        PulseGraphValueType::QAngle { .. } => PVAL_QANGLE(None),
+        PulseGraphValueType::Color { .. } => PVAL_COLOR_RGB(None),
+        PulseGraphValueType::EntityName { .. } => DOMAIN_ENTITY_NAME,
+        PulseGraphValueType::SoundEventName { .. } => PVAL_SNDEVT_NAME(None),
+        PulseGraphValueType::SchemaEnum { enum_type, .. } => PVAL_SCHEMA_ENUM(*enum_type),
+        PulseGraphValueType::Resource { resource_type, .. } => PVAL_RESOURCE(resource_type.clone(), None),
+        // everything else is picked from a list or only configures the node
+        _ => return Ok(()),
+    };
+    if types_compatible(expected, &actual) {
+        Ok(())
+    } else {
+        Err(format!("expects {expected}, but is set to a {actual} value"))
+    }
+}
@@ -9,8 +9,9 @@ mod compiler;
 mod pulsetypes;
 mod typing;
 mod utils;
-mod compat;
+pub mod cli;
 pub use app::PulseGraphEditor;
+pub use app::merge::MergeSession;
 // ----------------------------------------------------------------------------
 // When compiling for web:
 
@@ -4,11 +4,21 @@
 #![cfg_attr(all(not(debug_assertions), target_os = "windows"), windows_subsystem = "windows")]
 
 use libpulseedit::PulseGraphEditor;
+use libpulseedit::cli::CliResult;
 use std::sync::Arc;
 
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let start = match libpulseedit::cli::run(&args) {
+        Some(CliResult::Exit(exit_code)) => std::process::exit(exit_code),
+        start => start,
+    };
+    let merge_resolved = match &start {
+        Some(CliResult::ResolveMerge(session)) => Some(session.resolved_flag()),
+        _ => None,
+    };
     let d = eframe::icon_data::from_png_bytes(include_bytes!("../icon.png"))
         .expect("The icon data must be valid");
 
@@ -23,12 +33,22 @@ fn main() {
         "Pulse Graph Editor",
         options,
         Box::new(|cc| {
-            Ok(Box::new(PulseGraphEditor::new(cc)))
+            let editor = PulseGraphEditor::new(cc);
+            Ok(Box::new(match start {
+                Some(CliResult::ResolveMerge(session)) => editor.with_merge(*session, &cc.egui_ctx),
+                Some(CliResult::ShowDiff(session)) => editor.with_diff(*session, &cc.egui_ctx),
+                _ => editor,
+            }))
             // #[cfg(not(feature = "persistence"))]
             // Ok(Box::<PulseGraphEditor>::default())
         }),
     )
     .expect("Failed to run app");
+    // git takes the exit code of the mergetool to tell if the merge was resolved
+    if let Some(resolved) = merge_resolved {
+        let resolved = resolved.load(std::sync::atomic::Ordering::Relaxed);
+        std::process::exit(if resolved { 0 } else { 1 });
+    }
 }
 
 fn setup_panic_hook() {
@@ -48,7 +68,7 @@ fn setup_panic_hook() {
             .set_level(rfd::MessageLevel::Error)
             .set_title("Whoops!")
             .set_description(format!(
-                "The editor has crashed due to an unhandled error.\n\n{}\n\n{}",
+                "The editor has crashed due to an unhandled error. Graphs with unsaved changes will be offered for recovery the next time it starts.\n\n{}\n\n{}",
                 panic_formatted,
                 panic_payload_display
                     .as_deref()
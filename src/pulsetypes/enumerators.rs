@@ -1,5 +1,6 @@
 
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::str::FromStr;
 use strum::VariantArray as _;
 use strum_macros::VariantArray;
@@ -95,6 +96,8 @@ pub enum HitGroup {
     Count,
 }
 
+// named like in the engine schema
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, VariantArray)]
 pub enum AILOD {
     #[default]
@@ -104,6 +107,8 @@ pub enum AILOD {
     VeryLow,
 }
 
+// named like in the engine schema
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, VariantArray)]
 pub enum NPCSTATE {
     #[default]
@@ -190,6 +195,42 @@ pub enum DamageTypes {
     Lethal,
     Dangerzone,
 }
+impl DamageTypes {
+    // values of DamageTypes_t in the CS2 schema, there are gaps between them.
+    // The ones CS2 doesn't have are from other games and their values aren't known.
+    pub fn bits(self) -> Option<i32> {
+        match self {
+            DamageTypes::Generic => Some(0),
+            DamageTypes::Crush => Some(1 << 0),
+            DamageTypes::Bullet => Some(1 << 1),
+            DamageTypes::Slash => Some(1 << 2),
+            DamageTypes::Burn => Some(1 << 3),
+            DamageTypes::Vehicle => Some(1 << 4),
+            DamageTypes::Fall => Some(1 << 5),
+            DamageTypes::Blast => Some(1 << 6),
+            DamageTypes::Club => Some(1 << 7),
+            DamageTypes::Shock => Some(1 << 8),
+            DamageTypes::Sonic => Some(1 << 9),
+            DamageTypes::EnergyBeam => Some(1 << 10),
+            DamageTypes::Drown => Some(1 << 14),
+            DamageTypes::Poison => Some(1 << 15),
+            DamageTypes::Radiation => Some(1 << 16),
+            DamageTypes::DrownRecover => Some(1 << 17),
+            DamageTypes::Acid => Some(1 << 18),
+            DamageTypes::Physgun => Some(1 << 20),
+            DamageTypes::Dissolve => Some(1 << 21),
+            DamageTypes::BlastSurface => Some(1 << 22),
+            DamageTypes::Buckshot => Some(1 << 24),
+            DamageTypes::Headshot => Some(1 << 25),
+            DamageTypes::Dangerzone => Some(1 << 26),
+            DamageTypes::Crit
+            | DamageTypes::Buffed
+            | DamageTypes::Dot
+            | DamageTypes::GroundAura
+            | DamageTypes::Lethal => None,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, VariantArray)]
 pub enum StanceType {
@@ -225,6 +266,7 @@ pub enum ChoreoLookAtMode {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum SchemaEnumType {
     CursorCancelPriority,
     TraceContents,
@@ -246,6 +288,7 @@ pub enum SchemaEnumType {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum SchemaEnumValue {
     CursorCancelPriority(PulseCursorCancelPriority),
     TraceContents(PulseTraceContents),
@@ -264,6 +307,8 @@ pub enum SchemaEnumValue {
     SharedMovementGait(SharedMovementGait),
     ChoreoLookAtSpeed(ChoreoLookAtSpeed),
     ChoreoLookAtMode(ChoreoLookAtMode),
+    // combination of single values, only used by flag enums (see SchemaEnumType::is_flags)
+    Flags(SchemaEnumType, Vec<SchemaEnumValue>),
 }
 
 impl FromStr for SchemaEnumType {
@@ -296,6 +341,10 @@ impl FromStr for SchemaEnumType {
 }
 
 impl SchemaEnumType {
+    // these are bit flags in the engine, so more than one value can be set at once.
+    pub fn is_flags(self) -> bool {
+        matches!(self, SchemaEnumType::DamageTypes)
+    }
     pub fn get_all_types_as_enums(&self) -> Vec<SchemaEnumValue> {
         match self {
             SchemaEnumType::CursorCancelPriority => {
@@ -447,8 +496,8 @@ impl SchemaEnumType {
 }
 
 impl SchemaEnumValue {
-    pub fn get_ui_name(&self) -> &'static str {
-        match self {
+    pub fn get_ui_name(&self) -> Cow<'static, str> {
+        let name = match self {
             SchemaEnumValue::CursorCancelPriority(value) => value.to_str_ui(),
             SchemaEnumValue::TraceContents(value) => value.to_str_ui(),
             SchemaEnumValue::CollisionGroup(value) => value.to_str_ui(),
@@ -466,10 +515,20 @@ impl SchemaEnumValue {
             SchemaEnumValue::SharedMovementGait(value) => value.to_str_ui(),
             SchemaEnumValue::ChoreoLookAtSpeed(value) => value.to_str_ui(),
             SchemaEnumValue::ChoreoLookAtMode(value) => value.to_str_ui(),
-        }
+            SchemaEnumValue::Flags(_, values) => {
+                if values.is_empty() {
+                    return Cow::Borrowed("None");
+                }
+                return Cow::Owned(
+                    values.iter().map(|v| v.get_ui_name()).collect::<Vec<_>>().join(", ")
+                );
+            }
+        };
+        Cow::Borrowed(name)
     }
-    pub fn to_str(&self) -> &'static str {
-        match self {
+    // engine representation of the value, flags are joined the same way as in the KV3 files.
+    pub fn to_str(&self) -> Cow<'static, str> {
+        let name = match self {
             SchemaEnumValue::CursorCancelPriority(value) => value.to_str(),
             SchemaEnumValue::TraceContents(value) => value.to_str(),
             SchemaEnumValue::CollisionGroup(value) => value.to_str(),
@@ -487,6 +546,48 @@ impl SchemaEnumValue {
             SchemaEnumValue::SharedMovementGait(value) => value.to_str(),
             SchemaEnumValue::ChoreoLookAtSpeed(value) => value.to_str(),
             SchemaEnumValue::ChoreoLookAtMode(value) => value.to_str(),
+            SchemaEnumValue::Flags(typ, values) => {
+                if values.is_empty() {
+                    return SchemaEnumValue::default_from_type(typ).to_str();
+                }
+                return Cow::Owned(
+                    values.iter().map(|v| v.to_str()).collect::<Vec<_>>().join(" | ")
+                );
+            }
+        };
+        Cow::Borrowed(name)
+    }
+    // parse the engine representation back, accepts combined flags like "DMG_BURN | DMG_BLAST"
+    pub fn from_str_typed(typ: &SchemaEnumType, s: &str) -> Option<Self> {
+        let choices = typ.get_all_types_as_enums();
+        let mut values = vec![];
+        for part in s.split('|').map(str::trim).filter(|p| !p.is_empty()) {
+            values.push(choices.iter().find(|c| c.to_str() == part)?.clone());
+        }
+        Some(SchemaEnumValue::from_flags(*typ, values))
+    }
+    // list of the single values that are set
+    pub fn get_flags(&self) -> Vec<SchemaEnumValue> {
+        match self {
+            SchemaEnumValue::Flags(_, values) => values.clone(),
+            _ => vec![self.clone()],
+        }
+    }
+    pub fn from_flags(typ: SchemaEnumType, mut values: Vec<SchemaEnumValue>) -> Self {
+        if values.len() == 1 {
+            values.pop().unwrap()
+        } else {
+            SchemaEnumValue::Flags(typ, values)
+        }
+    }
+    // integer value of the flag set, None if the value of one of the flags isn't known.
+    pub fn get_flag_bits(&self) -> Option<i32> {
+        match self {
+            SchemaEnumValue::DamageTypes(value) => value.bits(),
+            SchemaEnumValue::Flags(_, values) => values.iter()
+                .try_fold(0, |acc, v| v.get_flag_bits().map(|bits| acc | bits)),
+            // not a flag
+            _ => None,
         }
     }
     pub fn default_from_type(typ: &SchemaEnumType) -> Self {
@@ -1011,3 +1112,40 @@ impl PulseEnumTrait for SoundEventStartType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_flags_to_and_from_str() {
+        let typ = SchemaEnumType::DamageTypes;
+        let value = SchemaEnumValue::from_str_typed(&typ, "DMG_BURN | DMG_BLAST").unwrap();
+        assert_eq!(value.to_str(), "DMG_BURN | DMG_BLAST");
+        assert_eq!(value.get_flag_bits(), Some((1 << 3) | (1 << 6)));
+
+        let single = SchemaEnumValue::from_str_typed(&typ, "DMG_CRUSH").unwrap();
+        assert_eq!(single, SchemaEnumValue::DamageTypes(DamageTypes::Crush));
+        assert_eq!(SchemaEnumValue::Flags(typ, vec![]).to_str(), "DMG_GENERIC");
+        assert!(SchemaEnumValue::from_str_typed(&typ, "DMG_NOTREAL").is_none());
+        // STATIC_LEVEL is 0 in PulseTraceContents_t, so it's a plain enum and not a flag set.
+        assert!(!SchemaEnumType::TraceContents.is_flags());
+        assert_eq!(SchemaEnumValue::TraceContents(PulseTraceContents::Solid).get_flag_bits(), None);
+    }
+
+    #[test]
+    pub fn test_damage_type_bits() {
+        // DMG_ values from the engine
+        assert_eq!(DamageTypes::EnergyBeam.bits(), Some(0x400));
+        assert_eq!(DamageTypes::Drown.bits(), Some(0x4000));
+        assert_eq!(DamageTypes::Physgun.bits(), Some(0x100000));
+        assert_eq!(DamageTypes::Buckshot.bits(), Some(0x1000000));
+        assert_eq!(DamageTypes::Headshot.bits(), Some(0x2000000));
+        assert_eq!(DamageTypes::Crit.bits(), None);
+        let flags = SchemaEnumValue::Flags(SchemaEnumType::DamageTypes, vec![
+            SchemaEnumValue::DamageTypes(DamageTypes::Drown),
+            SchemaEnumValue::DamageTypes(DamageTypes::Crit),
+        ]);
+        assert_eq!(flags.get_flag_bits(), None);
+    }
+}
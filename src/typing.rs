@@ -282,6 +282,13 @@ impl PulseValueType {
             PulseValueType::PVAL_ARRAY(Box::new(PulseValueType::PVAL_ANY)),
             PulseValueType::PVAL_RESOURCE(None, None),
             PulseValueType::PVAL_GAMETIME(None),
+            PulseValueType::PVAL_SCHEMA_ENUM(SchemaEnumType::DamageTypes),
+            PulseValueType::PVAL_SCHEMA_ENUM(SchemaEnumType::TraceContents),
+        ]
+    }
+    pub fn get_flag_enum_types() -> Vec<PulseValueType> {
+        vec![
+            PulseValueType::PVAL_SCHEMA_ENUM(SchemaEnumType::DamageTypes),
         ]
     }
     pub fn get_vector_types() -> Vec<PulseValueType> {
@@ -4,18 +4,31 @@ use crate::app::types::*;
 
 // returns list of pairs of nodes and inputs connected to a given output.
 // TODO could be optimized to return a group of node and it's inputs
+// reroute nodes are skipped, the inputs connected to their outputs are returned instead.
 pub fn get_nodes_and_inputs_connected_from_output(
     graph: &PulseGraph,
     out_action_id: &OutputId,
 ) -> anyhow::Result<Vec<(NodeId, InputId)>> {
     let mut node_input_pairs = vec![];
-    for connection in graph.iter_connections() {
-        if connection.1 == *out_action_id {
+    let mut outputs = vec![*out_action_id];
+    let mut visited_reroutes = vec![];
+    while let Some(output_id) = outputs.pop() {
+        for connection in graph.iter_connections() {
+            if connection.1 != output_id {
+                continue;
+            }
             let node_of_input = graph
                 .inputs
                 .get(connection.0)
                 .ok_or(anyhow!("Can't find input value {:?}", connection.0))?
                 .node();
+            if is_reroute(graph, node_of_input) {
+                if !visited_reroutes.contains(&node_of_input) {
+                    visited_reroutes.push(node_of_input);
+                    outputs.push(graph.nodes[node_of_input].get_output("out")?);
+                }
+                continue;
+            }
             let input_id = connection.0;
             node_input_pairs.push((node_of_input, input_id));
         }
@@ -23,6 +36,25 @@ pub fn get_nodes_and_inputs_connected_from_output(
     Ok(node_input_pairs)
 }
 
+// Same as graph.connection, but goes through reroute nodes to the output that actually provides the value.
+pub fn get_connected_output(graph: &PulseGraph, input_id: InputId) -> Option<OutputId> {
+    let mut output_id = graph.connection(input_id)?;
+    // the limit stops reroutes connected in a loop
+    for _ in 0..graph.nodes.len() {
+        let node_id = graph.get_output(output_id).node;
+        if !is_reroute(graph, node_id) {
+            return Some(output_id);
+        }
+        output_id = graph.connection(graph.nodes[node_id].get_input("in").ok()?)?;
+    }
+    None
+}
+
+fn is_reroute(graph: &PulseGraph, node_id: NodeId) -> bool {
+    graph.nodes.get(node_id)
+        .is_some_and(|node| node.user_data.template == PulseNodeTemplate::Reroute)
+}
+
 // return list of pairs of the connected node and corresponding name
 pub fn get_nodes_connected_to_output<'a>(
     origin_node: &'a Node<PulseNodeData>,